@@ -1,15 +1,32 @@
-use toml_edit::{DocumentMut, Item, Table, Value};
+use semver::VersionReq;
+use toml_edit::{DocumentMut, InlineTable, Item, Table, Value};
 
-use crate::config::Manifest;
+use super::fetch_policy;
+use super::search::load_index;
+use crate::config::{Manifest, PluginEntry};
+use crate::resolver::FetchPolicy;
 use crate::{Error, Result};
 
 /// Add a plugin to the manifest.
+#[allow(clippy::too_many_arguments)]
 pub fn run(
     name: String,
     marketplace: Option<String>,
     tag: Option<String>,
     commit: Option<String>,
+    branch: Option<String>,
+    version: Option<String>,
+    prerelease: bool,
+    offline: bool,
 ) -> Result<()> {
+    let version = version
+        .map(|v| {
+            VersionReq::parse(&v).map_err(|e| {
+                Error::ManifestParse(format!("invalid version requirement '{}' for plugin '{}': {}", v, name, e))
+            })
+        })
+        .transpose()?;
+
     // Find the manifest to edit
     let manifest_path = find_manifest()?;
 
@@ -35,7 +52,8 @@ pub fn run(
         }
         None => {
             // Search marketplaces for the plugin
-            let found = search_marketplaces(&doc, &name)?;
+            let manifest = Manifest::load(&manifest_path)?;
+            let found = search_marketplaces(&manifest, &name, fetch_policy(offline, [&manifest]))?;
             if found.is_empty() {
                 return Err(Error::PluginNotFound {
                     plugin: name,
@@ -55,23 +73,18 @@ pub fn run(
     };
 
     // Build the plugin entry
-    let mut plugin_table = toml_edit::InlineTable::new();
-    plugin_table.insert("marketplace", marketplace_name.clone().into());
-
-    if let Some(t) = &tag {
-        plugin_table.insert("tag", t.clone().into());
-    }
-    if let Some(c) = &commit {
-        plugin_table.insert("commit", c.clone().into());
-    }
-
-    // Ensure [plugins] section exists
-    if !doc.contains_table("plugins") {
-        doc["plugins"] = Item::Table(Table::new());
-    }
+    let entry = PluginEntry {
+        marketplace: marketplace_name.clone(),
+        tag: tag.clone(),
+        commit: commit.clone(),
+        branch: branch.clone(),
+        version: version.clone(),
+        prerelease,
+        ..PluginEntry::default()
+    };
 
     // Add the plugin
-    doc["plugins"][&name] = Item::Value(Value::InlineTable(plugin_table));
+    set_plugin_entry(&mut doc, &name, &entry);
 
     // Write back
     std::fs::write(&manifest_path, doc.to_string()).map_err(|e| Error::FileWrite {
@@ -83,6 +96,10 @@ pub fn run(
         println!("Added {} from {} (tag: {})", name, marketplace_name, t);
     } else if let Some(c) = &commit {
         println!("Added {} from {} (commit: {})", name, marketplace_name, c);
+    } else if let Some(b) = &branch {
+        println!("Added {} from {} (branch: {})", name, marketplace_name, b);
+    } else if let Some(v) = &version {
+        println!("Added {} from {} (version: {})", name, marketplace_name, v);
     } else {
         println!("Added {} from {}", name, marketplace_name);
     }
@@ -97,15 +114,54 @@ fn find_manifest() -> Result<std::path::PathBuf> {
         return Ok(project_path);
     }
 
-    if let Some(global_path) = Manifest::global_path() {
-        if global_path.exists() {
-            return Ok(global_path);
-        }
+    if let Some(global_path) = Manifest::global_path()
+        && global_path.exists()
+    {
+        return Ok(global_path);
     }
 
     Err(Error::NoManifest)
 }
 
+/// Write a plugin entry as an inline table under `[plugins]`.
+pub(super) fn set_plugin_entry(doc: &mut DocumentMut, name: &str, entry: &PluginEntry) {
+    let mut plugin_table = InlineTable::new();
+    match &entry.git {
+        Some(url) => {
+            plugin_table.insert("git", url.clone().into());
+            if let Some(s) = &entry.subdir {
+                plugin_table.insert("subdir", s.clone().into());
+            }
+        }
+        None => {
+            plugin_table.insert("marketplace", entry.marketplace.clone().into());
+        }
+    }
+
+    if let Some(t) = &entry.tag {
+        plugin_table.insert("tag", t.clone().into());
+    }
+    if let Some(c) = &entry.commit {
+        plugin_table.insert("commit", c.clone().into());
+    }
+    if let Some(b) = &entry.branch {
+        plugin_table.insert("branch", b.clone().into());
+    }
+    if let Some(v) = &entry.version {
+        plugin_table.insert("version", v.to_string().into());
+    }
+    if entry.prerelease {
+        plugin_table.insert("prerelease", true.into());
+    }
+
+    // Ensure [plugins] section exists
+    if !doc.contains_table("plugins") {
+        doc["plugins"] = Item::Table(Table::new());
+    }
+
+    doc["plugins"][name] = Item::Value(Value::InlineTable(plugin_table));
+}
+
 /// Check if a marketplace is declared in the manifest.
 fn marketplace_exists(doc: &DocumentMut, name: &str) -> bool {
     doc.get("marketplaces")
@@ -115,16 +171,13 @@ fn marketplace_exists(doc: &DocumentMut, name: &str) -> bool {
 }
 
 /// Search declared marketplaces for a plugin.
-/// This is a placeholder - in a real implementation, we'd need to
-/// clone/fetch the marketplaces and check their marketplace.json files.
-fn search_marketplaces(doc: &DocumentMut, _plugin_name: &str) -> Result<Vec<String>> {
-    // For MVP, just return all declared marketplaces
-    // The user needs to specify --marketplace or we use the first one
-    let marketplaces = doc
-        .get("marketplaces")
-        .and_then(|m| m.as_table())
-        .map(|t| t.iter().map(|(k, _)| k.to_string()).collect())
-        .unwrap_or_default();
-
-    Ok(marketplaces)
+/// Fetches each marketplace and returns the ones whose marketplace.json lists the plugin.
+fn search_marketplaces(manifest: &Manifest, plugin_name: &str, policy: FetchPolicy) -> Result<Vec<String>> {
+    let index = load_index(&manifest.marketplaces, policy)?;
+
+    Ok(index
+        .marketplaces_with(plugin_name)
+        .into_iter()
+        .filter(|name| manifest.marketplaces.contains_key(name))
+        .collect())
 }
@@ -0,0 +1,483 @@
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime};
+
+use super::install::short_commit;
+use crate::config::{LockFile, Manifest, SourceType, GIT_MARKETPLACE};
+use crate::installer::{CacheEntry, CacheEntryKind, CacheManager, ClaudeCodeIntegration, Transaction};
+use crate::Result;
+
+/// Why an entry is still needed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Usage {
+    /// Referenced by no known lock file, and Claude Code does not point to it.
+    Unused,
+    /// Referenced by a known lock file; `install` recreates it if removed.
+    Locked,
+    /// Claude Code points to it, so it is never removed.
+    InUse,
+}
+
+impl fmt::Display for Usage {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Usage::Unused => write!(f, "unused"),
+            Usage::Locked => write!(f, "locked"),
+            Usage::InUse => write!(f, "in use"),
+        }
+    }
+}
+
+/// What the cache is still needed for.
+struct References {
+    /// Directories Claude Code loads plugins and marketplaces from.
+    claude_paths: Vec<PathBuf>,
+    /// Entries the known lock files reference.
+    locked: HashSet<CacheEntryKind>,
+}
+
+impl References {
+    /// Collect the paths in Claude Code's files and the entries referenced by
+    /// the global lock, the current project's lock and every lock recorded by
+    /// an install.
+    fn load(cache: &CacheManager, claude: &ClaudeCodeIntegration) -> Result<Self> {
+        let mut claude_paths: Vec<PathBuf> = claude
+            .read_installed_plugins()?
+            .plugins
+            .into_values()
+            .flatten()
+            .map(|entry| canonical(PathBuf::from(entry.install_path)))
+            .collect();
+        claude_paths.extend(
+            claude
+                .read_known_marketplaces()?
+                .into_values()
+                .map(|entry| canonical(PathBuf::from(entry.install_location))),
+        );
+
+        let mut lock_paths = BTreeSet::new();
+        let manifest_paths = Manifest::global_path().into_iter().chain([Manifest::project_path()]);
+        for manifest_path in manifest_paths {
+            let lock_path = LockFile::path_for_manifest(&manifest_path);
+            if lock_path.exists() {
+                lock_paths.insert(std::path::absolute(&lock_path).unwrap_or(lock_path));
+            }
+        }
+        lock_paths.extend(cache.known_locks()?);
+
+        let mut locked = HashSet::new();
+        for lock_path in lock_paths {
+            let lock = match LockFile::load(&lock_path) {
+                Ok(lock) => lock,
+                Err(e) => {
+                    eprintln!("Warning: ignoring {}: {}", lock_path.display(), e);
+                    continue;
+                }
+            };
+            locked.extend(locked_entries(&lock));
+        }
+
+        Ok(Self { claude_paths, locked })
+    }
+
+    /// Claude Code's paths and the entry's path are compared once symlinks are
+    /// resolved, so a symlinked cache directory cannot hide a path in use.
+    fn usage(&self, entry: &CacheEntry) -> Usage {
+        let entry_path = canonical(entry.path.clone());
+        let points_to = |path: &PathBuf| path.starts_with(&entry_path) || entry_path.starts_with(path);
+        if self.claude_paths.iter().any(points_to) {
+            Usage::InUse
+        } else if self.locked.contains(&entry.kind) {
+            Usage::Locked
+        } else {
+            Usage::Unused
+        }
+    }
+}
+
+/// `path` with symlinks resolved, or as it is if it does not exist.
+fn canonical(path: PathBuf) -> PathBuf {
+    std::fs::canonicalize(&path).unwrap_or(path)
+}
+
+/// The cache entries a lock needs to install without re-cloning anything.
+fn locked_entries(lock: &LockFile) -> Vec<CacheEntryKind> {
+    let mut entries: Vec<CacheEntryKind> = lock
+        .marketplaces
+        .iter()
+        .map(|m| CacheEntryKind::Marketplace { name: m.name.clone() })
+        .collect();
+    for pkg in &lock.packages {
+        entries.push(CacheEntryKind::Plugin {
+            marketplace: pkg.marketplace.clone(),
+            plugin: pkg.name.clone(),
+            commit: pkg.plugin_commit.clone(),
+        });
+        if pkg.source_type == SourceType::External || pkg.git.is_some() {
+            entries.push(CacheEntryKind::PluginRepo {
+                marketplace: pkg.marketplace.clone(),
+                plugin: pkg.name.clone(),
+            });
+        }
+        if pkg.git.is_some() {
+            entries.push(CacheEntryKind::Marketplace {
+                name: GIT_MARKETPLACE.to_string(),
+            });
+        }
+    }
+    entries
+}
+
+/// List every entry in the cache with its size, age and usage.
+pub fn ls() -> Result<()> {
+    let cache = CacheManager::new()?;
+    let claude = ClaudeCodeIntegration::new();
+    let references = References::load(&cache, &claude)?;
+
+    let entries = cache.entries()?;
+    if entries.is_empty() {
+        println!("The cache at {} is empty.", cache.cache_dir().display());
+        return Ok(());
+    }
+
+    let now = SystemTime::now();
+    for entry in &entries {
+        println!(
+            "{:>9}  {:>8}  {:<6}  {}",
+            format_size(entry.size),
+            format_age(now, entry.modified),
+            references.usage(entry),
+            describe(&entry.kind)
+        );
+    }
+    Ok(())
+}
+
+/// Show how much space the cache takes and how much `cache gc` would free.
+pub fn size() -> Result<()> {
+    let cache = CacheManager::new()?;
+    let claude = ClaudeCodeIntegration::new();
+    let references = References::load(&cache, &claude)?;
+    let entries = cache.entries()?;
+
+    let total = |filter: &dyn Fn(&CacheEntry) -> bool| entries.iter().filter(|e| filter(e)).map(|e| e.size).sum::<u64>();
+    println!("Cache: {}", cache.cache_dir().display());
+    println!(
+        "  Plugins:             {:>9}",
+        format_size(total(&|e| matches!(e.kind, CacheEntryKind::Plugin { .. })))
+    );
+    println!(
+        "  Plugin repositories: {:>9}",
+        format_size(total(&|e| matches!(e.kind, CacheEntryKind::PluginRepo { .. })))
+    );
+    println!(
+        "  Marketplaces:        {:>9}",
+        format_size(total(&|e| matches!(e.kind, CacheEntryKind::Marketplace { .. })))
+    );
+    println!("  Total:               {:>9}", format_size(total(&|_| true)));
+
+    let unused = total(&|e| references.usage(e) == Usage::Unused);
+    if unused > 0 {
+        println!("\n{} is unused; `skill-manager cache gc` frees it.", format_size(unused));
+    }
+    Ok(())
+}
+
+/// Remove everything in the cache that no known lock file references and
+/// Claude Code does not point to.
+///
+/// `max_age` also evicts locked entries not modified for that long, and
+/// `max_size` evicts the oldest locked entries until the cache fits. Entries
+/// Claude Code points to are never removed.
+pub fn gc(dry_run: bool, max_size: Option<u64>, max_age: Option<Duration>) -> Result<()> {
+    let cache = CacheManager::new()?;
+    let claude = ClaudeCodeIntegration::new();
+
+    // Wait for a running install, so what it extracts is registered first.
+    // Only the lock is taken: an interrupted install is left for `install`.
+    let _lock = if dry_run {
+        None
+    } else {
+        cache.ensure_cache_dir()?;
+        let lock = Transaction::lock(cache.cache_dir())?;
+        cache.forget_missing_locks()?;
+        Some(lock)
+    };
+
+    let references = References::load(&cache, &claude)?;
+    let entries = cache.entries()?;
+    let plan = plan_gc(&entries, &references, SystemTime::now(), max_size, max_age);
+
+    let verb = if dry_run { "Would remove" } else { "Removing" };
+    let mut freed = 0;
+    for entry in &plan.remove {
+        println!("{} {} ({})", verb, describe(&entry.kind), format_size(entry.size));
+        if !dry_run {
+            cache.remove_entry(entry)?;
+        }
+        freed += entry.size;
+    }
+
+    if plan.remove.is_empty() {
+        println!("Nothing to remove.");
+    } else if dry_run {
+        println!("\nWould free {}.", format_size(freed));
+    } else {
+        println!("\nFreed {}.", format_size(freed));
+    }
+    if let Some(max_size) = max_size
+        && plan.kept > max_size
+    {
+        println!(
+            "The cache still takes {}, more than {}, because Claude Code uses the rest.",
+            format_size(plan.kept),
+            format_size(max_size)
+        );
+    }
+    Ok(())
+}
+
+/// Entries `cache gc` removes, and the size of what it keeps.
+struct GcPlan<'a> {
+    remove: Vec<&'a CacheEntry>,
+    kept: u64,
+}
+
+fn plan_gc<'a>(
+    entries: &'a [CacheEntry],
+    references: &References,
+    now: SystemTime,
+    max_size: Option<u64>,
+    max_age: Option<Duration>,
+) -> GcPlan<'a> {
+    let mut remove = Vec::new();
+    let mut evictable = Vec::new();
+    let mut kept = 0;
+    for entry in entries {
+        let expired = max_age.is_some_and(|max_age| age(now, entry.modified) > max_age);
+        match references.usage(entry) {
+            Usage::Unused => remove.push(entry),
+            Usage::Locked if expired => remove.push(entry),
+            Usage::Locked => {
+                evictable.push(entry);
+                kept += entry.size;
+            }
+            Usage::InUse => kept += entry.size,
+        }
+    }
+
+    if let Some(max_size) = max_size {
+        evictable.sort_by_key(|entry| entry.modified);
+        for entry in evictable {
+            if kept <= max_size {
+                break;
+            }
+            remove.push(entry);
+            kept -= entry.size;
+        }
+    }
+
+    GcPlan { remove, kept }
+}
+
+fn describe(kind: &CacheEntryKind) -> String {
+    match kind {
+        CacheEntryKind::Plugin {
+            marketplace,
+            plugin,
+            commit,
+        } => format!("plugin {}@{} {}", plugin, marketplace, short_commit(commit)),
+        CacheEntryKind::PluginRepo { marketplace, plugin } => {
+            format!("plugin repository {}@{}", plugin, marketplace)
+        }
+        CacheEntryKind::Marketplace { name } => format!("marketplace {}", name),
+    }
+}
+
+fn age(now: SystemTime, modified: SystemTime) -> Duration {
+    now.duration_since(modified).unwrap_or_default()
+}
+
+/// Format how long ago something was modified, in its largest whole unit.
+fn format_age(now: SystemTime, modified: SystemTime) -> String {
+    let secs = age(now, modified).as_secs();
+    match secs {
+        0..60 => "just now".to_string(),
+        60..3600 => format!("{}m ago", secs / 60),
+        3600..86400 => format!("{}h ago", secs / 3600),
+        _ => format!("{}d ago", secs / 86400),
+    }
+}
+
+const SIZE_UNITS: [(&str, u64); 4] = [("T", 1 << 40), ("G", 1 << 30), ("M", 1 << 20), ("K", 1 << 10)];
+
+/// Format a size in bytes with binary units, e.g. `1.5 MiB`.
+fn format_size(bytes: u64) -> String {
+    for (unit, factor) in SIZE_UNITS {
+        if bytes >= factor {
+            return format!("{:.1} {}iB", bytes as f64 / factor as f64, unit);
+        }
+    }
+    format!("{} B", bytes)
+}
+
+/// Parse a size such as `500M`, `2GiB` or `1.5G`. Units are binary; a bare
+/// number is in bytes.
+pub(super) fn parse_size(s: &str) -> std::result::Result<u64, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit() && c != '.').unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: f64 = number.parse().map_err(|_| format!("invalid size '{}'", s))?;
+
+    let unit = unit.trim().to_ascii_uppercase();
+    let unit = unit.trim_end_matches("IB").trim_end_matches('B');
+    let factor = if unit.is_empty() {
+        1
+    } else {
+        SIZE_UNITS
+            .iter()
+            .find(|(name, _)| *name == unit)
+            .map(|(_, factor)| *factor)
+            .ok_or_else(|| format!("unknown unit in '{}' (use K, M, G or T)", s))?
+    };
+    Ok((number * factor as f64) as u64)
+}
+
+/// Parse an age such as `30d`, `12h` or `2w`. A bare number is in days.
+pub(super) fn parse_age(s: &str) -> std::result::Result<Duration, String> {
+    let s = s.trim();
+    let split = s.find(|c: char| !c.is_ascii_digit()).unwrap_or(s.len());
+    let (number, unit) = s.split_at(split);
+    let number: u64 = number.parse().map_err(|_| format!("invalid age '{}'", s))?;
+
+    let hours = match unit.trim() {
+        "h" => 1,
+        "" | "d" => 24,
+        "w" => 24 * 7,
+        _ => return Err(format!("unknown unit in '{}' (use h, d or w)", s)),
+    };
+    number
+        .checked_mul(hours * 3600)
+        .map(Duration::from_secs)
+        .ok_or_else(|| format!("age '{}' is too large", s))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::path::Path;
+
+    fn entry(cache_dir: &Path, kind: CacheEntryKind, size: u64, days_old: u64, now: SystemTime) -> CacheEntry {
+        let path = match &kind {
+            CacheEntryKind::Plugin {
+                marketplace,
+                plugin,
+                commit,
+            } => cache_dir.join("plugins").join(marketplace).join(plugin).join(commit),
+            CacheEntryKind::PluginRepo { marketplace, plugin } => {
+                cache_dir.join("plugin-repos").join(marketplace).join(plugin)
+            }
+            CacheEntryKind::Marketplace { name } => cache_dir.join("marketplaces").join(name),
+        };
+        CacheEntry {
+            kind,
+            path,
+            size,
+            modified: now - Duration::from_secs(days_old * 86400),
+        }
+    }
+
+    fn plugin(commit: &str) -> CacheEntryKind {
+        CacheEntryKind::Plugin {
+            marketplace: "dev".to_string(),
+            plugin: "foo".to_string(),
+            commit: commit.to_string(),
+        }
+    }
+
+    #[test]
+    fn test_plan_gc() {
+        let cache_dir = Path::new("/cache");
+        let now = SystemTime::now();
+        let entries = vec![
+            entry(cache_dir, plugin("installed"), 100, 90, now),
+            entry(cache_dir, plugin("locked-old"), 100, 60, now),
+            entry(cache_dir, plugin("locked-new"), 100, 1, now),
+            entry(cache_dir, plugin("unused"), 100, 0, now),
+            entry(cache_dir, CacheEntryKind::Marketplace { name: "dev".to_string() }, 100, 90, now),
+        ];
+        let references = References {
+            claude_paths: vec![
+                cache_dir.join("plugins/dev/foo/installed"),
+                cache_dir.join("marketplaces/dev"),
+            ],
+            locked: [plugin("installed"), plugin("locked-old"), plugin("locked-new")]
+                .into_iter()
+                .collect(),
+        };
+        let removed = |plan: GcPlan| -> Vec<String> {
+            plan.remove.iter().map(|e| e.path.file_name().unwrap().to_string_lossy().into_owned()).collect()
+        };
+
+        assert_eq!(references.usage(&entries[0]), Usage::InUse);
+        assert_eq!(references.usage(&entries[4]), Usage::InUse);
+
+        // Only unreferenced entries by default
+        let plan = plan_gc(&entries, &references, now, None, None);
+        assert_eq!(plan.kept, 400);
+        assert_eq!(removed(plan), ["unused"]);
+
+        // Locked entries past the age limit, never the ones Claude Code uses
+        let plan = plan_gc(&entries, &references, now, None, Some(Duration::from_secs(30 * 86400)));
+        assert_eq!(removed(plan), ["locked-old", "unused"]);
+
+        // The oldest locked entries until the cache fits
+        let plan = plan_gc(&entries, &references, now, Some(250), None);
+        assert_eq!(plan.kept, 200);
+        assert_eq!(removed(plan), ["unused", "locked-old", "locked-new"]);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_usage_through_symlinked_cache() {
+        let temp = tempfile::tempdir().unwrap();
+        let real = temp.path().join("real");
+        std::fs::create_dir_all(real.join("marketplaces/dev")).unwrap();
+        let link = temp.path().join("link");
+        std::os::unix::fs::symlink(&real, &link).unwrap();
+
+        // Claude Code records the real path, the cache is listed through the link
+        let references = References {
+            claude_paths: vec![canonical(real.join("marketplaces/dev"))],
+            locked: HashSet::new(),
+        };
+        let marketplace = entry(
+            &link,
+            CacheEntryKind::Marketplace { name: "dev".to_string() },
+            100,
+            0,
+            SystemTime::now(),
+        );
+        assert_eq!(references.usage(&marketplace), Usage::InUse);
+    }
+
+    #[test]
+    fn test_parse_size_and_age() {
+        assert_eq!(parse_size("1024"), Ok(1024));
+        assert_eq!(parse_size("500M"), Ok(500 << 20));
+        assert_eq!(parse_size("2GiB"), Ok(2 << 30));
+        assert_eq!(parse_size("1.5 gb"), Ok(3 << 29));
+        assert!(parse_size("10X").is_err());
+        assert!(parse_size("big").is_err());
+
+        assert_eq!(parse_age("30"), Ok(Duration::from_secs(30 * 86400)));
+        assert_eq!(parse_age("12h"), Ok(Duration::from_secs(12 * 3600)));
+        assert_eq!(parse_age("2w"), Ok(Duration::from_secs(14 * 86400)));
+        assert!(parse_age("1y").is_err());
+        assert!(parse_age("99999999999999w").is_err());
+
+        assert_eq!(format_size(512), "512 B");
+        assert_eq!(format_size(3 << 19), "1.5 MiB");
+    }
+}
@@ -0,0 +1,376 @@
+use std::io::{BufRead, IsTerminal, Write};
+use std::path::Path;
+
+use toml_edit::{DocumentMut, InlineTable, Item, Table, Value};
+
+use super::add::set_plugin_entry;
+use crate::config::{LockFile, Manifest, MarketplaceEntry, PluginEntry};
+use crate::{Error, Result};
+
+/// How to settle a plugin declared differently in the global and project manifests.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ConflictChoice {
+    /// Update the global manifest to the project version.
+    UpdateGlobal,
+    /// Keep the global version and skip the project plugin.
+    Skip,
+    /// Abort the installation without changing anything.
+    Abort,
+}
+
+impl ConflictChoice {
+    /// Map the `--prefer-global` / `--prefer-project` flags to a choice.
+    pub fn from_flags(prefer_global: bool, prefer_project: bool) -> Option<Self> {
+        if prefer_global {
+            Some(Self::Skip)
+        } else if prefer_project {
+            Some(Self::UpdateGlobal)
+        } else {
+            None
+        }
+    }
+
+    /// Ask the user how to resolve a conflict.
+    ///
+    /// Fails with [`Error::PluginConflict`] when stdin is not a terminal, so
+    /// non-interactive runs never block waiting for input.
+    pub fn prompt(conflict: &PluginConflict) -> Result<Self> {
+        let stdin = std::io::stdin();
+        if !stdin.is_terminal() {
+            return Err(conflict.to_error());
+        }
+
+        println!("    Options:");
+        println!("      1. Update global to the project version");
+        println!("      2. Skip (keep the global version)");
+        println!("      3. Abort");
+
+        let mut input = stdin.lock();
+        loop {
+            print!("    Choose [1-3]: ");
+            std::io::stdout().flush()?;
+
+            let mut line = String::new();
+            if input.read_line(&mut line)? == 0 {
+                return Ok(Self::Abort);
+            }
+
+            match line.trim() {
+                "1" => return Ok(Self::UpdateGlobal),
+                "2" => return Ok(Self::Skip),
+                "3" => return Ok(Self::Abort),
+                other => println!("    Invalid choice '{}'", other),
+            }
+        }
+    }
+}
+
+/// A plugin requested at different versions by the global and project manifests.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PluginConflict {
+    pub plugin: String,
+    pub global: PluginEntry,
+    pub project: PluginEntry,
+}
+
+impl PluginConflict {
+    /// Print the conflict, including the versions resolved in each lock file.
+    pub fn print(&self, global_lock: &LockFile, project_lock: &LockFile) {
+        println!("  Conflict: {} is declared in both manifests", self.plugin);
+        println!(
+            "    Global:  {}",
+            describe_version(&self.global, global_lock, &self.plugin)
+        );
+        println!(
+            "    Project: {}",
+            describe_version(&self.project, project_lock, &self.plugin)
+        );
+        println!("    Claude Code loads global over project plugins.");
+    }
+
+    /// The error reported when a conflict cannot be resolved interactively.
+    pub fn to_error(&self) -> Error {
+        Error::PluginConflict {
+            plugin: self.plugin.clone(),
+            global: describe_entry(&self.global),
+            project: describe_entry(&self.project),
+        }
+    }
+}
+
+/// Find plugins present in both manifests with a different marketplace or pin.
+///
+/// Conflicts are returned sorted by plugin name.
+pub fn detect_conflicts(global: &Manifest, project: &Manifest) -> Vec<PluginConflict> {
+    let mut conflicts: Vec<PluginConflict> = project
+        .plugins
+        .iter()
+        .filter_map(|(name, project_entry)| {
+            let global_entry = global.plugins.get(name)?;
+            if global_entry == project_entry {
+                return None;
+            }
+            Some(PluginConflict {
+                plugin: name.clone(),
+                global: global_entry.clone(),
+                project: project_entry.clone(),
+            })
+        })
+        .collect();
+
+    conflicts.sort_by(|a, b| a.plugin.cmp(&b.plugin));
+    conflicts
+}
+
+/// Rewrite the global plugins.toml so a plugin matches the project's entry.
+pub fn update_global_manifest(global_path: &Path, project: &Manifest, plugin: &str) -> Result<()> {
+    let content = std::fs::read_to_string(global_path).map_err(|e| Error::FileRead {
+        path: global_path.to_path_buf(),
+        source: e,
+    })?;
+
+    let mut doc: DocumentMut = content
+        .parse()
+        .map_err(|e: toml_edit::TomlError| Error::ManifestParse(e.to_string()))?;
+
+    apply_project_entry(&mut doc, project, plugin);
+
+    std::fs::write(global_path, doc.to_string()).map_err(|e| Error::FileWrite {
+        path: global_path.to_path_buf(),
+        source: e,
+    })
+}
+
+/// Replace a plugin entry in the global document with the project's entry.
+///
+/// The project's marketplace is copied into the document if it is not
+/// declared there yet.
+fn apply_project_entry(doc: &mut DocumentMut, project: &Manifest, plugin: &str) {
+    let Some(entry) = project.plugins.get(plugin) else {
+        return;
+    };
+
+    if !doc.contains_table("marketplaces") {
+        doc["marketplaces"] = Item::Table(Table::new());
+    }
+
+    let declared = doc["marketplaces"]
+        .as_table()
+        .is_some_and(|t| t.contains_key(&entry.marketplace));
+    if !declared && let Some(marketplace) = project.marketplaces.get(&entry.marketplace) {
+        doc["marketplaces"][&entry.marketplace] = Item::Value(marketplace_value(marketplace));
+    }
+
+    set_plugin_entry(doc, plugin, entry);
+}
+
+/// Build the `[marketplaces]` value for an entry, using the short form when unpinned.
+fn marketplace_value(entry: &MarketplaceEntry) -> Value {
+    // A relative path is relative to the project, so the global manifest gets the resolved one
+    if let Some(dir) = entry.directory() {
+        let mut table = InlineTable::new();
+        table.insert("path", dir.display().to_string().into());
+        return Value::InlineTable(table);
+    }
+    if entry.tag.is_none() && entry.commit.is_none() && entry.branch.is_none() {
+        return entry.url.clone().into();
+    }
+
+    let mut table = InlineTable::new();
+    table.insert("url", entry.url.clone().into());
+    if let Some(t) = &entry.tag {
+        table.insert("tag", t.clone().into());
+    }
+    if let Some(c) = &entry.commit {
+        table.insert("commit", c.clone().into());
+    }
+    if let Some(b) = &entry.branch {
+        table.insert("branch", b.clone().into());
+    }
+    Value::InlineTable(table)
+}
+
+/// Describe a requested version along with its resolved version from the lock.
+fn describe_version(entry: &PluginEntry, lock: &LockFile, plugin: &str) -> String {
+    let requested = describe_entry(entry);
+    match lock.find_package(plugin) {
+        Some(pkg) => format!("v{} ({})", pkg.resolved_version, requested),
+        None => requested,
+    }
+}
+
+/// Describe how a plugin entry is pinned, e.g. `official, tag: v4.1.1`.
+pub fn describe_entry(entry: &PluginEntry) -> String {
+    let origin = entry.origin();
+    if let Some(ref tag) = entry.tag {
+        format!("{}, tag: {}", origin, tag)
+    } else if let Some(ref commit) = entry.commit {
+        format!("{}, commit: {}", origin, &commit[..7.min(commit.len())])
+    } else if let Some(ref branch) = entry.branch {
+        format!("{}, branch: {}", origin, branch)
+    } else if let Some(ref version) = entry.version {
+        format!("{}, version: {}", origin, version)
+    } else {
+        format!("{}, latest", origin)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(content: &str) -> Manifest {
+        Manifest::parse(content).unwrap()
+    }
+
+    #[test]
+    fn test_same_version_is_not_a_conflict() {
+        let global = parse(
+            r#"
+[marketplaces]
+official = "owner/repo"
+
+[plugins]
+superpowers = { marketplace = "official", tag = "v4.1.1" }
+"#,
+        );
+        let project = global.clone();
+
+        assert!(detect_conflicts(&global, &project).is_empty());
+    }
+
+    #[test]
+    fn test_plugin_in_one_manifest_is_not_a_conflict() {
+        let global = parse(
+            r#"
+[marketplaces]
+official = "owner/repo"
+
+[plugins]
+superpowers = { marketplace = "official" }
+"#,
+        );
+        let project = parse(
+            r#"
+[marketplaces]
+official = "owner/repo"
+
+[plugins]
+typescript-lsp = { marketplace = "official" }
+"#,
+        );
+
+        assert!(detect_conflicts(&global, &project).is_empty());
+    }
+
+    #[test]
+    fn test_different_tag_is_a_conflict() {
+        let global = parse(
+            r#"
+[marketplaces]
+official = "owner/repo"
+
+[plugins]
+superpowers = { marketplace = "official", tag = "v4.0.0" }
+"#,
+        );
+        let project = parse(
+            r#"
+[marketplaces]
+official = "owner/repo"
+
+[plugins]
+superpowers = { marketplace = "official", tag = "v4.1.1" }
+"#,
+        );
+
+        let conflicts = detect_conflicts(&global, &project);
+        assert_eq!(conflicts.len(), 1);
+        assert_eq!(conflicts[0].plugin, "superpowers");
+        assert_eq!(conflicts[0].global.tag, Some("v4.0.0".to_string()));
+        assert_eq!(conflicts[0].project.tag, Some("v4.1.1".to_string()));
+    }
+
+    #[test]
+    fn test_different_marketplace_is_a_conflict() {
+        let global = parse(
+            r#"
+[marketplaces]
+official = "owner/repo"
+
+[plugins]
+superpowers = { marketplace = "official" }
+"#,
+        );
+        let project = parse(
+            r#"
+[marketplaces]
+fork = "someone/fork"
+
+[plugins]
+superpowers = { marketplace = "fork" }
+"#,
+        );
+
+        assert_eq!(detect_conflicts(&global, &project).len(), 1);
+    }
+
+    #[test]
+    fn test_apply_project_entry_copies_marketplace() {
+        let global_content = r#"# my global plugins
+[marketplaces]
+official = "owner/repo"
+
+[plugins]
+superpowers = { marketplace = "official" }
+"#;
+        let project = parse(
+            r#"
+[marketplaces]
+fork = { url = "someone/fork", tag = "v1.0" }
+
+[plugins]
+superpowers = { marketplace = "fork", commit = "abc123" }
+"#,
+        );
+
+        let mut doc: DocumentMut = global_content.parse().unwrap();
+        apply_project_entry(&mut doc, &project, "superpowers");
+
+        let content = doc.to_string();
+        assert!(content.starts_with("# my global plugins"));
+
+        let global = parse(&content);
+        assert_eq!(global.plugins["superpowers"], project.plugins["superpowers"]);
+        assert_eq!(global.marketplaces["fork"], project.marketplaces["fork"]);
+        assert!(global.validate().is_ok());
+        assert!(detect_conflicts(&global, &project).is_empty());
+    }
+
+    #[test]
+    fn test_apply_project_entry_keeps_declared_marketplace() {
+        let global_content = r#"
+[marketplaces]
+official = "owner/repo"
+
+[plugins]
+superpowers = { marketplace = "official", tag = "v4.0.0" }
+"#;
+        let project = parse(
+            r#"
+[marketplaces]
+official = "owner/repo"
+
+[plugins]
+superpowers = { marketplace = "official", tag = "v4.1.1" }
+"#,
+        );
+
+        let mut doc: DocumentMut = global_content.parse().unwrap();
+        apply_project_entry(&mut doc, &project, "superpowers");
+
+        let global = parse(&doc.to_string());
+        assert_eq!(global.marketplaces.len(), 1);
+        assert_eq!(global.plugins["superpowers"].tag, Some("v4.1.1".to_string()));
+    }
+}
@@ -0,0 +1,555 @@
+use std::collections::{BTreeMap, HashMap, HashSet, VecDeque};
+
+use git2::Repository;
+use semver::{Version, VersionReq};
+
+use super::install::{resolve_package, short_commit};
+use crate::config::{LockFile, LockedMarketplace, LockedPackage, LockedRequirement, PluginEntry, GIT_MARKETPLACE};
+use crate::resolver::{MarketplaceResolver, PluginDependency, PluginSource};
+use crate::{Error, Result};
+
+/// Dependencies declared by freshly resolved packages, keyed by the dependent's name.
+pub(super) type PendingDependencies = Vec<(String, BTreeMap<String, PluginDependency>)>;
+
+/// Resolve the dependencies of freshly resolved packages transitively.
+///
+/// A dependency already in `packages` only has to satisfy the declaration.
+/// Others are resolved at their marketplace's locked commit and added as
+/// pulled-in packages; one from the previous lock is reused while its
+/// declaration and marketplace are unchanged. Each declaration is recorded
+/// on its dependent as a requirement. Dependencies of packages reused from
+/// the previous lock are carried over from it. Afterwards, pulled-in
+/// packages nothing depends on are dropped, every recorded requirement is
+/// checked against the package now locked for it, cycles are rejected and
+/// the packages are sorted by name.
+pub(super) fn resolve_dependencies(
+    marketplaces: &[LockedMarketplace],
+    packages: &mut Vec<LockedPackage>,
+    pending: PendingDependencies,
+    previous: Option<&LockFile>,
+    resolver: &MarketplaceResolver,
+) -> Result<()> {
+    let mut queue: VecDeque<_> = pending.into_iter().collect();
+
+    while let Some((dependent, dependencies)) = queue.pop_front() {
+        let dependent_marketplace = packages
+            .iter()
+            .find(|p| p.name == dependent)
+            .map(|p| p.marketplace.clone())
+            .unwrap_or_default();
+
+        for (name, dependency) in dependencies {
+            let entry = dependency_entry(&dependent, &dependent_marketplace, &name, &dependency)?;
+            if let Some(pkg) = packages.iter_mut().find(|p| p.name == dependent) {
+                pkg.requirements.insert(name.clone(), requirement(&entry));
+            }
+
+            // Checked against the declaration with every other requirement below
+            if packages.iter().any(|p| p.name == name) {
+                continue;
+            }
+
+            let marketplace = marketplaces
+                .iter()
+                .find(|m| m.name == entry.marketplace)
+                .ok_or_else(|| Error::UndeclaredMarketplace(entry.marketplace.clone()))?;
+
+            let fingerprint = entry.fingerprint();
+            if let Some(locked) = previous.and_then(|lock| {
+                lock.find_package(&name)
+                    .filter(|locked| !locked.direct && locked.fingerprint.as_ref() == Some(&fingerprint))
+                    .filter(|_| {
+                        lock.find_marketplace(&marketplace.name)
+                            .is_some_and(|m| m.fingerprint == marketplace.fingerprint)
+                    })
+            }) {
+                packages.push(locked.clone());
+                continue;
+            }
+
+            println!("  Resolving dependency '{}' of '{}'...", name, dependent);
+            let (mut package, dependencies) = resolve_package(&name, &entry, marketplace, resolver)?;
+            package.direct = false;
+            packages.push(package);
+            queue.push_back((name, dependencies));
+        }
+    }
+
+    if let Some(previous) = previous {
+        carry_dependencies(previous, packages);
+    }
+    prune_unreachable(packages);
+    check_requirements(marketplaces, packages, previous, resolver)?;
+    check_graph(packages)?;
+    packages.sort_by(|a, b| a.name.cmp(&b.name));
+
+    Ok(())
+}
+
+/// Turn a dependency declaration into the plugin entry it is resolved from.
+fn dependency_entry(
+    dependent: &str,
+    dependent_marketplace: &str,
+    name: &str,
+    dependency: &PluginDependency,
+) -> Result<PluginEntry> {
+    let invalid = |reason: String| Error::InvalidDependency {
+        plugin: dependent.to_string(),
+        dependency: name.to_string(),
+        reason,
+    };
+
+    if dependency.tag.is_some() && dependency.version.is_some() {
+        return Err(invalid("declares both a tag and a version".to_string()));
+    }
+    let version = dependency
+        .version
+        .as_deref()
+        .map(|v| VersionReq::parse(v).map_err(|e| invalid(format!("invalid version '{}': {}", v, e))))
+        .transpose()?;
+
+    // Git plugins have no marketplace of their own to default to
+    let marketplace = match dependency.marketplace {
+        Some(ref marketplace) => marketplace.clone(),
+        None if dependent_marketplace == GIT_MARKETPLACE => {
+            return Err(invalid("dependencies of git plugins must name a marketplace".to_string()));
+        }
+        None => dependent_marketplace.to_string(),
+    };
+
+    Ok(PluginEntry {
+        marketplace,
+        tag: dependency.tag.clone(),
+        version,
+        ..PluginEntry::default()
+    })
+}
+
+/// The requirement recorded in the lock for a dependency's entry.
+fn requirement(entry: &PluginEntry) -> LockedRequirement {
+    LockedRequirement {
+        marketplace: entry.marketplace.clone(),
+        tag: entry.tag.clone(),
+        version: entry.version.as_ref().map(VersionReq::to_string),
+    }
+}
+
+/// Check every recorded requirement against the package locked for it.
+///
+/// A pair that is locked exactly as in the previous lock was checked when
+/// that lock was written and is skipped.
+fn check_requirements(
+    marketplaces: &[LockedMarketplace],
+    packages: &[LockedPackage],
+    previous: Option<&LockFile>,
+    resolver: &MarketplaceResolver,
+) -> Result<()> {
+    let unchanged = |pkg: &LockedPackage| {
+        previous
+            .and_then(|lock| lock.find_package(&pkg.name))
+            .is_some_and(|locked| locked.plugin_commit == pkg.plugin_commit && locked.requirements == pkg.requirements)
+    };
+
+    for dependent in packages {
+        for (name, requirement) in &dependent.requirements {
+            let Some(pkg) = packages.iter().find(|p| &p.name == name) else {
+                continue;
+            };
+            if unchanged(dependent) && unchanged(pkg) {
+                continue;
+            }
+            check_satisfies(pkg, &dependent.name, requirement, marketplaces, resolver)?;
+        }
+    }
+
+    Ok(())
+}
+
+/// Check that a locked package satisfies a dependency's requirement.
+fn check_satisfies(
+    pkg: &LockedPackage,
+    dependent: &str,
+    requirement: &LockedRequirement,
+    marketplaces: &[LockedMarketplace],
+    resolver: &MarketplaceResolver,
+) -> Result<()> {
+    let unmet = if pkg.marketplace != requirement.marketplace {
+        Some(format!("from marketplace '{}'", requirement.marketplace))
+    } else if let Some(ref tag) = requirement.tag
+        && !at_tag(pkg, tag, marketplaces, resolver)?
+    {
+        Some(format!("at tag {}", tag))
+    } else if let Some(ref req) = requirement.version
+        && !VersionReq::parse(req)
+            .is_ok_and(|req| Version::parse(&pkg.resolved_version).is_ok_and(|v| req.matches(&v)))
+    {
+        Some(format!("version {}", req))
+    } else {
+        None
+    };
+
+    match unmet {
+        Some(requirement) => Err(Error::DependencyConflict {
+            plugin: pkg.name.clone(),
+            dependent: dependent.to_string(),
+            requirement,
+            resolved: format!(
+                "{} ({}) from '{}'",
+                pkg.resolved_version,
+                short_commit(&pkg.plugin_commit),
+                pkg.marketplace
+            ),
+        }),
+        None => Ok(()),
+    }
+}
+
+/// Whether a package is locked to the commit `tag` points to.
+///
+/// A package locked by version or branch can still be at the tag, so the
+/// tag is looked up in the repository the package was resolved from.
+fn at_tag(
+    pkg: &LockedPackage,
+    tag: &str,
+    marketplaces: &[LockedMarketplace],
+    resolver: &MarketplaceResolver,
+) -> Result<bool> {
+    if pkg.tag.as_deref() == Some(tag) {
+        return Ok(true);
+    }
+
+    let path = if let Some(ref url) = pkg.git {
+        resolver.fetch_plugin_repo_for(GIT_MARKETPLACE, &pkg.name, url, Some(&pkg.plugin_commit))?;
+        resolver.plugin_repo_path(GIT_MARKETPLACE, &pkg.name)
+    } else {
+        let marketplace = marketplaces
+            .iter()
+            .find(|m| m.name == pkg.marketplace)
+            .ok_or_else(|| Error::UndeclaredMarketplace(pkg.marketplace.clone()))?;
+        let mkt_json = resolver.marketplace_json_at(&marketplace.name, &marketplace.url, &pkg.marketplace_commit)?;
+        match &resolver.find_plugin(&mkt_json, &marketplace.name, &pkg.name)?.source {
+            PluginSource::External(source) => {
+                resolver.fetch_plugin_repo_for(&marketplace.name, &pkg.name, &source.url, Some(&pkg.plugin_commit))?;
+                resolver.plugin_repo_path(&marketplace.name, &pkg.name)
+            }
+            PluginSource::Local(_) | PluginSource::Unsupported(_) => resolver.marketplace_path(&marketplace.name),
+        }
+    };
+
+    // Directory marketplaces have no tags
+    let Ok(repo) = Repository::open(&path) else {
+        return Ok(false);
+    };
+    Ok(resolver.resolve_tag(&repo, &pkg.marketplace, tag).is_ok_and(|commit| commit == pkg.plugin_commit))
+}
+
+/// Copy dependencies of reused packages from the previous lock, transitively.
+fn carry_dependencies(previous: &LockFile, packages: &mut Vec<LockedPackage>) {
+    let mut index = 0;
+    while index < packages.len() {
+        for name in packages[index].dependencies.clone() {
+            if packages.iter().any(|p| p.name == name) {
+                continue;
+            }
+            if let Some(locked) = previous.find_package(&name) {
+                packages.push(LockedPackage {
+                    direct: false,
+                    ..locked.clone()
+                });
+            }
+        }
+        index += 1;
+    }
+}
+
+/// Drop pulled-in packages that no direct package depends on anymore.
+fn prune_unreachable(packages: &mut Vec<LockedPackage>) {
+    let by_name: HashMap<&str, &LockedPackage> = packages.iter().map(|p| (p.name.as_str(), p)).collect();
+    let mut reachable: HashSet<String> = HashSet::new();
+    let mut stack: Vec<&str> = packages.iter().filter(|p| p.direct).map(|p| p.name.as_str()).collect();
+
+    while let Some(name) = stack.pop() {
+        if !reachable.insert(name.to_string()) {
+            continue;
+        }
+        if let Some(pkg) = by_name.get(name) {
+            stack.extend(pkg.dependencies.iter().map(String::as_str));
+        }
+    }
+
+    packages.retain(|p| reachable.contains(&p.name));
+}
+
+/// Check that every dependency is locked and the dependency graph has no cycles.
+pub(super) fn check_graph(packages: &[LockedPackage]) -> Result<()> {
+    let by_name: HashMap<&str, &LockedPackage> = packages.iter().map(|p| (p.name.as_str(), p)).collect();
+
+    for pkg in packages {
+        for dependency in &pkg.dependencies {
+            if !by_name.contains_key(dependency.as_str()) {
+                return Err(Error::InvalidDependency {
+                    plugin: pkg.name.clone(),
+                    dependency: dependency.clone(),
+                    reason: "not in plugins.lock".to_string(),
+                });
+            }
+        }
+    }
+
+    // Depth-first search; a dependency on the current path closes a cycle
+    fn visit<'a>(
+        name: &'a str,
+        by_name: &HashMap<&'a str, &'a LockedPackage>,
+        path: &mut Vec<&'a str>,
+        done: &mut HashSet<&'a str>,
+    ) -> Result<()> {
+        if let Some(start) = path.iter().position(|n| *n == name) {
+            let mut cycle = path[start..].to_vec();
+            cycle.push(name);
+            return Err(Error::DependencyCycle(cycle.join(" -> ")));
+        }
+        if !done.insert(name) {
+            return Ok(());
+        }
+
+        path.push(name);
+        for dependency in &by_name[name].dependencies {
+            visit(dependency, by_name, path, done)?;
+        }
+        path.pop();
+        Ok(())
+    }
+
+    let mut names: Vec<&str> = by_name.keys().copied().collect();
+    names.sort();
+    let mut done = HashSet::new();
+    for name in names {
+        visit(name, &by_name, &mut Vec::new(), &mut done)?;
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SourceType;
+    use crate::test_util::{commit_files, marketplace_files};
+    use std::path::Path;
+
+    fn package(name: &str, dependencies: &[&str]) -> LockedPackage {
+        LockedPackage {
+            name: name.to_string(),
+            marketplace: "dev".to_string(),
+            source_type: SourceType::Local,
+            marketplace_commit: "abc1234".to_string(),
+            plugin_commit: "abc1234".to_string(),
+            resolved_version: "1.0.0".to_string(),
+            version_req: None,
+            tag: None,
+            branch: None,
+            git: None,
+            subdir: None,
+            content_hash: None,
+            fingerprint: None,
+            dependencies: dependencies.iter().map(|d| d.to_string()).collect(),
+            requirements: BTreeMap::new(),
+            direct: true,
+        }
+    }
+
+    /// A resolver with a `dev` marketplace holding the given plugins, and
+    /// the marketplace locked at the commit adding them.
+    fn marketplace(
+        temp_dir: &Path,
+        plugins: &[(&str, &str, &str)],
+        tags: &[&str],
+    ) -> (MarketplaceResolver, Vec<LockedMarketplace>) {
+        let upstream_dir = temp_dir.join("upstream");
+        let upstream = Repository::init(&upstream_dir).unwrap();
+        let commit = commit_files(&upstream, &marketplace_files(plugins), "plugins");
+        let target = upstream.revparse_single(&commit).unwrap();
+        for tag in tags {
+            upstream.tag_lightweight(tag, &target, false).unwrap();
+        }
+
+        let resolver = MarketplaceResolver::new(temp_dir.join("cache"));
+        resolver
+            .ensure_marketplace("dev", &format!("file://{}", upstream_dir.display()))
+            .unwrap();
+        let marketplaces = vec![LockedMarketplace {
+            name: "dev".to_string(),
+            url: format!("file://{}", upstream_dir.display()),
+            path: None,
+            commit,
+            branch: None,
+            fingerprint: None,
+        }];
+        (resolver, marketplaces)
+    }
+
+    /// Resolve `direct` from the `dev` marketplace onto `packages`, then their dependencies.
+    fn resolve_onto(
+        packages: &mut Vec<LockedPackage>,
+        direct: &[&str],
+        previous: Option<&LockFile>,
+        marketplaces: &[LockedMarketplace],
+        resolver: &MarketplaceResolver,
+    ) -> Result<()> {
+        let mut pending = Vec::new();
+        for name in direct {
+            let entry = PluginEntry {
+                marketplace: "dev".to_string(),
+                ..PluginEntry::default()
+            };
+            let (package, dependencies) = resolve_package(name, &entry, &marketplaces[0], resolver)?;
+            packages.push(package);
+            pending.push((name.to_string(), dependencies));
+        }
+        resolve_dependencies(marketplaces, packages, pending, previous, resolver)
+    }
+
+    fn resolve(plugins: &[(&str, &str, &str)], direct: &[&str]) -> Result<Vec<LockedPackage>> {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (resolver, marketplaces) = marketplace(temp_dir.path(), plugins, &[]);
+        let mut packages = Vec::new();
+        resolve_onto(&mut packages, direct, None, &marketplaces, &resolver)?;
+        Ok(packages)
+    }
+
+    #[test]
+    fn test_resolve_dependencies_transitively() {
+        let packages = resolve(
+            &[
+                ("skills", "1.0.0", r#""lsp": "^2""#),
+                ("lsp", "2.1.0", r#""util": {"version": "1"}"#),
+                ("util", "1.4.0", ""),
+                ("unused", "1.0.0", ""),
+            ],
+            &["skills"],
+        )
+        .unwrap();
+
+        let names: Vec<_> = packages.iter().map(|p| (p.name.as_str(), p.direct)).collect();
+        assert_eq!(names, vec![("lsp", false), ("skills", true), ("util", false)]);
+        assert_eq!(packages[0].dependencies, vec!["util"]);
+        assert_eq!(packages[1].dependencies, vec!["lsp"]);
+    }
+
+    #[test]
+    fn test_resolve_dependencies_conflict() {
+        let err = resolve(
+            &[("skills", "1.0.0", r#""lsp": "^3""#), ("lsp", "2.1.0", "")],
+            &["lsp", "skills"],
+        )
+        .unwrap_err();
+
+        assert!(matches!(err, Error::DependencyConflict { ref plugin, ref dependent, .. }
+            if plugin == "lsp" && dependent == "skills"));
+    }
+
+    #[test]
+    fn test_resolve_dependencies_records_requirements() {
+        let packages = resolve(
+            &[("skills", "1.0.0", r#""lsp": "^2""#), ("lsp", "2.1.0", "")],
+            &["skills"],
+        )
+        .unwrap();
+
+        let requirement = &packages[1].requirements["lsp"];
+        assert_eq!(requirement.marketplace, "dev");
+        assert_eq!(requirement.version.as_deref(), Some("^2"));
+        assert_eq!(requirement.tag, None);
+    }
+
+    #[test]
+    fn test_tag_requirement_compares_commits() {
+        let plugins = [("skills", "1.0.0", r#""lsp": {"tag": "lsp-v2"}"#), ("lsp", "2.1.0", "")];
+
+        // lsp is locked without a tag, but its commit is the one lsp-v2 points to
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (resolver, marketplaces) = marketplace(temp_dir.path(), &plugins, &["lsp-v2"]);
+        let mut packages = Vec::new();
+        resolve_onto(&mut packages, &["lsp", "skills"], None, &marketplaces, &resolver).unwrap();
+        assert_eq!(packages[0].tag, None);
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (resolver, marketplaces) = marketplace(temp_dir.path(), &plugins, &[]);
+        let err = resolve_onto(&mut Vec::new(), &["lsp", "skills"], None, &marketplaces, &resolver).unwrap_err();
+        assert!(matches!(err, Error::DependencyConflict { ref requirement, .. } if requirement == "at tag lsp-v2"));
+    }
+
+    #[test]
+    fn test_reused_dependent_is_checked_against_updated_dependency() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let (resolver, marketplaces) = marketplace(temp_dir.path(), &[("lsp", "3.0.0", "")], &[]);
+
+        // skills was locked needing lsp ^2, which lsp 2.1.0 satisfied
+        let mut skills = package("skills", &["lsp"]);
+        skills.requirements.insert(
+            "lsp".to_string(),
+            LockedRequirement {
+                marketplace: "dev".to_string(),
+                tag: None,
+                version: Some("^2".to_string()),
+            },
+        );
+        let mut lsp = package("lsp", &[]);
+        lsp.resolved_version = "2.1.0".to_string();
+        let previous = LockFile {
+            packages: vec![lsp, skills.clone()],
+            ..LockFile::new()
+        };
+
+        // Only lsp is resolved again, and skills is reused as it was
+        let mut packages = vec![skills];
+        let err = resolve_onto(&mut packages, &["lsp"], Some(&previous), &marketplaces, &resolver).unwrap_err();
+        assert!(matches!(err, Error::DependencyConflict { ref plugin, ref dependent, ref requirement, .. }
+            if plugin == "lsp" && dependent == "skills" && requirement == "version ^2"));
+    }
+
+    #[test]
+    fn test_git_plugin_dependency_needs_marketplace() {
+        let dependency = PluginDependency {
+            version: Some("^1".to_string()),
+            ..PluginDependency::default()
+        };
+        let err = dependency_entry("tool", GIT_MARKETPLACE, "lsp", &dependency).unwrap_err();
+        assert!(
+            matches!(err, Error::InvalidDependency { ref reason, .. } if reason.contains("must name a marketplace")),
+            "{}",
+            err
+        );
+
+        let dependency = PluginDependency {
+            marketplace: Some("dev".to_string()),
+            ..dependency
+        };
+        let entry = dependency_entry("tool", GIT_MARKETPLACE, "lsp", &dependency).unwrap();
+        assert_eq!(entry.marketplace, "dev");
+    }
+
+    #[test]
+    fn test_check_graph_detects_cycles() {
+        let packages = vec![package("a", &["b"]), package("b", &["c"]), package("c", &["a"])];
+        let err = check_graph(&packages).unwrap_err();
+        assert_eq!(err.to_string(), "dependency cycle: a -> b -> c -> a");
+
+        let packages = vec![package("a", &["b", "c"]), package("b", &["c"]), package("c", &[])];
+        check_graph(&packages).unwrap();
+
+        let packages = vec![package("a", &["missing"])];
+        assert!(matches!(check_graph(&packages), Err(Error::InvalidDependency { .. })));
+    }
+
+    #[test]
+    fn test_prune_unreachable() {
+        let mut packages = vec![package("a", &["b"]), package("b", &[]), package("c", &[])];
+        packages[1].direct = false;
+        packages[2].direct = false;
+        prune_unreachable(&mut packages);
+
+        let names: Vec<_> = packages.iter().map(|p| p.name.as_str()).collect();
+        assert_eq!(names, vec!["a", "b"]);
+    }
+}
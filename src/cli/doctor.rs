@@ -0,0 +1,710 @@
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fmt;
+use std::path::{Path, PathBuf};
+
+use serde_json::Value;
+
+use super::conflict;
+use super::install::{changed_directories, lock_diff, project_scope, short_commit, transaction_paths};
+use crate::config::{LockFile, Manifest, GIT_MARKETPLACE};
+use crate::installer::{
+    CacheManager, ClaudeCodeIntegration, InstalledPluginEntry, KnownMarketplaceEntry, PluginScope, Transaction,
+};
+use crate::resolver::MarketplaceResolver;
+use crate::{Error, Result};
+
+/// How serious a finding is.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Severity {
+    /// Worth knowing, but nothing is broken.
+    Note,
+    /// Likely unintended; Claude Code still loads what the lock says.
+    Warning,
+    /// Claude Code does not load a plugin the way the lock says.
+    Error,
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Severity::Note => write!(f, "note"),
+            Severity::Warning => write!(f, "warning"),
+            Severity::Error => write!(f, "error"),
+        }
+    }
+}
+
+/// A problem found by `doctor`.
+#[derive(Debug, Clone)]
+struct Finding {
+    severity: Severity,
+    /// What is wrong, in one line.
+    message: String,
+    /// Why it matters and how to repair it by hand.
+    explanation: String,
+    /// The repair `--fix` applies, if one is safe.
+    fix: Option<Fix>,
+}
+
+impl Finding {
+    fn new(severity: Severity, message: String, explanation: String) -> Self {
+        Self {
+            severity,
+            message,
+            explanation,
+            fix: None,
+        }
+    }
+
+    fn with_fix(mut self, fix: Option<Fix>) -> Self {
+        self.fix = fix;
+        self
+    }
+}
+
+/// A repair that only brings Claude Code's files in line with a lock, using
+/// plugins and marketplaces already in the cache.
+#[derive(Debug, Clone)]
+enum Fix {
+    /// Point installed_plugins.json at the locked, extracted plugin.
+    RegisterPlugin {
+        name: String,
+        marketplace: String,
+        path: PathBuf,
+        version: String,
+        commit: String,
+        scope: PluginScope,
+    },
+    /// Add the plugin to enabledPlugins in settings.json.
+    EnablePlugin { name: String, marketplace: String },
+    /// Point known_marketplaces.json at the marketplace's cache path.
+    RegisterMarketplace { name: String, path: PathBuf },
+    /// Drop an installed_plugins.json entry whose files are gone, disabling
+    /// the plugin once no scope has it installed.
+    RemovePlugin {
+        name: String,
+        marketplace: String,
+        scope: PluginScope,
+    },
+}
+
+impl Fix {
+    fn apply(&self, claude: &ClaudeCodeIntegration) -> Result<()> {
+        match self {
+            Fix::RegisterPlugin {
+                name,
+                marketplace,
+                path,
+                version,
+                commit,
+                scope,
+            } => claude.add_installed_plugin(name, marketplace, path, version, commit, scope),
+            Fix::EnablePlugin { name, marketplace } => claude.enable_plugin(name, marketplace),
+            Fix::RegisterMarketplace { name, path } => claude.register_marketplace(name, path),
+            Fix::RemovePlugin {
+                name,
+                marketplace,
+                scope,
+            } => {
+                claude.remove_installed_plugin(name, marketplace, scope)?;
+                let key = format!("{}@{}", name, marketplace);
+                let still_installed = claude
+                    .read_installed_plugins()?
+                    .plugins
+                    .get(&key)
+                    .is_some_and(|entries| !entries.is_empty());
+                if !still_installed {
+                    claude.disable_plugin(name, marketplace)?;
+                }
+                Ok(())
+            }
+        }
+    }
+}
+
+/// A manifest checked against the scope it installs into.
+struct Target {
+    manifest: Manifest,
+    scope: PluginScope,
+    /// Plugins the global manifest declares differently, which install skips.
+    skipped: HashSet<String>,
+    /// Resolves the manifest's marketplaces, like install's resolver for it.
+    resolver: MarketplaceResolver,
+}
+
+/// Cross-check the manifests, lock files, cache and Claude Code's files.
+///
+/// With `fix`, repairs that are safe are applied under the same lock and
+/// rollback as `install`, and the remaining problems are reported. Nothing is
+/// fixed while an interrupted install is pending, since only `install` rolls
+/// it back. Fails if any error is left.
+pub fn run(fix: bool) -> Result<()> {
+    let global_manifest = Manifest::load_global()?;
+    let project_manifest = Manifest::load_project()?;
+
+    if global_manifest.is_none() && project_manifest.is_none() {
+        println!("No plugins.toml found. Run `skill-manager init` to create one.");
+        return Ok(());
+    }
+
+    let cache = CacheManager::new()?;
+    let claude = ClaudeCodeIntegration::new();
+    let resolver = MarketplaceResolver::new(cache.cache_dir().to_path_buf());
+
+    let conflicting: HashSet<String> = match (&global_manifest, &project_manifest) {
+        (Some(global), Some(project)) => conflict::detect_conflicts(global, project)
+            .into_iter()
+            .map(|c| c.plugin)
+            .collect(),
+        _ => HashSet::new(),
+    };
+    let mut targets = Vec::new();
+    if let Some(manifest) = global_manifest {
+        targets.push(Target {
+            resolver: resolver.for_manifest(&manifest),
+            manifest,
+            scope: PluginScope::User,
+            skipped: HashSet::new(),
+        });
+    }
+    if let Some(manifest) = project_manifest {
+        targets.push(Target {
+            resolver: resolver.for_manifest(&manifest),
+            manifest,
+            scope: project_scope()?,
+            skipped: conflicting,
+        });
+    }
+
+    if !fix {
+        let findings = diagnose(&targets, &cache, &claude)?;
+        return report(&findings);
+    }
+
+    // An interrupted install is reported, not rolled back as a side effect
+    if Transaction::pending(cache.cache_dir())?.is_some() {
+        println!("Not fixing anything until the interrupted install is rolled back.\n");
+        let findings = diagnose(&targets, &cache, &claude)?;
+        return report(&findings);
+    }
+
+    cache.ensure_cache_dir()?;
+    let mut transaction = Transaction::begin(cache.cache_dir(), &transaction_paths(&claude))?;
+
+    let fixed = diagnose(&targets, &cache, &claude).and_then(|findings| {
+        let mut fixed = 0;
+        for finding in &findings {
+            if let Some(ref fix) = finding.fix {
+                fix.apply(&claude)?;
+                transaction.checkpoint()?;
+                println!("Fixed: {}", finding.message);
+                fixed += 1;
+            }
+        }
+        Ok(fixed)
+    });
+    match fixed {
+        Ok(fixed) => {
+            transaction.commit()?;
+            if fixed > 0 {
+                println!();
+            }
+        }
+        Err(e) => {
+            if let Err(rollback_err) = transaction.rollback() {
+                eprintln!("Warning: could not restore files changed by the fixes: {}", rollback_err);
+            }
+            return Err(e);
+        }
+    }
+
+    let findings = diagnose(&targets, &cache, &claude)?;
+    report(&findings)
+}
+
+/// Print findings and a summary. Fails if any of them is an error.
+fn report(findings: &[Finding]) -> Result<()> {
+    if findings.is_empty() {
+        println!("No problems found.");
+        return Ok(());
+    }
+
+    for finding in findings {
+        println!("{}: {}", finding.severity, finding.message);
+        for line in finding.explanation.lines() {
+            println!("  {}", line);
+        }
+        if finding.fix.is_some() {
+            println!("  `skill-manager doctor --fix` repairs this.");
+        }
+    }
+
+    let count = |severity| findings.iter().filter(|f| f.severity == severity).count();
+    let errors = count(Severity::Error);
+    println!(
+        "\n{} error(s), {} warning(s), {} note(s)",
+        errors,
+        count(Severity::Warning),
+        count(Severity::Note)
+    );
+
+    if errors > 0 {
+        return Err(Error::DoctorFoundErrors(errors));
+    }
+    Ok(())
+}
+
+/// Collect every finding, most fundamental first: an interrupted install,
+/// then each manifest and its lock, then Claude Code's marketplaces.
+fn diagnose(
+    targets: &[Target],
+    cache: &CacheManager,
+    claude: &ClaudeCodeIntegration,
+) -> Result<Vec<Finding>> {
+    let mut findings = Vec::new();
+
+    if let Some(started) = Transaction::pending(cache.cache_dir())? {
+        findings.push(Finding::new(
+            Severity::Error,
+            format!("an install started at {} did not finish", started),
+            "Claude Code's files and plugins.lock may be half-updated, unless that install is still running.\n\
+             Run `skill-manager install` to roll them back; `doctor --fix` leaves them alone."
+                .to_string(),
+        ));
+    }
+
+    let known = claude.read_known_marketplaces()?;
+    let settings = claude.read_settings()?;
+    let enabled = match settings.get("enabledPlugins") {
+        Some(Value::Object(map)) => map.clone(),
+        _ => serde_json::Map::new(),
+    };
+
+    // Where install registers each marketplace; the project's wins a clash
+    let mut marketplaces = BTreeMap::new();
+    for target in targets {
+        let Some(lock) = check_lock(target, &mut findings)? else {
+            continue;
+        };
+        let mut names: Vec<&str> = lock.marketplaces.iter().map(|m| m.name.as_str()).collect();
+        if lock.packages.iter().any(|pkg| pkg.git.is_some()) {
+            names.push(GIT_MARKETPLACE);
+        }
+        for name in names {
+            marketplaces.insert(name.to_string(), target.resolver.marketplace_path(name));
+        }
+
+        let installed: HashMap<String, InstalledPluginEntry> =
+            claude.installed_plugins_in_scope(&target.scope)?.into_iter().collect();
+        check_packages(target, &lock, &installed, &enabled, cache, &mut findings);
+        check_leftovers(target, &lock, &installed, cache, &mut findings);
+    }
+
+    check_marketplaces(&marketplaces, &known, cache, &mut findings);
+    Ok(findings)
+}
+
+/// Check that a manifest is valid and its lock exists and is up to date.
+/// Returns the lock, if it can be read.
+fn check_lock(target: &Target, findings: &mut Vec<Finding>) -> Result<Option<LockFile>> {
+    let manifest = &target.manifest;
+    let manifest_path = manifest.path.clone().ok_or(Error::NoManifest)?;
+
+    if let Err(e) = manifest.validate() {
+        findings.push(Finding::new(
+            Severity::Error,
+            format!("{} is invalid: {}", manifest_path.display(), e),
+            "skill-manager refuses to install from it until it is corrected.".to_string(),
+        ));
+        return Ok(None);
+    }
+
+    let lock_path = LockFile::path_for_manifest(&manifest_path);
+    let lock = match LockFile::load_if_exists(&lock_path) {
+        Ok(Some(lock)) => lock,
+        Ok(None) => {
+            findings.push(Finding::new(
+                Severity::Error,
+                format!("{} has no lock file", manifest_path.display()),
+                "None of its plugins are installed yet. Run `skill-manager install` to resolve and install them."
+                    .to_string(),
+            ));
+            return Ok(None);
+        }
+        Err(e) => {
+            findings.push(Finding::new(
+                Severity::Error,
+                format!("{} cannot be read: {}", lock_path.display(), e),
+                "Delete it and run `skill-manager install` to resolve the manifest again.".to_string(),
+            ));
+            return Ok(None);
+        }
+    };
+
+    let up_to_date = lock.config_hash == Some(manifest.compute_hash())
+        || lock.config_hash == Some(manifest.compute_legacy_hash());
+    let mut changes = if up_to_date { Vec::new() } else { lock_diff(manifest, &lock) };
+    changes.extend(
+        changed_directories(&lock, &target.resolver)
+            .iter()
+            .map(|name| format!("~ marketplace '{}': directory contents changed", name)),
+    );
+    if !up_to_date || !changes.is_empty() {
+        changes.push("Claude Code still has the locked versions. Run `skill-manager install` to apply the changes.".to_string());
+        findings.push(Finding::new(
+            Severity::Warning,
+            format!("{} is out of date with {}", lock_path.display(), manifest_path.display()),
+            changes.join("\n"),
+        ));
+    }
+
+    Ok(Some(lock))
+}
+
+/// Check each locked package against the cache, installed_plugins.json and
+/// enabledPlugins.
+fn check_packages(
+    target: &Target,
+    lock: &LockFile,
+    installed: &HashMap<String, InstalledPluginEntry>,
+    enabled: &serde_json::Map<String, Value>,
+    cache: &CacheManager,
+    findings: &mut Vec<Finding>,
+) {
+    for pkg in &lock.packages {
+        let key = format!("{}@{}", pkg.name, pkg.marketplace);
+        if target.skipped.contains(&pkg.name) {
+            findings.push(Finding::new(
+                Severity::Note,
+                format!("plugin '{}' is declared differently in the global manifest", pkg.name),
+                "Claude Code loads global plugins over project ones, so install skips the project version."
+                    .to_string(),
+            ));
+            continue;
+        }
+
+        let path = cache.plugin_path(&pkg.marketplace, &pkg.name, &pkg.plugin_commit);
+        let extracted = match pkg.content_hash {
+            Some(ref hash) => cache.is_plugin_extracted(&pkg.marketplace, &pkg.name, &pkg.plugin_commit, hash),
+            None => path.exists(),
+        };
+        if !path.exists() {
+            findings.push(Finding::new(
+                Severity::Error,
+                format!("plugin '{}' is not extracted in the cache", key),
+                format!(
+                    "Expected at {}. Run `skill-manager install` to extract it from the locked commit.",
+                    path.display()
+                ),
+            ));
+        } else if !extracted {
+            findings.push(Finding::new(
+                Severity::Error,
+                format!("the cached copy of plugin '{}' does not match plugins.lock", key),
+                format!(
+                    "Files under {} were changed or left half-written. Run `skill-manager install` to extract it again.",
+                    path.display()
+                ),
+            ));
+        }
+
+        let register = extracted.then(|| Fix::RegisterPlugin {
+            name: pkg.name.clone(),
+            marketplace: pkg.marketplace.clone(),
+            path: path.clone(),
+            version: pkg.resolved_version.clone(),
+            commit: pkg.plugin_commit.clone(),
+            scope: target.scope.clone(),
+        });
+        let scope = describe_scope(&target.scope);
+        match installed.get(&key) {
+            None => findings.push(
+                Finding::new(
+                    Severity::Error,
+                    format!("plugin '{}' is not in installed_plugins.json for {}", key, scope),
+                    "Claude Code only loads plugins registered there.".to_string(),
+                )
+                .with_fix(register),
+            ),
+            Some(entry) if !Path::new(&entry.install_path).exists() => findings.push(
+                Finding::new(
+                    Severity::Error,
+                    format!("plugin '{}' is registered at {}, which does not exist", key, entry.install_path),
+                    "Claude Code cannot load it from there.".to_string(),
+                )
+                .with_fix(register),
+            ),
+            Some(entry) if Path::new(&entry.install_path) != path || entry.git_commit_sha != pkg.plugin_commit => {
+                findings.push(
+                    Finding::new(
+                        Severity::Warning,
+                        format!(
+                            "plugin '{}' is registered at {} but locked at {}",
+                            key,
+                            short_commit(&entry.git_commit_sha),
+                            short_commit(&pkg.plugin_commit)
+                        ),
+                        format!(
+                            "Claude Code loads {} instead of the locked copy, \
+                             probably after an update through its /plugin UI.",
+                            entry.install_path
+                        ),
+                    )
+                    .with_fix(register),
+                )
+            }
+            Some(_) => {}
+        }
+
+        match enabled.get(&key) {
+            Some(Value::Bool(true)) => {}
+            Some(Value::Bool(false)) => findings.push(Finding::new(
+                Severity::Note,
+                format!("plugin '{}' is disabled in settings.json", key),
+                "It was turned off, probably through Claude Code's /plugin UI; turn it on there to load it.".to_string(),
+            )),
+            _ => findings.push(
+                Finding::new(
+                    Severity::Error,
+                    format!("plugin '{}' is not enabled in settings.json", key),
+                    "Claude Code does not load plugins missing from enabledPlugins.".to_string(),
+                )
+                .with_fix(Some(Fix::EnablePlugin {
+                    name: pkg.name.clone(),
+                    marketplace: pkg.marketplace.clone(),
+                })),
+            ),
+        }
+    }
+}
+
+/// Check plugins skill-manager installed for a scope that are no longer locked.
+fn check_leftovers(
+    target: &Target,
+    lock: &LockFile,
+    installed: &HashMap<String, InstalledPluginEntry>,
+    cache: &CacheManager,
+    findings: &mut Vec<Finding>,
+) {
+    let wanted: HashSet<String> = lock
+        .packages
+        .iter()
+        .filter(|pkg| !target.skipped.contains(&pkg.name))
+        .map(|pkg| format!("{}@{}", pkg.name, pkg.marketplace))
+        .collect();
+    let plugins_dir = cache.cache_dir().join("plugins");
+
+    let mut leftovers: Vec<(&String, &InstalledPluginEntry)> = installed
+        .iter()
+        .filter(|(key, entry)| !wanted.contains(*key) && Path::new(&entry.install_path).starts_with(&plugins_dir))
+        .collect();
+    leftovers.sort_by(|a, b| a.0.cmp(b.0));
+
+    for (key, entry) in leftovers {
+        let Some((name, marketplace)) = key.rsplit_once('@') else {
+            continue;
+        };
+        let scope = describe_scope(&target.scope);
+        if Path::new(&entry.install_path).exists() {
+            findings.push(Finding::new(
+                Severity::Note,
+                format!("plugin '{}' is installed for {} but no longer locked", key, scope),
+                "Run `skill-manager install --prune` to remove it.".to_string(),
+            ));
+        } else {
+            findings.push(
+                Finding::new(
+                    Severity::Warning,
+                    format!("plugin '{}' is installed for {} from {}, which does not exist", key, scope, entry.install_path),
+                    "skill-manager installed it, but it is no longer locked and its files are gone.".to_string(),
+                )
+                .with_fix(Some(Fix::RemovePlugin {
+                    name: name.to_string(),
+                    marketplace: marketplace.to_string(),
+                    scope: target.scope.clone(),
+                })),
+            );
+        }
+    }
+}
+
+/// Check the locked marketplaces' cache paths and registrations, and every
+/// registered marketplace's location.
+fn check_marketplaces(
+    locked: &BTreeMap<String, PathBuf>,
+    known: &HashMap<String, KnownMarketplaceEntry>,
+    cache: &CacheManager,
+    findings: &mut Vec<Finding>,
+) {
+    for (name, path) in locked {
+        let exists = path.exists();
+        if !exists {
+            findings.push(Finding::new(
+                Severity::Error,
+                format!("marketplace '{}' is missing from the cache", name),
+                format!(
+                    "Expected at {}; Claude Code cannot read its marketplace.json. \
+                     Run `skill-manager install` to restore it.",
+                    path.display()
+                ),
+            ));
+        }
+
+        let register = exists.then(|| Fix::RegisterMarketplace {
+            name: name.clone(),
+            path: path.clone(),
+        });
+        match known.get(name) {
+            None => findings.push(
+                Finding::new(
+                    Severity::Error,
+                    format!("marketplace '{}' is not in known_marketplaces.json", name),
+                    "Claude Code ignores plugins from marketplaces it does not know.".to_string(),
+                )
+                .with_fix(register),
+            ),
+            Some(entry) if Path::new(&entry.install_location) != path => findings.push(
+                Finding::new(
+                    Severity::Warning,
+                    format!("marketplace '{}' is registered at {}", name, entry.install_location),
+                    format!("skill-manager installs its plugins from {}.", path.display()),
+                )
+                .with_fix(register),
+            ),
+            Some(_) => {}
+        }
+    }
+
+    let mut others: Vec<(&String, &KnownMarketplaceEntry)> =
+        known.iter().filter(|(name, _)| !locked.contains_key(*name)).collect();
+    others.sort_by(|a, b| a.0.cmp(b.0));
+    let marketplaces_dir = cache.cache_dir().join("marketplaces");
+    for (name, entry) in others {
+        let location = Path::new(&entry.install_location);
+        if !location.exists() {
+            let hint = if location.starts_with(&marketplaces_dir) {
+                "No lock uses it. Run `skill-manager install --prune` to unregister it."
+            } else {
+                "No lock uses it, and skill-manager did not register it. Remove it through Claude Code's /plugin UI."
+            };
+            findings.push(Finding::new(
+                Severity::Warning,
+                format!("marketplace '{}' is registered at {}, which does not exist", name, entry.install_location),
+                hint.to_string(),
+            ));
+        }
+    }
+}
+
+/// Describe a scope for messages: `user scope` or `project <path>`.
+fn describe_scope(scope: &PluginScope) -> String {
+    match scope {
+        PluginScope::User => "user scope".to_string(),
+        PluginScope::Project(path) => format!("project {}", path.display()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::{LockedMarketplace, LockedPackage, SourceType};
+    use crate::installer::content_hash;
+    use std::fs;
+
+    #[test]
+    fn test_diagnose_and_fix() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let project_dir = temp_dir.path().join("project");
+        fs::create_dir_all(&project_dir).unwrap();
+        let manifest_path = project_dir.join("plugins.toml");
+        fs::write(
+            &manifest_path,
+            "[marketplaces]\ndev = \"owner/dev\"\n\n[plugins]\nfoo = { marketplace = \"dev\" }\n",
+        )
+        .unwrap();
+        let manifest = Manifest::load(&manifest_path).unwrap();
+
+        let cache = CacheManager::with_cache_dir(temp_dir.path().join("cache"));
+        let resolver = MarketplaceResolver::new(cache.cache_dir().to_path_buf());
+        let claude = ClaudeCodeIntegration::with_claude_dir(temp_dir.path().join("claude"));
+
+        // The plugin and its marketplace are in the cache, but Claude Code knows nothing
+        let commit = "a".repeat(40);
+        let plugin_path = cache.plugin_path("dev", "foo", &commit);
+        fs::create_dir_all(plugin_path.join(".claude-plugin")).unwrap();
+        fs::write(plugin_path.join(".claude-plugin/plugin.json"), r#"{"name": "foo"}"#).unwrap();
+        fs::create_dir_all(resolver.marketplace_path("dev")).unwrap();
+
+        let lock = LockFile {
+            config_hash: Some(manifest.compute_hash()),
+            marketplaces: vec![LockedMarketplace {
+                name: "dev".to_string(),
+                url: "https://github.com/owner/dev.git".to_string(),
+                path: None,
+                commit: commit.clone(),
+                branch: None,
+                fingerprint: Some(manifest.marketplaces["dev"].fingerprint()),
+            }],
+            packages: vec![LockedPackage {
+                name: "foo".to_string(),
+                marketplace: "dev".to_string(),
+                source_type: SourceType::Local,
+                marketplace_commit: commit.clone(),
+                plugin_commit: commit.clone(),
+                resolved_version: "1.0.0".to_string(),
+                version_req: None,
+                tag: None,
+                branch: None,
+                git: None,
+                subdir: None,
+                content_hash: Some(content_hash(&plugin_path).unwrap()),
+                fingerprint: Some(manifest.plugins["foo"].fingerprint()),
+                dependencies: Vec::new(),
+                requirements: BTreeMap::new(),
+                direct: true,
+            }],
+            path: None,
+        };
+        lock.save(&LockFile::path_for_manifest(&manifest_path)).unwrap();
+
+        let targets = vec![Target {
+            resolver: resolver.for_manifest(&manifest),
+            manifest,
+            scope: PluginScope::Project(project_dir.clone()),
+            skipped: HashSet::new(),
+        }];
+
+        let findings = diagnose(&targets, &cache, &claude).unwrap();
+        let messages: Vec<&str> = findings.iter().map(|f| f.message.as_str()).collect();
+        assert_eq!(findings.len(), 3, "{:?}", messages);
+        assert!(findings.iter().all(|f| f.severity == Severity::Error && f.fix.is_some()));
+        assert!(messages[0].contains("not in installed_plugins.json"));
+        assert!(messages[1].contains("not enabled"));
+        assert!(messages[2].contains("not in known_marketplaces.json"));
+
+        for finding in &findings {
+            finding.fix.as_ref().unwrap().apply(&claude).unwrap();
+        }
+        let findings = diagnose(&targets, &cache, &claude).unwrap();
+        assert!(findings.is_empty(), "{:?}", findings);
+
+        // A modified cached copy cannot be fixed in place
+        fs::write(plugin_path.join("extra.md"), "changed").unwrap();
+        let findings = diagnose(&targets, &cache, &claude).unwrap();
+        assert_eq!(findings.len(), 1);
+        assert!(findings[0].message.contains("does not match plugins.lock"));
+        assert!(findings[0].fix.is_none());
+
+        // A plugin that is no longer locked and whose files are gone is dropped
+        fs::remove_file(plugin_path.join("extra.md")).unwrap();
+        let gone = cache.plugin_path("dev", "old", &commit);
+        claude
+            .add_installed_plugin("old", "dev", &gone, "0.1.0", &commit, &targets[0].scope)
+            .unwrap();
+        let findings = diagnose(&targets, &cache, &claude).unwrap();
+        assert_eq!(findings.len(), 1);
+        assert_eq!(findings[0].severity, Severity::Warning);
+        findings[0].fix.as_ref().unwrap().apply(&claude).unwrap();
+        assert!(diagnose(&targets, &cache, &claude).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,274 @@
+use std::collections::{BTreeMap, HashMap};
+use std::path::{Path, PathBuf};
+
+use git2::Repository;
+use serde_json::Value;
+use toml_edit::{DocumentMut, Item, Table};
+
+use super::add::set_plugin_entry;
+use crate::config::PluginEntry;
+use crate::installer::{ClaudeCodeIntegration, InstalledPluginEntry, KnownMarketplaceEntry, PluginScope};
+use crate::{Error, Result};
+
+/// Header comment for exported manifests.
+const EXPORT_HEADER: &str = "# skill-manager plugins manifest\n\
+                             # Exported from Claude Code by `skill-manager export`\n\n";
+
+/// Generate a plugins.toml from the plugins currently installed in Claude Code.
+pub fn run(global: bool, project: Option<PathBuf>, output: Option<PathBuf>) -> Result<()> {
+    let scope = if global {
+        PluginScope::User
+    } else {
+        let path = match project {
+            Some(path) => path,
+            None => std::env::current_dir().map_err(|e| Error::FileRead {
+                path: PathBuf::from("."),
+                source: e,
+            })?,
+        };
+        PluginScope::Project(path)
+    };
+
+    let claude = ClaudeCodeIntegration::new();
+    let installed = claude.installed_plugins_in_scope(&scope)?;
+    let known = claude.read_known_marketplaces()?;
+
+    let exported = build_manifest(&installed, &known);
+    for warning in &exported.warnings {
+        eprintln!("Warning: {}", warning);
+    }
+
+    let content = format!("{}{}", EXPORT_HEADER, exported.doc);
+
+    match output {
+        Some(path) => {
+            if path.exists() {
+                return Err(Error::ManifestExists(path));
+            }
+            std::fs::write(&path, content).map_err(|e| Error::FileWrite {
+                path: path.clone(),
+                source: e,
+            })?;
+            eprintln!("Exported {} plugin(s) to {}", exported.plugin_count, path.display());
+        }
+        None => print!("{}", content),
+    }
+
+    Ok(())
+}
+
+/// A manifest document generated from Claude Code's state.
+struct ExportedManifest {
+    doc: DocumentMut,
+    plugin_count: usize,
+    /// Plugins and marketplaces that could not be exported, and why.
+    warnings: Vec<String>,
+}
+
+/// Build a manifest document from installed plugin entries and known marketplaces.
+///
+/// Plugins are pinned to their installed commit. Plugins whose marketplace
+/// cannot be mapped back to a URL are left out with a warning.
+fn build_manifest(
+    installed: &[(String, InstalledPluginEntry)],
+    known: &HashMap<String, KnownMarketplaceEntry>,
+) -> ExportedManifest {
+    let mut warnings = Vec::new();
+    let mut marketplaces: BTreeMap<String, String> = BTreeMap::new();
+    let mut plugins: BTreeMap<String, PluginEntry> = BTreeMap::new();
+
+    for (key, entry) in installed {
+        let Some((name, marketplace)) = key.rsplit_once('@') else {
+            warnings.push(format!("skipping '{}': not in <plugin>@<marketplace> form", key));
+            continue;
+        };
+
+        if !marketplaces.contains_key(marketplace) {
+            let url = known
+                .get(marketplace)
+                .and_then(|m| marketplace_url(&m.source));
+            match url {
+                Some(url) => {
+                    marketplaces.insert(marketplace.to_string(), url);
+                }
+                None => {
+                    warnings.push(format!(
+                        "skipping '{}': marketplace '{}' has no exportable source",
+                        key, marketplace
+                    ));
+                    continue;
+                }
+            }
+        }
+
+        if let Some(existing) = plugins.get(name) {
+            warnings.push(format!(
+                "skipping '{}': '{}' is already exported from marketplace '{}'",
+                key, name, existing.marketplace
+            ));
+            continue;
+        }
+
+        let commit = (!entry.git_commit_sha.is_empty()).then(|| entry.git_commit_sha.clone());
+        plugins.insert(
+            name.to_string(),
+            PluginEntry {
+                marketplace: marketplace.to_string(),
+                commit,
+                ..PluginEntry::default()
+            },
+        );
+    }
+
+    // Only declare marketplaces that an exported plugin uses
+    marketplaces.retain(|name, _| plugins.values().any(|p| &p.marketplace == name));
+
+    let mut doc = DocumentMut::new();
+    let mut marketplaces_table = Table::new();
+    for (name, url) in &marketplaces {
+        marketplaces_table.insert(name, toml_edit::value(url.as_str()));
+    }
+    doc["marketplaces"] = Item::Table(marketplaces_table);
+    doc["plugins"] = Item::Table(Table::new());
+    for (name, entry) in &plugins {
+        set_plugin_entry(&mut doc, name, entry);
+    }
+
+    ExportedManifest {
+        doc,
+        plugin_count: plugins.len(),
+        warnings,
+    }
+}
+
+/// Map a known_marketplaces.json source back to a manifest URL.
+///
+/// - `github` sources become `owner/repo` shorthand
+/// - `git` sources use their URL
+/// - `directory` sources use the `origin` remote of the git repository at the path
+fn marketplace_url(source: &Value) -> Option<String> {
+    match source.get("source")?.as_str()? {
+        "github" => source.get("repo")?.as_str().map(str::to_string),
+        "git" => source.get("url")?.as_str().map(str::to_string),
+        "directory" => {
+            let path = source.get("path")?.as_str()?;
+            origin_url(Path::new(path))
+        }
+        _ => None,
+    }
+}
+
+/// Read the `origin` remote URL of a git repository.
+fn origin_url(path: &Path) -> Option<String> {
+    let repo = Repository::open(path).ok()?;
+    let remote = repo.find_remote("origin").ok()?;
+    remote.url().map(str::to_string)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::Manifest;
+    use serde_json::json;
+
+    fn installed(key: &str, commit: &str) -> (String, InstalledPluginEntry) {
+        (
+            key.to_string(),
+            InstalledPluginEntry {
+                scope: "user".to_string(),
+                project_path: None,
+                install_path: "/path/to/plugin".to_string(),
+                version: "1.0.0".to_string(),
+                installed_at: "2026-01-01T00:00:00Z".to_string(),
+                last_updated: "2026-01-01T00:00:00Z".to_string(),
+                git_commit_sha: commit.to_string(),
+            },
+        )
+    }
+
+    fn known(source: Value) -> KnownMarketplaceEntry {
+        KnownMarketplaceEntry {
+            source,
+            install_location: "/path/to/marketplace".to_string(),
+            last_updated: "2026-01-01T00:00:00Z".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_marketplace_url_from_sources() {
+        assert_eq!(
+            marketplace_url(&json!({"source": "github", "repo": "anthropics/claude-plugins-official"})),
+            Some("anthropics/claude-plugins-official".to_string())
+        );
+        assert_eq!(
+            marketplace_url(&json!({"source": "git", "url": "git@github.com:mycompany/plugins.git"})),
+            Some("git@github.com:mycompany/plugins.git".to_string())
+        );
+        assert_eq!(marketplace_url(&json!({"source": "unknown"})), None);
+    }
+
+    #[test]
+    fn test_marketplace_url_from_directory_uses_origin() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(temp_dir.path()).unwrap();
+        repo.remote("origin", "https://git.example.com/plugins.git").unwrap();
+
+        let source = json!({"source": "directory", "path": temp_dir.path().to_string_lossy()});
+        assert_eq!(
+            marketplace_url(&source),
+            Some("https://git.example.com/plugins.git".to_string())
+        );
+
+        let plain_dir = tempfile::tempdir().unwrap();
+        let source = json!({"source": "directory", "path": plain_dir.path().to_string_lossy()});
+        assert_eq!(marketplace_url(&source), None);
+    }
+
+    #[test]
+    fn test_build_manifest() {
+        let installed = vec![
+            installed("superpowers@official", "abc123"),
+            installed("typescript-lsp@official", "def456"),
+            installed("orphan@missing", "fed789"),
+        ];
+        let known = HashMap::from([
+            (
+                "official".to_string(),
+                known(json!({"source": "github", "repo": "anthropics/claude-plugins-official"})),
+            ),
+            ("unused".to_string(), known(json!({"source": "github", "repo": "owner/unused"}))),
+        ]);
+
+        let exported = build_manifest(&installed, &known);
+        assert_eq!(exported.plugin_count, 2);
+        assert_eq!(exported.warnings.len(), 1);
+        assert!(exported.warnings[0].contains("orphan@missing"));
+
+        let manifest = Manifest::parse(&exported.doc.to_string()).unwrap();
+        assert!(manifest.validate().is_ok());
+        assert_eq!(manifest.marketplaces.len(), 1);
+        assert_eq!(
+            manifest.marketplaces["official"].url,
+            "https://github.com/anthropics/claude-plugins-official.git"
+        );
+        assert_eq!(manifest.plugins["superpowers"].commit, Some("abc123".to_string()));
+        assert_eq!(manifest.plugins["typescript-lsp"].commit, Some("def456".to_string()));
+    }
+
+    #[test]
+    fn test_build_manifest_skips_duplicate_names() {
+        let installed = vec![installed("foo@alpha", "abc123"), installed("foo@beta", "def456")];
+        let known = HashMap::from([
+            ("alpha".to_string(), known(json!({"source": "github", "repo": "owner/alpha"}))),
+            ("beta".to_string(), known(json!({"source": "github", "repo": "owner/beta"}))),
+        ]);
+
+        let exported = build_manifest(&installed, &known);
+        assert_eq!(exported.plugin_count, 1);
+        assert_eq!(exported.warnings.len(), 1);
+
+        let manifest = Manifest::parse(&exported.doc.to_string()).unwrap();
+        assert_eq!(manifest.plugins["foo"].marketplace, "alpha");
+        assert_eq!(manifest.marketplaces.len(), 1);
+    }
+}
@@ -1,30 +1,171 @@
-use crate::config::{LockFile, LockedMarketplace, LockedPackage, Manifest, SourceType};
-use crate::installer::{CacheManager, ClaudeCodeIntegration, PluginScope};
-use crate::resolver::{MarketplaceResolver, PluginSource};
+use super::conflict::{self, ConflictChoice};
+use super::dependencies::{self, PendingDependencies};
+use super::fetch_policy;
+use crate::config::{
+    LockFile, LockedMarketplace, LockedPackage, Manifest, MarketplaceEntry, PluginEntry, SourceType, GIT_MARKETPLACE,
+};
+use crate::installer::{
+    content_hash, CacheManager, ClaudeCodeIntegration, ExtractedPlugin, GitPluginSource, PluginScope, Recovered,
+    Transaction,
+};
+use crate::resolver::{
+    collect_results, map_parallel, FetchTarget, MarketplaceResolver, PluginDependency, PluginSource, ResolvedPlugin,
+};
 use crate::{Error, Result};
-use std::path::Path;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
 
-/// Determine the PluginScope from the manifest path.
-/// Global manifest (~/.config/skill-manager/plugins.toml) -> User scope
-/// Project manifest (./.claude/plugins.toml) -> Project scope with canonicalized cwd
-fn scope_from_manifest_path(manifest_path: &Path) -> Result<PluginScope> {
-    // Check if it's the global manifest by comparing with the expected global path
-    if let Some(global_path) = Manifest::global_path() {
-        if manifest_path == global_path {
-            return Ok(PluginScope::User);
+/// A manifest paired with the scope it installs into and its resolved lock.
+struct ManifestPlan {
+    manifest: Manifest,
+    scope: PluginScope,
+    lock_path: PathBuf,
+    lock: LockFile,
+    /// Whether the lock changed and must be written back.
+    lock_changed: bool,
+    /// Plugins that lost a global/project conflict and are not installed for this scope.
+    skipped: HashSet<String>,
+    /// Resolves this manifest's entries; see [`MarketplaceResolver::for_manifest`].
+    resolver: MarketplaceResolver,
+}
+
+/// How strictly install follows the existing lock files.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum LockMode {
+    /// Re-resolve changed manifest entries and write the lock files.
+    #[default]
+    Resolve,
+    /// Fail instead of changing a lock file.
+    Locked,
+    /// Like `Locked`, and install only from the cache without fetching.
+    Frozen,
+}
+
+impl LockMode {
+    /// Map the `--locked` / `--frozen` flags to a mode.
+    pub fn from_flags(locked: bool, frozen: bool) -> Self {
+        if frozen {
+            Self::Frozen
+        } else if locked {
+            Self::Locked
+        } else {
+            Self::Resolve
         }
     }
 
-    // It's a project manifest - use the current working directory as the project path
+    /// Whether lock files must be used as they are.
+    fn is_locked(self) -> bool {
+        self != Self::Resolve
+    }
+}
+
+/// Determine the PluginScope for the project manifest.
+/// Project manifest (./.claude/plugins.toml) -> Project scope with the current directory
+pub(super) fn project_scope() -> Result<PluginScope> {
     let cwd = std::env::current_dir().map_err(|e| Error::FileRead {
-        path: std::path::PathBuf::from("."),
+        path: PathBuf::from("."),
         source: e,
     })?;
     Ok(PluginScope::Project(cwd))
 }
 
 /// Install plugins from the manifest.
-pub fn run(update: bool, _prefer_global: bool, _prefer_project: bool) -> Result<()> {
+///
+/// With `prune`, plugins and marketplaces that skill-manager registered with
+/// Claude Code but that are no longer locked are removed afterwards.
+///
+/// Repositories are fetched on up to `jobs` threads, by default one per core.
+/// With `offline`, only cached clones are used.
+///
+/// Claude Code's files, the lock files and the global manifest are
+/// snapshotted first and restored if the install fails, so it either
+/// completes or changes nothing. An earlier install that was interrupted is
+/// rolled back first. Only one install runs at a time.
+pub fn run(
+    update: bool,
+    prefer_global: bool,
+    prefer_project: bool,
+    prune: bool,
+    mode: LockMode,
+    jobs: Option<usize>,
+    offline: bool,
+) -> Result<()> {
+    let cache = CacheManager::new()?;
+    cache.ensure_cache_dir()?;
+    let claude = ClaudeCodeIntegration::new();
+
+    if let Some(recovered) = Transaction::recover(cache.cache_dir())? {
+        print_recovered(&recovered);
+    }
+    let mut transaction = Transaction::begin(cache.cache_dir(), &transaction_paths(&claude))?;
+
+    let installed = install(
+        update,
+        prefer_global,
+        prefer_project,
+        prune,
+        mode,
+        jobs,
+        offline,
+        &cache,
+        &claude,
+        &mut transaction,
+    );
+    match installed {
+        Ok(()) => transaction.commit(),
+        Err(e) => {
+            match transaction.rollback() {
+                Ok(0) => {}
+                Ok(restored) => println!("Install failed; restored {} file(s) it had changed", restored),
+                Err(rollback_err) => eprintln!("Warning: could not restore files changed by the install: {}", rollback_err),
+            }
+            Err(e)
+        }
+    }
+}
+
+/// Report an interrupted install that [`Transaction::recover`] rolled back.
+fn print_recovered(recovered: &Recovered) {
+    println!("Rolled back an interrupted install started at {}", recovered.started);
+    for path in &recovered.skipped {
+        eprintln!(
+            "Warning: {} was changed after the interrupted install wrote it; left it as it is",
+            path.display()
+        );
+    }
+}
+
+/// Files an install may change: Claude Code's settings, installed plugins
+/// and known marketplaces, both lock files, and the global manifest, which
+/// resolving a conflict can rewrite.
+pub(super) fn transaction_paths(claude: &ClaudeCodeIntegration) -> Vec<PathBuf> {
+    let mut paths = vec![
+        claude.settings_path(),
+        claude.installed_plugins_path(),
+        claude.known_marketplaces_path(),
+    ];
+    if let Some(global) = Manifest::global_path() {
+        paths.push(LockFile::path_for_manifest(&global));
+        paths.push(global);
+    }
+    paths.push(LockFile::path_for_manifest(&Manifest::project_path()));
+    paths
+}
+
+/// Resolve the manifests and apply them to Claude Code; see [`run`].
+#[allow(clippy::too_many_arguments)]
+fn install(
+    update: bool,
+    prefer_global: bool,
+    prefer_project: bool,
+    prune: bool,
+    mode: LockMode,
+    jobs: Option<usize>,
+    offline: bool,
+    cache: &CacheManager,
+    claude: &ClaudeCodeIntegration,
+    transaction: &mut Transaction,
+) -> Result<()> {
     // Load manifests
     let global_manifest = Manifest::load_global()?;
     let project_manifest = Manifest::load_project()?;
@@ -33,22 +174,73 @@ pub fn run(update: bool, _prefer_global: bool, _prefer_project: bool) -> Result<
         return Err(Error::NoManifest);
     }
 
-    // For MVP, we'll just handle whichever manifest exists
-    // TODO: Merge manifests and handle conflicts
-    let manifest = project_manifest
-        .or(global_manifest)
-        .ok_or(Error::NoManifest)?;
+    // Initialize components
+    let policy = fetch_policy(offline, project_manifest.iter().chain(&global_manifest));
+    let mut resolver = MarketplaceResolver::new(cache.cache_dir().to_path_buf()).with_fetch_policy(policy);
+    if let Some(jobs) = jobs {
+        resolver = resolver.with_jobs(jobs);
+    }
 
-    let manifest_path = manifest.path.clone().ok_or(Error::NoManifest)?;
-    let scope = scope_from_manifest_path(&manifest_path)?;
-    manifest.validate()?;
+    // Resolve each manifest into its own lock, global first
+    let mut global_plan = match global_manifest {
+        Some(manifest) => Some(plan_manifest(manifest, PluginScope::User, update, mode, &resolver)?),
+        None => None,
+    };
+    let mut project_plan = match project_manifest {
+        Some(manifest) => Some(plan_manifest(manifest, project_scope()?, update, mode, &resolver)?),
+        None => None,
+    };
 
-    // Initialize components
-    let cache = CacheManager::new()?;
-    cache.ensure_cache_dir()?;
+    if let (Some(global), Some(project)) = (global_plan.as_mut(), project_plan.as_mut()) {
+        let preferred = ConflictChoice::from_flags(prefer_global, prefer_project);
+        handle_conflicts(global, project, preferred, update, mode, &resolver)?;
+        transaction.checkpoint()?;
+        warn_marketplace_clashes(global, project);
+    }
 
-    let resolver = MarketplaceResolver::new(cache.cache_dir().to_path_buf());
-    let claude = ClaudeCodeIntegration::new();
+    let mut plans: Vec<ManifestPlan> = [global_plan, project_plan].into_iter().flatten().collect();
+
+    register_git_marketplace(&plans, cache, claude)?;
+    transaction.checkpoint()?;
+
+    let mut installed_count = 0;
+    for plan in &mut plans {
+        installed_count += install_plan(plan, cache, claude, mode, transaction)?;
+    }
+
+    println!("\nInstalled {} plugin(s)", installed_count);
+
+    if prune {
+        let mut removed_count = 0;
+        for plan in &plans {
+            removed_count += prune_plan(plan, cache, claude)?;
+        }
+        let unregistered_count = prune_marketplaces(&plans, &resolver, claude)?;
+        transaction.checkpoint()?;
+        println!(
+            "Removed {} plugin(s) and {} marketplace(s) no longer in the manifest",
+            removed_count, unregistered_count
+        );
+    }
+
+    Ok(())
+}
+
+/// Validate a manifest and resolve its lock, reusing the existing lock when up to date.
+/// The manifest gets its own resolver, sharing the work `resolver` has done.
+///
+/// In locked modes a missing or outdated lock is an error instead.
+fn plan_manifest(
+    manifest: Manifest,
+    scope: PluginScope,
+    update: bool,
+    mode: LockMode,
+    resolver: &MarketplaceResolver,
+) -> Result<ManifestPlan> {
+    let manifest_path = manifest.path.clone().ok_or(Error::NoManifest)?;
+    manifest.validate()?;
+    let plan_resolver = resolver.for_manifest(&manifest);
+    let resolver = &plan_resolver;
 
     // Compute manifest hash for change detection
     let current_hash = manifest.compute_hash();
@@ -61,188 +253,1304 @@ pub fn run(update: bool, _prefer_global: bool, _prefer_project: bool) -> Result<
         None
     };
 
-    // Determine if we need to re-resolve based on hash comparison
-    let needs_resolve = update
-        || existing_lock.is_none()
-        || existing_lock
-            .as_ref()
-            .is_some_and(|lock| lock.config_hash.as_ref() != Some(&current_hash));
-
-    // Resolve or use locked versions
-    let (locked_marketplaces, locked_packages) = if !needs_resolve {
-        let lock = existing_lock.as_ref().unwrap();
-        println!("Using locked versions from {}", lock_path.display());
-        (lock.marketplaces.clone(), lock.packages.clone())
-    } else {
-        if existing_lock.is_some() && !update {
-            println!("Config changed, re-resolving plugin versions...");
-        } else {
-            println!("Resolving plugin versions...");
+    // Directory marketplaces are re-resolved whenever their contents changed
+    let changed_dirs = match existing_lock {
+        Some(ref lock) => changed_directories(lock, resolver),
+        None => Vec::new(),
+    };
+
+    // Re-resolve only the entries whose fingerprint changed since the lock was written
+    let (locked_marketplaces, locked_packages, lock_changed) = match existing_lock {
+        Some(lock) if lock.config_hash.as_ref() == Some(&current_hash) && changed_dirs.is_empty() => {
+            println!("Using locked versions from {}", lock_path.display());
+            (lock.marketplaces, lock.packages, false)
+        }
+        Some(mut lock) if lock.config_hash == Some(manifest.compute_legacy_hash()) && changed_dirs.is_empty() => {
+            // Written before fingerprints existed, but still up to date
+            println!("Using locked versions from {}", lock_path.display());
+            if mode.is_locked() {
+                (lock.marketplaces, lock.packages, false)
+            } else {
+                record_fingerprints(&manifest, &mut lock);
+                (lock.marketplaces, lock.packages, true)
+            }
+        }
+        Some(lock) if mode.is_locked() => {
+            let mut changes = lock_diff(&manifest, &lock);
+            changes.extend(
+                changed_dirs
+                    .iter()
+                    .map(|name| format!("~ marketplace '{}': directory contents changed", name)),
+            );
+            return Err(Error::LockOutdated {
+                path: lock_path,
+                changes: changes.join("\n"),
+            });
+        }
+        None if mode.is_locked() => return Err(Error::LockNotFound(lock_path)),
+        Some(lock) => {
+            if lock.config_hash.as_ref() == Some(&current_hash) {
+                println!(
+                    "Marketplace directories changed ({}), re-resolving them for {}...",
+                    changed_dirs.join(", "),
+                    manifest_path.display()
+                );
+            } else {
+                println!("Config changed, re-resolving changed entries for {}...", manifest_path.display());
+            }
+            let (marketplaces, packages) = resolve_all(&manifest, Some(&lock), resolver)?;
+            (marketplaces, packages, true)
+        }
+        None => {
+            println!("Resolving plugin versions for {}...", manifest_path.display());
+            let (marketplaces, packages) = resolve_all(&manifest, None, resolver)?;
+            (marketplaces, packages, true)
         }
-        resolve_all(&manifest, &resolver)?
     };
 
     // Create/update lock file with current hash
-    let lock_file = LockFile {
+    let lock = LockFile {
         config_hash: Some(current_hash),
-        marketplaces: locked_marketplaces.clone(),
-        packages: locked_packages.clone(),
+        marketplaces: locked_marketplaces,
+        packages: locked_packages,
         path: Some(lock_path.clone()),
     };
 
-    if needs_resolve {
-        lock_file.save(&lock_path)?;
-        println!("Wrote {}", lock_path.display());
+    Ok(ManifestPlan {
+        manifest,
+        scope,
+        lock_path,
+        lock,
+        lock_changed,
+        skipped: HashSet::new(),
+        resolver: plan_resolver,
+    })
+}
+
+/// Warn about marketplaces both manifests declare differently. Claude Code
+/// knows a marketplace by its name only, so the one registered last, the
+/// project's, is the one its `/plugin` UI shows; plugins are installed from
+/// the marketplace their own manifest declares either way.
+fn warn_marketplace_clashes(global: &ManifestPlan, project: &ManifestPlan) {
+    for marketplace in &project.lock.marketplaces {
+        let clashes = global
+            .lock
+            .find_marketplace(&marketplace.name)
+            .is_some_and(|other| other.url != marketplace.url || other.path != marketplace.path);
+        if clashes {
+            eprintln!(
+                "Warning: marketplace '{}' is declared differently by the global and the project manifest; \
+                 Claude Code is pointed at the project's",
+                marketplace.name
+            );
+        }
+    }
+}
+
+/// Detect plugins declared differently in both manifests and settle them.
+///
+/// Without a preferred choice from the command line, the user is prompted for
+/// each conflict. All choices are collected before anything is written, so
+/// aborting leaves every file untouched.
+fn handle_conflicts(
+    global: &mut ManifestPlan,
+    project: &mut ManifestPlan,
+    preferred: Option<ConflictChoice>,
+    update: bool,
+    mode: LockMode,
+    resolver: &MarketplaceResolver,
+) -> Result<()> {
+    let conflicts = conflict::detect_conflicts(&global.manifest, &project.manifest);
+    if conflicts.is_empty() {
+        return Ok(());
     }
 
+    let mut choices = Vec::with_capacity(conflicts.len());
+    for c in &conflicts {
+        c.print(&global.lock, &project.lock);
+
+        let choice = match preferred {
+            Some(choice) => choice,
+            None => ConflictChoice::prompt(c)?,
+        };
+        if choice == ConflictChoice::Abort {
+            return Err(Error::Aborted);
+        }
+        if choice == ConflictChoice::UpdateGlobal && mode.is_locked() {
+            // Updating the global manifest would also change its lock
+            return Err(Error::LockOutdated {
+                path: global.lock_path.clone(),
+                changes: format!("~ plugin '{}': would change to the project version", c.plugin),
+            });
+        }
+        choices.push(choice);
+    }
+
+    let global_path = global.manifest.path.clone().ok_or(Error::NoManifest)?;
+    let mut global_changed = false;
+    for (c, choice) in conflicts.iter().zip(choices) {
+        match choice {
+            ConflictChoice::Skip => {
+                println!("  Keeping global {}; the project-specified version will not be used.", c.plugin);
+                project.skipped.insert(c.plugin.clone());
+            }
+            ConflictChoice::UpdateGlobal => {
+                conflict::update_global_manifest(&global_path, &project.manifest, &c.plugin)?;
+                println!("  Updated {} in {}", c.plugin, global_path.display());
+                global_changed = true;
+            }
+            ConflictChoice::Abort => unreachable!("abort returns before applying choices"),
+        }
+    }
+
+    if global_changed {
+        println!("  Claude Code may need a restart to pick up the updated global plugins.");
+        let manifest = Manifest::load(&global_path)?;
+        *global = plan_manifest(manifest, PluginScope::User, update, mode, resolver)?;
+    }
+
+    Ok(())
+}
+
+/// Write the plan's lock if needed and install its packages into Claude Code.
+/// The lock is recorded in the cache so `cache gc` keeps what it references.
+/// Returns the number of installed plugins. The transaction is checkpointed
+/// after each step that changes Claude Code's files.
+///
+/// In locked modes the lock is never written, so content hashes missing from
+/// it are not recorded.
+fn install_plan(
+    plan: &mut ManifestPlan,
+    cache: &CacheManager,
+    claude: &ClaudeCodeIntegration,
+    mode: LockMode,
+    transaction: &mut Transaction,
+) -> Result<usize> {
+    let resolver = &plan.resolver;
     // Register marketplaces with Claude Code
-    for marketplace in &locked_marketplaces {
+    for marketplace in &plan.lock.marketplaces {
         let marketplace_path = resolver.marketplace_path(&marketplace.name);
+        if mode == LockMode::Frozen && !marketplace_path.exists() {
+            return Err(Error::NotInCache(format!("marketplace '{}'", marketplace.name)));
+        }
+        resolver.checkout_marketplace(&marketplace.name, &marketplace.url, &marketplace.commit)?;
         claude.register_marketplace(&marketplace.name, &marketplace_path)?;
     }
+    transaction.checkpoint()?;
 
-    // Install plugins
+    if mode != LockMode::Frozen {
+        prefetch_packages(plan, cache, resolver)?;
+    }
+
+    // Install plugins, recording content hashes missing from the lock
     let mut installed_count = 0;
-    for pkg in &locked_packages {
-        let marketplace = locked_marketplaces
-            .iter()
-            .find(|m| m.name == pkg.marketplace)
-            .ok_or_else(|| Error::UndeclaredMarketplace(pkg.marketplace.clone()))?;
+    let mut recorded_hashes = false;
+    for index in 0..plan.lock.packages.len() {
+        let pkg = &plan.lock.packages[index];
+        if plan.skipped.contains(&pkg.name) {
+            println!("Skipping {} (global version takes precedence)", pkg.name);
+            continue;
+        }
+
+        let marketplace = plan.lock.find_marketplace(&pkg.marketplace);
+        if marketplace.is_none() && pkg.git.is_none() {
+            return Err(Error::UndeclaredMarketplace(pkg.marketplace.clone()));
+        }
 
         println!("Installing {}...", pkg.name);
+        let content_hash = install_package(
+            pkg,
+            marketplace,
+            &plan.scope,
+            cache,
+            resolver,
+            claude,
+            mode == LockMode::Frozen,
+        )?;
+        transaction.checkpoint()?;
+        installed_count += 1;
 
-        // Extract plugin to cache
-        let install_path = match pkg.source_type {
-            SourceType::Local => {
-                let marketplace_path = resolver.marketplace_path(&pkg.marketplace);
-
-                // Get the source path from the marketplace.json
-                let repo = resolver.ensure_marketplace(&pkg.marketplace, &marketplace.url)?;
-                resolver.checkout_commit(&repo, &pkg.marketplace, &pkg.marketplace_commit)?;
-                let mkt_json = resolver.parse_marketplace_json(&repo, &pkg.marketplace)?;
-                let plugin_info = resolver.find_plugin(&mkt_json, &pkg.marketplace, &pkg.name)?;
-
-                let source_path = match &plugin_info.source {
-                    PluginSource::Local(path) => path,
-                    PluginSource::External { .. } => {
-                        return Err(Error::PluginNotFound {
-                            plugin: pkg.name.clone(),
-                            marketplace: pkg.marketplace.clone(),
-                        });
-                    }
-                };
-
-                cache.extract_local_plugin(
-                    &marketplace_path,
-                    source_path,
-                    &pkg.marketplace,
-                    &pkg.name,
-                    &pkg.plugin_commit,
-                )?
+        let pkg = &mut plan.lock.packages[index];
+        if pkg.content_hash.is_none() {
+            pkg.content_hash = Some(content_hash);
+            recorded_hashes = true;
+        }
+    }
+
+    if plan.lock_changed || (recorded_hashes && !mode.is_locked()) {
+        plan.lock.save(&plan.lock_path)?;
+        transaction.checkpoint()?;
+        println!("Wrote {}", plan.lock_path.display());
+    }
+    cache.record_lock(&plan.lock_path)?;
+
+    Ok(installed_count)
+}
+
+/// Generate and register the marketplace that lists git plugins, so Claude
+/// Code can key them as `name@skill-manager-git`.
+///
+/// Every scope shares the marketplace, so it lists the git plugins of the
+/// plans and of every other lock installs have used, each at its locked
+/// commit. Claude Code keys plugins by name only: a name that locks pin
+/// differently is listed as the first of them has it, the global plan
+/// first, then the project plan, then other projects. Without any git
+/// plugin left, the marketplace is unregistered and removed, unless Claude
+/// Code still has plugins installed from it.
+fn register_git_marketplace(plans: &[ManifestPlan], cache: &CacheManager, claude: &ClaudeCodeIntegration) -> Result<()> {
+    let plan_locks: Vec<PathBuf> = plans
+        .iter()
+        .map(|plan| std::path::absolute(&plan.lock_path).unwrap_or_else(|_| plan.lock_path.clone()))
+        .collect();
+    let mut other_locks = Vec::new();
+    for lock_path in cache.known_locks()? {
+        if plan_locks.contains(&lock_path) {
+            continue;
+        }
+        match LockFile::load(&lock_path) {
+            Ok(lock) => other_locks.push((lock_path, lock)),
+            Err(e) => eprintln!("Warning: ignoring {}: {}", lock_path.display(), e),
+        }
+    }
+
+    let plan_packages = plans.iter().zip(&plan_locks).flat_map(|(plan, lock_path)| {
+        plan.lock
+            .packages
+            .iter()
+            .filter(|pkg| !plan.skipped.contains(&pkg.name))
+            .map(move |pkg| (lock_path, pkg))
+    });
+    let other_packages = other_locks
+        .iter()
+        .flat_map(|(lock_path, lock)| lock.packages.iter().map(move |pkg| (lock_path, pkg)));
+
+    let mut plugins: BTreeMap<&str, (GitPluginSource, &PathBuf)> = BTreeMap::new();
+    for (lock_path, pkg) in plan_packages.chain(other_packages) {
+        let Some(ref url) = pkg.git else {
+            continue;
+        };
+        let source = GitPluginSource {
+            url,
+            subdir: pkg.subdir.as_deref(),
+            commit: &pkg.plugin_commit,
+        };
+        match plugins.get(pkg.name.as_str()) {
+            None => {
+                plugins.insert(&pkg.name, (source, lock_path));
             }
-            SourceType::External => {
-                // For external plugins, the repo is already cloned during resolution
-                let plugin_repo_path = cache
-                    .cache_dir()
-                    .join("plugin-repos")
-                    .join(&pkg.marketplace)
-                    .join(&pkg.name);
-
-                cache.extract_external_plugin(
-                    &plugin_repo_path,
-                    &pkg.marketplace,
+            Some((listed, listed_by)) if *listed != source => eprintln!(
+                "Warning: git plugin '{}' is locked differently by {} and {}; \
+                 Claude Code's {} marketplace lists the one from {}",
+                pkg.name,
+                listed_by.display(),
+                lock_path.display(),
+                GIT_MARKETPLACE,
+                listed_by.display()
+            ),
+            Some(_) => {}
+        }
+    }
+
+    if plugins.is_empty() {
+        let installed = claude.read_installed_plugins()?;
+        let suffix = format!("@{}", GIT_MARKETPLACE);
+        let in_use = installed
+            .plugins
+            .iter()
+            .any(|(key, entries)| !entries.is_empty() && key.ends_with(&suffix));
+        if !in_use {
+            claude.unregister_marketplace(GIT_MARKETPLACE)?;
+            return cache.remove_git_marketplace();
+        }
+    }
+
+    let plugins: BTreeMap<&str, GitPluginSource> =
+        plugins.into_iter().map(|(name, (source, _))| (name, source)).collect();
+    let path = cache.write_git_marketplace(&plugins)?;
+    claude.register_marketplace(GIT_MARKETPLACE, &path)
+}
+
+/// Fetch the repositories of packages without a valid cached copy, in parallel.
+///
+/// Marketplaces are fetched first, since they list where external plugins
+/// live; git plugin repositories are fetched along with external ones.
+fn prefetch_packages(plan: &ManifestPlan, cache: &CacheManager, resolver: &MarketplaceResolver) -> Result<()> {
+    let uncached_packages: Vec<&LockedPackage> = plan
+        .lock
+        .packages
+        .iter()
+        .filter(|pkg| !plan.skipped.contains(&pkg.name))
+        .filter(|pkg| {
+            !pkg.content_hash
+                .as_deref()
+                .is_some_and(|hash| cache.is_plugin_extracted(&pkg.marketplace, &pkg.name, &pkg.plugin_commit, hash))
+        })
+        .collect();
+    let uncached: Vec<(&LockedPackage, &LockedMarketplace)> = uncached_packages
+        .iter()
+        .filter_map(|pkg| Some((*pkg, plan.lock.find_marketplace(&pkg.marketplace)?)))
+        .collect();
+
+    resolver.prefetch(
+        uncached
+            .iter()
+            .map(|(_, marketplace)| FetchTarget::Marketplace {
+                name: marketplace.name.clone(),
+                url: marketplace.url.clone(),
+            })
+            .collect(),
+    )?;
+
+    let targets = collect_results(
+        uncached
+            .iter()
+            .filter(|(pkg, _)| pkg.source_type == SourceType::External)
+            .map(|(pkg, marketplace)| {
+                plugin_repo_target(
+                    resolver,
+                    &marketplace.name,
+                    &marketplace.url,
+                    &pkg.marketplace_commit,
                     &pkg.name,
-                    &pkg.plugin_commit,
-                )?
-            }
+                    Some(&pkg.plugin_commit),
+                )
+            })
+            .collect(),
+    )?;
+    let git_targets = uncached_packages.iter().filter_map(|pkg| {
+        let url = pkg.git.as_ref()?;
+        Some(git_plugin_target(&pkg.name, url, Some(&pkg.plugin_commit)))
+    });
+    resolver.prefetch(targets.into_iter().flatten().chain(git_targets).collect())
+}
+
+/// Remove plugins skill-manager installed for the plan's scope that are no longer locked.
+///
+/// Only entries whose install path lies in skill-manager's plugin cache are
+/// considered, so plugins installed through Claude Code's own `/plugin` UI
+/// are never touched. Returns the number of removed plugins.
+fn prune_plan(
+    plan: &ManifestPlan,
+    cache: &CacheManager,
+    claude: &ClaudeCodeIntegration,
+) -> Result<usize> {
+    let wanted: HashSet<String> = plan
+        .lock
+        .packages
+        .iter()
+        .filter(|pkg| !plan.skipped.contains(&pkg.name))
+        .map(|pkg| format!("{}@{}", pkg.name, pkg.marketplace))
+        .collect();
+    let plugins_dir = cache.cache_dir().join("plugins");
+
+    let mut removed_count = 0;
+    for (key, entry) in claude.installed_plugins_in_scope(&plan.scope)? {
+        if wanted.contains(&key) || !Path::new(&entry.install_path).starts_with(&plugins_dir) {
+            continue;
+        }
+        let Some((name, marketplace)) = key.rsplit_once('@') else {
+            continue;
         };
 
-        // Register with Claude Code
-        claude.add_installed_plugin(
-            &pkg.name,
+        println!("Removing {}...", key);
+        claude.remove_installed_plugin(name, marketplace, &plan.scope)?;
+
+        // Other scopes may still use the plugin; only disable it once nothing does
+        let still_installed = claude
+            .read_installed_plugins()?
+            .plugins
+            .get(&key)
+            .is_some_and(|entries| !entries.is_empty());
+        if !still_installed {
+            claude.disable_plugin(name, marketplace)?;
+        }
+
+        removed_count += 1;
+    }
+
+    Ok(removed_count)
+}
+
+/// Unregister marketplaces skill-manager registered that no lock or installed plugin uses.
+/// Returns the number of unregistered marketplaces.
+fn prune_marketplaces(
+    plans: &[ManifestPlan],
+    resolver: &MarketplaceResolver,
+    claude: &ClaudeCodeIntegration,
+) -> Result<usize> {
+    let wanted: HashSet<&str> = plans
+        .iter()
+        .flat_map(|plan| plan.lock.marketplaces.iter().map(|m| m.name.as_str()))
+        .collect();
+    let marketplaces_dir = resolver.cache_dir.join("marketplaces");
+    let installed = claude.read_installed_plugins()?;
+
+    let mut known: Vec<_> = claude.read_known_marketplaces()?.into_iter().collect();
+    known.sort_by(|a, b| a.0.cmp(&b.0));
+
+    let mut unregistered_count = 0;
+    for (name, entry) in known {
+        if wanted.contains(name.as_str())
+            || !Path::new(&entry.install_location).starts_with(&marketplaces_dir)
+        {
+            continue;
+        }
+
+        let in_use = installed.plugins.iter().any(|(key, entries)| {
+            !entries.is_empty() && key.rsplit_once('@').is_some_and(|(_, m)| m == name)
+        });
+        if in_use {
+            continue;
+        }
+
+        println!("Unregistering marketplace {}...", name);
+        claude.unregister_marketplace(&name)?;
+        unregistered_count += 1;
+    }
+
+    Ok(unregistered_count)
+}
+
+/// Extract a locked package to the cache and register it with Claude Code.
+///
+/// A cached copy matching the lock's content hash is used as is; anything
+/// else is re-extracted from the locked commit, unless `frozen` restricts
+/// installation to the cache. Returns the content hash.
+fn install_package(
+    pkg: &LockedPackage,
+    marketplace: Option<&LockedMarketplace>,
+    scope: &PluginScope,
+    cache: &CacheManager,
+    resolver: &MarketplaceResolver,
+    claude: &ClaudeCodeIntegration,
+    frozen: bool,
+) -> Result<String> {
+    let expected_hash = pkg.content_hash.as_deref();
+
+    let extracted = match expected_hash {
+        Some(hash) if cache.is_plugin_extracted(&pkg.marketplace, &pkg.name, &pkg.plugin_commit, hash) => {
+            ExtractedPlugin {
+                path: cache.plugin_path(&pkg.marketplace, &pkg.name, &pkg.plugin_commit),
+                content_hash: hash.to_string(),
+            }
+        }
+        _ if frozen => cached_package(pkg, cache)?,
+        _ => {
+            if expected_hash.is_some()
+                && cache
+                    .plugin_path(&pkg.marketplace, &pkg.name, &pkg.plugin_commit)
+                    .exists()
+            {
+                println!("  Cached copy of {} does not match plugins.lock, re-extracting", pkg.name);
+            }
+            extract_package(pkg, marketplace, cache, resolver)?
+        }
+    };
+
+    // Register with Claude Code
+    claude.add_installed_plugin(
+        &pkg.name,
+        &pkg.marketplace,
+        &extracted.path,
+        &pkg.resolved_version,
+        &pkg.plugin_commit,
+        scope,
+    )?;
+
+    claude.enable_plugin(&pkg.name, &pkg.marketplace)?;
+
+    Ok(extracted.content_hash)
+}
+
+/// Use a package's cached copy without fetching or extracting anything.
+///
+/// Fails when the copy is missing or does not match the lock's content hash.
+fn cached_package(pkg: &LockedPackage, cache: &CacheManager) -> Result<ExtractedPlugin> {
+    let path = cache.plugin_path(&pkg.marketplace, &pkg.name, &pkg.plugin_commit);
+    if !path.exists() {
+        return Err(Error::NotInCache(format!(
+            "plugin '{}' at {}",
+            pkg.name,
+            &pkg.plugin_commit[..7.min(pkg.plugin_commit.len())]
+        )));
+    }
+
+    let hash = content_hash(&path).map_err(|e| Error::PluginExtract(pkg.name.clone(), e))?;
+    if let Some(ref expected) = pkg.content_hash
+        && *expected != hash
+    {
+        return Err(Error::ContentHashMismatch {
+            plugin: pkg.name.clone(),
+            expected: expected.clone(),
+            actual: hash,
+        });
+    }
+
+    Ok(ExtractedPlugin {
+        path,
+        content_hash: hash,
+    })
+}
+
+/// Extract a locked package from its marketplace or plugin repository.
+///
+/// Git plugins have no marketplace entry in the lock.
+fn extract_package(
+    pkg: &LockedPackage,
+    marketplace: Option<&LockedMarketplace>,
+    cache: &CacheManager,
+    resolver: &MarketplaceResolver,
+) -> Result<ExtractedPlugin> {
+    if let Some(ref url) = pkg.git {
+        let repo_path = resolver.checkout_plugin_repo(GIT_MARKETPLACE, &pkg.name, url, &pkg.plugin_commit)?;
+
+        return cache.extract_external_plugin(
+            &repo_path.join(pkg.subdir.as_deref().unwrap_or_default()),
             &pkg.marketplace,
-            &install_path,
-            &pkg.resolved_version,
+            &pkg.name,
             &pkg.plugin_commit,
-            &scope,
-        )?;
+            pkg.content_hash.as_deref(),
+        );
+    }
 
-        claude.enable_plugin(&pkg.name, &pkg.marketplace)?;
+    let marketplace = marketplace.ok_or_else(|| Error::UndeclaredMarketplace(pkg.marketplace.clone()))?;
+    // Get the plugin source from the marketplace.json
+    let mkt_json = resolver.marketplace_json_at(&pkg.marketplace, &marketplace.url, &pkg.marketplace_commit)?;
+    let plugin_info = resolver.find_plugin(&mkt_json, &pkg.marketplace, &pkg.name)?;
 
-        installed_count += 1;
-    }
+    match (pkg.source_type, &plugin_info.source) {
+        (SourceType::Local, PluginSource::Local(source_path)) => {
+            let root =
+                resolver.local_plugin_root(&pkg.marketplace, &marketplace.url, &pkg.marketplace_commit, source_path)?;
 
-    println!("\nInstalled {} plugin(s)", installed_count);
-    Ok(())
+            cache.extract_local_plugin(
+                &root,
+                source_path,
+                &pkg.marketplace,
+                &pkg.name,
+                &pkg.plugin_commit,
+                pkg.content_hash.as_deref(),
+            )
+        }
+        (SourceType::External, PluginSource::External(source)) => {
+            let subdir = source.subdir(&pkg.marketplace, &pkg.name)?;
+            let plugin_repo_path =
+                resolver.checkout_plugin_repo(&pkg.marketplace, &pkg.name, &source.url, &pkg.plugin_commit)?;
+
+            cache.extract_external_plugin(
+                &plugin_repo_path.join(subdir),
+                &pkg.marketplace,
+                &pkg.name,
+                &pkg.plugin_commit,
+                pkg.content_hash.as_deref(),
+            )
+        }
+        _ => Err(Error::PluginNotFound {
+            plugin: pkg.name.clone(),
+            marketplace: pkg.marketplace.clone(),
+        }),
+    }
 }
 
 /// Resolve all marketplaces and plugins to create lock file entries.
+///
+/// Entries of a previous lock whose fingerprint still matches the manifest
+/// keep their locked commits, so only new or changed entries are resolved.
+/// Plugins are also re-resolved when their marketplace was. Dependencies
+/// declared in plugin.json are pulled in transitively.
 fn resolve_all(
     manifest: &Manifest,
+    previous: Option<&LockFile>,
     resolver: &MarketplaceResolver,
 ) -> Result<(Vec<LockedMarketplace>, Vec<LockedPackage>)> {
     let mut locked_marketplaces = Vec::new();
     let mut locked_packages = Vec::new();
+    let mut pending: PendingDependencies = Vec::new();
+    let mut kept_marketplaces = HashSet::new();
 
-    // First, resolve all marketplaces
-    for (name, entry) in &manifest.marketplaces {
+    // First, resolve all marketplaces; changed ones are fetched in parallel
+    let mut marketplace_names: Vec<&String> = manifest.marketplaces.keys().collect();
+    marketplace_names.sort();
+
+    let reused_marketplace = |name: &str| {
+        previous
+            .and_then(|lock| lock.find_marketplace(name))
+            .filter(|locked| locked.fingerprint == Some(manifest.marketplaces[name].fingerprint()))
+            .filter(|locked| !directory_changed(locked, resolver))
+    };
+    let changed: Vec<&String> = marketplace_names
+        .iter()
+        .copied()
+        .filter(|name| reused_marketplace(name).is_none())
+        .collect();
+    for name in &changed {
         println!("  Resolving marketplace '{}'...", name);
+    }
+    let resolved = collect_results(map_parallel(&changed, resolver.jobs(), |name| {
+        resolve_marketplace_entry(name, &manifest.marketplaces[*name], resolver)
+    }))?;
+    let mut resolved: HashMap<&String, LockedMarketplace> = changed.into_iter().zip(resolved).collect();
 
-        let repo = resolver.ensure_marketplace(name, &entry.url)?;
+    for name in marketplace_names {
+        match reused_marketplace(name) {
+            Some(locked) => {
+                kept_marketplaces.insert(name.as_str());
+                locked_marketplaces.push(locked.clone());
+            }
+            None => locked_marketplaces.extend(resolved.remove(name)),
+        }
+    }
 
-        let commit = if let Some(ref c) = entry.commit {
-            c.clone()
-        } else if let Some(ref tag) = entry.tag {
-            resolver.resolve_tag(&repo, name, tag)?
-        } else {
-            resolver.resolve_head(&repo)?
-        };
+    // Then, resolve all plugins
+    let mut plugin_names: Vec<&String> = manifest.plugins.keys().collect();
+    plugin_names.sort();
 
-        // Checkout the resolved commit
-        resolver.checkout_commit(&repo, name, &commit)?;
+    let reused_package = |name: &str| {
+        previous
+            .and_then(|lock| lock.find_package(name))
+            .filter(|locked| locked.fingerprint == Some(manifest.plugins[name].fingerprint()))
+            .filter(|locked| locked.git.is_some() || kept_marketplaces.contains(locked.marketplace.as_str()))
+    };
 
-        locked_marketplaces.push(LockedMarketplace {
-            name: name.clone(),
-            url: entry.url.clone(),
-            commit,
-        });
-    }
+    // Fetch the repositories of external and git plugins in parallel before resolving them
+    let targets = collect_results(
+        plugin_names
+            .iter()
+            .filter(|name| reused_package(name).is_none())
+            .map(|name| {
+                let entry = &manifest.plugins[*name];
+                if let Some(ref url) = entry.git {
+                    return Ok(Some(git_plugin_target(name, url, None)));
+                }
+                let marketplace = locked_marketplaces
+                    .iter()
+                    .find(|m| m.name == entry.marketplace)
+                    .ok_or_else(|| Error::UndeclaredMarketplace(entry.marketplace.clone()))?;
+                plugin_repo_target(resolver, &marketplace.name, &marketplace.url, &marketplace.commit, name, None)
+            })
+            .collect(),
+    )?;
+    resolver.prefetch(targets.into_iter().flatten().collect())?;
+
+    for plugin_name in plugin_names {
+        let plugin_entry = &manifest.plugins[plugin_name];
+
+        if let Some(locked) = reused_package(plugin_name) {
+            // May have been pulled in as a dependency before being declared
+            locked_packages.push(LockedPackage {
+                direct: true,
+                ..locked.clone()
+            });
+            continue;
+        }
 
-    // Then, resolve all plugins
-    for (plugin_name, plugin_entry) in &manifest.plugins {
         println!("  Resolving plugin '{}'...", plugin_name);
 
-        let marketplace = locked_marketplaces
+        let (package, dependencies) = resolve_entry(plugin_name, plugin_entry, &locked_marketplaces, resolver)?;
+        locked_packages.push(package);
+        pending.push((plugin_name.clone(), dependencies));
+    }
+
+    dependencies::resolve_dependencies(&locked_marketplaces, &mut locked_packages, pending, previous, resolver)?;
+
+    Ok((locked_marketplaces, locked_packages))
+}
+
+/// Look up a plugin in its marketplace and describe the fetch its repository needs.
+///
+/// Plugins that live inside the marketplace need none. With a commit, the
+/// plugin repository is only fetched when the commit is missing.
+fn plugin_repo_target(
+    resolver: &MarketplaceResolver,
+    marketplace: &str,
+    url: &str,
+    marketplace_commit: &str,
+    plugin: &str,
+    commit: Option<&str>,
+) -> Result<Option<FetchTarget>> {
+    let mkt_json = resolver.marketplace_json_at(marketplace, url, marketplace_commit)?;
+
+    Ok(match &resolver.find_plugin(&mkt_json, marketplace, plugin)?.source {
+        PluginSource::External(source) => Some(FetchTarget::PluginRepo {
+            marketplace: marketplace.to_string(),
+            plugin: plugin.to_string(),
+            url: source.url.clone(),
+            commit: commit.map(str::to_string),
+        }),
+        PluginSource::Local(_) | PluginSource::Unsupported(_) => None,
+    })
+}
+
+/// Describe the fetch a git plugin's repository needs.
+fn git_plugin_target(plugin: &str, url: &str, commit: Option<&str>) -> FetchTarget {
+    FetchTarget::PluginRepo {
+        marketplace: GIT_MARKETPLACE.to_string(),
+        plugin: plugin.to_string(),
+        url: url.to_string(),
+        commit: commit.map(str::to_string),
+    }
+}
+
+/// Resolve a manifest's marketplace entry to a lock entry.
+///
+/// Directory marketplaces lock the hash of their contents.
+pub(super) fn resolve_marketplace_entry(
+    name: &str,
+    entry: &MarketplaceEntry,
+    resolver: &MarketplaceResolver,
+) -> Result<LockedMarketplace> {
+    let commit = match entry.directory() {
+        Some(dir) => resolver.resolve_directory(name, dir)?,
+        None => resolver.resolve_marketplace(name, entry)?.1,
+    };
+
+    Ok(LockedMarketplace {
+        name: name.to_string(),
+        url: entry.url.clone(),
+        path: entry.path.clone(),
+        commit,
+        branch: entry.branch.clone(),
+        fingerprint: Some(entry.fingerprint()),
+    })
+}
+
+/// Resolve a plugin entry, from its git repository or at its marketplace's
+/// locked commit.
+pub(super) fn resolve_entry(
+    name: &str,
+    entry: &PluginEntry,
+    marketplaces: &[LockedMarketplace],
+    resolver: &MarketplaceResolver,
+) -> Result<(LockedPackage, BTreeMap<String, PluginDependency>)> {
+    if let Some(ref url) = entry.git {
+        return resolve_git_package(name, entry, url, resolver);
+    }
+
+    let marketplace = marketplaces
+        .iter()
+        .find(|m| m.name == entry.marketplace)
+        .ok_or_else(|| Error::UndeclaredMarketplace(entry.marketplace.clone()))?;
+    resolve_package(name, entry, marketplace, resolver)
+}
+
+/// Resolve a git plugin entry straight from its repository.
+fn resolve_git_package(
+    name: &str,
+    entry: &PluginEntry,
+    url: &str,
+    resolver: &MarketplaceResolver,
+) -> Result<(LockedPackage, BTreeMap<String, PluginDependency>)> {
+    let resolved = resolver.resolve_git_plugin(name, url, entry.subdir.as_deref(), entry)?;
+
+    let package = LockedPackage {
+        git: Some(url.to_string()),
+        subdir: entry.subdir.clone(),
+        ..locked_package(&resolved, entry)
+    };
+    Ok((package, resolved.dependencies))
+}
+
+/// Resolve a plugin entry at its locked marketplace commit.
+///
+/// Returns the lock entry together with the dependencies its plugin.json
+/// declares, which still have to be resolved.
+pub(super) fn resolve_package(
+    name: &str,
+    entry: &PluginEntry,
+    marketplace: &LockedMarketplace,
+    resolver: &MarketplaceResolver,
+) -> Result<(LockedPackage, BTreeMap<String, PluginDependency>)> {
+    // Get marketplace info
+    let mkt_json = resolver.marketplace_json_at(&marketplace.name, &marketplace.url, &marketplace.commit)?;
+    let plugin_info = resolver.find_plugin(&mkt_json, &marketplace.name, name)?;
+
+    // Resolve the plugin
+    let resolved = resolver.resolve_plugin(&marketplace.name, &marketplace.commit, name, plugin_info, entry)?;
+
+    Ok((locked_package(&resolved, entry), resolved.dependencies))
+}
+
+/// The lock entry for a resolved plugin.
+fn locked_package(resolved: &ResolvedPlugin, entry: &PluginEntry) -> LockedPackage {
+    LockedPackage {
+        name: resolved.name.clone(),
+        marketplace: resolved.marketplace.clone(),
+        source_type: resolved.source_type,
+        marketplace_commit: resolved.marketplace_commit.clone(),
+        plugin_commit: resolved.plugin_commit.clone(),
+        resolved_version: resolved.resolved_version.clone(),
+        version_req: resolved.version_req.clone(),
+        tag: resolved.tag.clone(),
+        branch: resolved.branch.clone(),
+        git: None,
+        subdir: None,
+        content_hash: None,
+        fingerprint: Some(entry.fingerprint()),
+        dependencies: resolved.dependencies.keys().cloned().collect(),
+        requirements: BTreeMap::new(),
+        direct: true,
+    }
+}
+
+/// Describe how a manifest differs from its lock, one line per entry:
+/// `+` for entries missing from the lock, `-` for locked entries no longer in
+/// the manifest and `~` for entries whose manifest declaration changed.
+pub(super) fn lock_diff(manifest: &Manifest, lock: &LockFile) -> Vec<String> {
+    let mut changes = Vec::new();
+
+    let mut marketplace_names: Vec<&String> = manifest.marketplaces.keys().collect();
+    marketplace_names.sort();
+    for name in marketplace_names {
+        let entry = &manifest.marketplaces[name];
+        match lock.find_marketplace(name) {
+            None => changes.push(format!("+ marketplace '{}' ({})", name, entry.location())),
+            Some(locked) if locked.fingerprint != Some(entry.fingerprint()) => changes.push(format!(
+                "~ marketplace '{}': now {} (locked at {})",
+                name,
+                describe_marketplace(entry),
+                short_commit(&locked.commit)
+            )),
+            Some(_) => {}
+        }
+    }
+    for locked in &lock.marketplaces {
+        if !manifest.marketplaces.contains_key(&locked.name) {
+            changes.push(format!("- marketplace '{}'", locked.name));
+        }
+    }
+
+    let mut plugin_names: Vec<&String> = manifest.plugins.keys().collect();
+    plugin_names.sort();
+    for name in plugin_names {
+        let entry = &manifest.plugins[name];
+        match lock.find_package(name) {
+            None => changes.push(format!("+ plugin '{}' ({})", name, conflict::describe_entry(entry))),
+            Some(locked) if locked.fingerprint != Some(entry.fingerprint()) => changes.push(format!(
+                "~ plugin '{}': now {} (locked at {})",
+                name,
+                conflict::describe_entry(entry),
+                short_commit(&locked.plugin_commit)
+            )),
+            Some(_) => {}
+        }
+    }
+    for locked in &lock.packages {
+        if locked.direct && !manifest.plugins.contains_key(&locked.name) {
+            changes.push(format!("- plugin '{}'", locked.name));
+        }
+    }
+
+    changes
+}
+
+/// Describe a marketplace entry and how it is pinned, e.g. `<url>, tag: v1.0`.
+fn describe_marketplace(entry: &MarketplaceEntry) -> String {
+    if let Some(ref dir) = entry.path {
+        format!("directory {}", dir.display())
+    } else if let Some(ref tag) = entry.tag {
+        format!("{}, tag: {}", entry.url, tag)
+    } else if let Some(ref commit) = entry.commit {
+        format!("{}, commit: {}", entry.url, short_commit(commit))
+    } else if let Some(ref branch) = entry.branch {
+        format!("{}, branch: {}", entry.url, branch)
+    } else {
+        format!("{}, latest", entry.url)
+    }
+}
+
+/// Abbreviate a commit hash for display.
+pub(super) fn short_commit(commit: &str) -> &str {
+    &commit[..7.min(commit.len())]
+}
+
+/// Whether a locked directory marketplace no longer matches its directory.
+///
+/// The lock records the path as written in the manifest, so the directory
+/// is the one the manifest resolves it to. A directory that cannot be hashed
+/// counts as changed, so resolving it reports the error.
+fn directory_changed(locked: &LockedMarketplace, resolver: &MarketplaceResolver) -> bool {
+    if locked.path.is_none() {
+        return false;
+    }
+    resolver.directory(&locked.name).is_some_and(|dir| {
+        resolver
+            .resolve_directory(&locked.name, dir)
+            .map_or(true, |hash| hash != locked.commit)
+    })
+}
+
+/// The directory marketplaces of a lock whose contents changed since it was written.
+pub(super) fn changed_directories(lock: &LockFile, resolver: &MarketplaceResolver) -> Vec<String> {
+    lock.marketplaces
+        .iter()
+        .filter(|locked| directory_changed(locked, resolver))
+        .map(|locked| locked.name.clone())
+        .collect()
+}
+
+/// Record the manifest's entry fingerprints in a lock written before they existed.
+pub(super) fn record_fingerprints(manifest: &Manifest, lock: &mut LockFile) {
+    for locked in &mut lock.marketplaces {
+        if let Some(entry) = manifest.marketplaces.get(&locked.name) {
+            locked.fingerprint = Some(entry.fingerprint());
+        }
+    }
+    for locked in &mut lock.packages {
+        if let Some(entry) = manifest.plugins.get(&locked.name) {
+            locked.fingerprint = Some(entry.fingerprint());
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::installer::source_hash;
+    use crate::test_util::{commit_files, marketplace_files, write_files};
+    use git2::Repository;
+    use std::fs;
+
+    /// A marketplace with local plugins `foo` and `bar` at a version.
+    fn marketplace(version: &str) -> Vec<(String, String)> {
+        marketplace_files(&[("foo", version, ""), ("bar", version, "")])
+    }
+
+    fn manifest(url: &str, marketplace: &str, plugins: &[&str]) -> Manifest {
+        let plugins: Vec<String> = plugins
             .iter()
-            .find(|m| m.name == plugin_entry.marketplace)
-            .ok_or_else(|| Error::UndeclaredMarketplace(plugin_entry.marketplace.clone()))?;
-
-        // Get marketplace info
-        let repo = resolver.ensure_marketplace(&marketplace.name, &marketplace.url)?;
-        resolver.checkout_commit(&repo, &marketplace.name, &marketplace.commit)?;
-
-        let mkt_json = resolver.parse_marketplace_json(&repo, &marketplace.name)?;
-        let plugin_info = resolver.find_plugin(&mkt_json, &marketplace.name, plugin_name)?;
-
-        // Resolve the plugin
-        let resolved = resolver.resolve_plugin(
-            &marketplace.name,
-            &marketplace.commit,
-            plugin_name,
-            plugin_info,
-            plugin_entry.tag.as_deref(),
-            plugin_entry.commit.as_deref(),
-        )?;
+            .map(|p| format!(r#"{} = {{ marketplace = "dev" }}"#, p))
+            .collect();
+        Manifest::parse(&format!(
+            "[marketplaces]\ndev = {}\n\n[plugins]\n{}\n",
+            marketplace.replace("URL", url),
+            plugins.join("\n")
+        ))
+        .unwrap()
+    }
 
-        locked_packages.push(LockedPackage {
-            name: resolved.name,
-            marketplace: resolved.marketplace,
-            source_type: resolved.source_type,
-            marketplace_commit: resolved.marketplace_commit,
-            plugin_commit: resolved.plugin_commit,
-            resolved_version: resolved.resolved_version,
-        });
+    #[test]
+    fn test_resolve_all_only_resolves_changed_entries() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let upstream_dir = temp_dir.path().join("upstream");
+        let upstream = Repository::init(&upstream_dir).unwrap();
+        let first = commit_files(&upstream, &marketplace("1.0.0"), "1.0.0");
+        let url = format!("file://{}", upstream_dir.display());
+
+        let resolver = MarketplaceResolver::new(temp_dir.path().join("cache"));
+        let manifest1 = manifest(&url, r#""URL""#, &["foo"]);
+        let (marketplaces, packages) = resolve_all(&manifest1, None, &resolver).unwrap();
+        let lock1 = LockFile {
+            marketplaces,
+            packages,
+            ..LockFile::default()
+        };
+        assert_eq!(lock1.marketplaces[0].commit, first);
+
+        // Upstream moves on; adding a plugin keeps the locked marketplace and plugin
+        let second = commit_files(&upstream, &marketplace("2.0.0"), "2.0.0");
+        let resolver = MarketplaceResolver::new(temp_dir.path().join("cache"));
+        let manifest2 = manifest(&url, r#""URL""#, &["foo", "bar"]);
+        let (marketplaces, packages) = resolve_all(&manifest2, Some(&lock1), &resolver).unwrap();
+        assert_eq!(marketplaces, lock1.marketplaces);
+        assert_eq!(packages[1], lock1.packages[0]);
+        assert_eq!(packages[0].name, "bar");
+        assert_eq!(packages[0].plugin_commit, first);
+
+        // Changing the marketplace entry re-resolves it and its plugins
+        let branch = upstream.head().unwrap().shorthand().unwrap().to_string();
+        let marketplace = format!(r#"{{ url = "URL", branch = "{}" }}"#, branch);
+        let manifest3 = manifest(&url, &marketplace, &["foo"]);
+        let (marketplaces, packages) = resolve_all(&manifest3, Some(&lock1), &resolver).unwrap();
+        assert_eq!(marketplaces[0].commit, second);
+        assert_eq!(packages[0].plugin_commit, second);
+        assert_eq!(packages[0].resolved_version, "2.0.0");
     }
 
-    Ok((locked_marketplaces, locked_packages))
+    #[test]
+    fn test_directory_marketplace_follows_changes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let dir = temp_dir.path().join("my-marketplace");
+        write_files(&dir, &marketplace("1.0.0"));
+
+        let manifest = manifest("", &format!(r#"{{ path = "{}" }}"#, dir.display()), &["foo"]);
+        let cache_dir = temp_dir.path().join("cache");
+        let resolver = MarketplaceResolver::new(cache_dir.clone()).with_directories(&manifest.marketplaces);
+        let (marketplaces, packages) = resolve_all(&manifest, None, &resolver).unwrap();
+        let lock = LockFile {
+            marketplaces,
+            packages,
+            ..LockFile::default()
+        };
+        assert_eq!(lock.marketplaces[0].path, Some(dir.clone()));
+        assert_eq!(lock.marketplaces[0].commit, source_hash(&dir).unwrap());
+        assert_eq!(lock.packages[0].plugin_commit, source_hash(&dir.join("plugins/foo")).unwrap());
+        assert_eq!(lock.packages[0].resolved_version, "1.0.0");
+        assert!(!cache_dir.join("marketplaces/dev").exists());
+
+        // Unchanged, the lock is kept as it is
+        let resolver = MarketplaceResolver::new(cache_dir.clone()).with_directories(&manifest.marketplaces);
+        assert!(changed_directories(&lock, &resolver).is_empty());
+
+        // Editing the directory re-resolves the marketplace and its plugins
+        write_files(&dir, &marketplace("1.1.0"));
+        let resolver = MarketplaceResolver::new(cache_dir).with_directories(&manifest.marketplaces);
+        assert_eq!(changed_directories(&lock, &resolver), vec!["dev".to_string()]);
+        let (marketplaces, packages) = resolve_all(&manifest, Some(&lock), &resolver).unwrap();
+        assert_ne!(marketplaces[0].commit, lock.marketplaces[0].commit);
+        assert_ne!(packages[0].plugin_commit, lock.packages[0].plugin_commit);
+        assert_eq!(packages[0].resolved_version, "1.1.0");
+    }
+
+    #[test]
+    fn test_same_marketplace_name_in_both_scopes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let upstream_dir = temp_dir.path().join("upstream");
+        let upstream = Repository::init(&upstream_dir).unwrap();
+        let commit = commit_files(&upstream, &marketplace("1.0.0"), "1.0.0");
+        let url = format!("file://{}", upstream_dir.display());
+        let dir = temp_dir.path().join("my-marketplace");
+        write_files(&dir, &marketplace("2.0.0"));
+
+        // A global git marketplace and a project directory marketplace, both `dev`
+        let global = manifest(&url, r#""URL""#, &["foo"]);
+        let project = manifest("", &format!(r#"{{ path = "{}" }}"#, dir.display()), &["foo"]);
+        let cache_dir = temp_dir.path().join("cache");
+        let resolver = MarketplaceResolver::new(cache_dir.clone());
+        let global_resolver = resolver.for_manifest(&global);
+        let project_resolver = resolver.for_manifest(&project);
+
+        let (marketplaces, packages) = resolve_all(&global, None, &global_resolver).unwrap();
+        assert_eq!(marketplaces[0].path, None);
+        assert_eq!(marketplaces[0].commit, commit);
+        assert_eq!(packages[0].resolved_version, "1.0.0");
+        assert_eq!(global_resolver.marketplace_path("dev"), cache_dir.join("marketplaces/dev"));
+        let lock = LockFile {
+            marketplaces,
+            packages,
+            ..LockFile::default()
+        };
+        assert!(changed_directories(&lock, &global_resolver).is_empty());
+
+        let (marketplaces, packages) = resolve_all(&project, None, &project_resolver).unwrap();
+        assert_eq!(marketplaces[0].path, Some(dir.clone()));
+        assert_eq!(marketplaces[0].commit, source_hash(&dir).unwrap());
+        assert_eq!(packages[0].resolved_version, "2.0.0");
+        assert_eq!(project_resolver.marketplace_path("dev"), dir);
+    }
+
+    #[test]
+    fn test_directory_marketplace_lock_is_portable() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let content = "[marketplaces]\ndev = { path = \"../my-marketplace\" }\n\n[plugins]\nfoo = { marketplace = \"dev\" }\n";
+
+        // The same project checked out in two places
+        let mut locks = Vec::new();
+        for checkout in ["alice/project", "bob/work/project"] {
+            let root = temp_dir.path().join(checkout);
+            write_files(&root.join("my-marketplace"), &marketplace("1.0.0"));
+            fs::create_dir_all(root.join(".claude")).unwrap();
+            let manifest_path = root.join(".claude/plugins.toml");
+            fs::write(&manifest_path, content).unwrap();
+
+            let manifest = Manifest::load(&manifest_path).unwrap();
+            let resolver = MarketplaceResolver::new(temp_dir.path().join("cache")).with_directories(&manifest.marketplaces);
+            let (marketplaces, packages) = resolve_all(&manifest, None, &resolver).unwrap();
+            let lock = LockFile {
+                config_hash: Some(manifest.compute_hash()),
+                marketplaces,
+                packages,
+                ..LockFile::default()
+            };
+            assert!(changed_directories(&lock, &resolver).is_empty());
+            locks.push((manifest.marketplaces["dev"].fingerprint(), lock));
+        }
+
+        assert_eq!(locks[0].0, locks[1].0);
+        assert_eq!(locks[0].1.config_hash, locks[1].1.config_hash);
+        assert_eq!(locks[0].1.marketplaces, locks[1].1.marketplaces);
+        assert_eq!(locks[0].1.packages, locks[1].1.packages);
+        assert_eq!(locks[0].1.marketplaces[0].path, Some(PathBuf::from("../my-marketplace")));
+    }
+
+    #[test]
+    fn test_resolve_git_plugin() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let upstream_dir = temp_dir.path().join("upstream");
+        let upstream = Repository::init(&upstream_dir).unwrap();
+        let commit = commit_files(&upstream, &marketplace("1.0.0"), "1.0.0");
+
+        let manifest = Manifest::parse(&format!(
+            "[plugins]\nfoo = {{ git = \"file://{}\", subdir = \"plugins/foo\" }}\n",
+            upstream_dir.display()
+        ))
+        .unwrap();
+        manifest.validate().unwrap();
+
+        let cache = CacheManager::with_cache_dir(temp_dir.path().join("cache"));
+        let resolver = MarketplaceResolver::new(cache.cache_dir().to_path_buf());
+        let (marketplaces, packages) = resolve_all(&manifest, None, &resolver).unwrap();
+        assert!(marketplaces.is_empty());
+        let pkg = &packages[0];
+        assert_eq!(pkg.marketplace, GIT_MARKETPLACE);
+        assert_eq!(pkg.source_type, SourceType::External);
+        assert_eq!(pkg.plugin_commit, commit);
+        assert_eq!(pkg.resolved_version, "1.0.0");
+        assert_eq!(pkg.subdir.as_deref(), Some("plugins/foo"));
+
+        // Only the subdirectory is extracted
+        let extracted = extract_package(pkg, None, &cache, &resolver).unwrap();
+        assert!(extracted.path.join(".claude-plugin/plugin.json").exists());
+        assert!(!extracted.path.join("plugins").exists());
+
+        // An unchanged entry keeps its locked commit
+        commit_files(&upstream, &marketplace("2.0.0"), "2.0.0");
+        let lock = LockFile {
+            packages: packages.clone(),
+            ..LockFile::default()
+        };
+        let resolver = MarketplaceResolver::new(cache.cache_dir().to_path_buf());
+        let (_, packages) = resolve_all(&manifest, Some(&lock), &resolver).unwrap();
+        assert_eq!(packages[0].plugin_commit, commit);
+    }
+
+    #[test]
+    fn test_git_marketplace_lists_every_lock() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache = CacheManager::with_cache_dir(temp_dir.path().join("cache"));
+        let claude = ClaudeCodeIntegration::with_claude_dir(temp_dir.path().join("claude"));
+        let git_package = |name: &str, url: &str, subdir: Option<&str>, commit: &str| LockedPackage {
+            name: name.to_string(),
+            marketplace: GIT_MARKETPLACE.to_string(),
+            source_type: SourceType::External,
+            marketplace_commit: commit.to_string(),
+            plugin_commit: commit.to_string(),
+            resolved_version: "1.0.0".to_string(),
+            version_req: None,
+            tag: None,
+            branch: None,
+            git: Some(url.to_string()),
+            subdir: subdir.map(str::to_string),
+            content_hash: None,
+            fingerprint: None,
+            dependencies: Vec::new(),
+            requirements: BTreeMap::new(),
+            direct: true,
+        };
+        let plan = |lock: LockFile| ManifestPlan {
+            manifest: Manifest::default(),
+            scope: PluginScope::Project(temp_dir.path().join("project")),
+            lock_path: temp_dir.path().join("project/plugins.lock"),
+            lock,
+            lock_changed: false,
+            skipped: HashSet::new(),
+            resolver: MarketplaceResolver::new(cache.cache_dir().to_path_buf()),
+        };
+
+        // Another project installed a git plugin earlier
+        let other_lock = temp_dir.path().join("other/plugins.lock");
+        fs::create_dir_all(temp_dir.path().join("other")).unwrap();
+        LockFile {
+            packages: vec![git_package("bar", "https://example.com/bar.git", None, "b1")],
+            ..LockFile::default()
+        }
+        .save(&other_lock)
+        .unwrap();
+        cache.record_lock(&other_lock).unwrap();
+
+        let packages = vec![git_package("foo", "https://example.com/tools.git", Some("plugins/foo"), "f1")];
+        let plans = [plan(LockFile {
+            packages,
+            ..LockFile::default()
+        })];
+        register_git_marketplace(&plans, &cache, &claude).unwrap();
+
+        let json_path = cache.git_marketplace_path().join(".claude-plugin/marketplace.json");
+        let json: serde_json::Value = serde_json::from_slice(&fs::read(json_path).unwrap()).unwrap();
+        assert_eq!(
+            json["plugins"],
+            serde_json::json!([
+                {"name": "bar", "source": {"source": "url", "url": "https://example.com/bar.git", "sha": "b1"}},
+                {"name": "foo", "source": {
+                    "source": "git-subdir",
+                    "url": "https://example.com/tools.git",
+                    "path": "plugins/foo",
+                    "sha": "f1",
+                }},
+            ])
+        );
+        assert!(claude.read_known_marketplaces().unwrap().contains_key(GIT_MARKETPLACE));
+
+        // Once no lock has a git plugin, the marketplace goes away
+        fs::remove_file(&other_lock).unwrap();
+        register_git_marketplace(&[plan(LockFile::default())], &cache, &claude).unwrap();
+        assert!(!claude.read_known_marketplaces().unwrap().contains_key(GIT_MARKETPLACE));
+        assert!(!cache.git_marketplace_path().exists());
+    }
+
+    #[test]
+    fn test_lock_diff() {
+        let locked = Manifest::parse(
+            r#"
+[marketplaces]
+official = "owner/repo"
+
+[plugins]
+foo = { marketplace = "official" }
+bar = { marketplace = "official", tag = "v1.0" }
+"#,
+        )
+        .unwrap();
+        let mut lock = LockFile::default();
+        for (name, entry) in &locked.marketplaces {
+            lock.marketplaces.push(LockedMarketplace {
+                name: name.clone(),
+                url: entry.url.clone(),
+                path: None,
+                commit: "abc1234def".to_string(),
+                branch: None,
+                fingerprint: Some(entry.fingerprint()),
+            });
+        }
+        for name in ["foo", "bar"] {
+            lock.packages.push(LockedPackage {
+                name: name.to_string(),
+                marketplace: "official".to_string(),
+                source_type: SourceType::Local,
+                marketplace_commit: "abc1234def".to_string(),
+                plugin_commit: "abc1234def".to_string(),
+                resolved_version: "1.0.0".to_string(),
+                version_req: None,
+                tag: None,
+                branch: None,
+                git: None,
+                subdir: None,
+                content_hash: None,
+                fingerprint: Some(locked.plugins[name].fingerprint()),
+                dependencies: vec![],
+                requirements: BTreeMap::new(),
+                direct: true,
+            });
+        }
+        assert!(lock_diff(&locked, &lock).is_empty());
+
+        let changed = Manifest::parse(
+            r#"
+[marketplaces]
+official = "owner/repo"
+
+[plugins]
+bar = { marketplace = "official", tag = "v2.0" }
+baz = { marketplace = "official" }
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            lock_diff(&changed, &lock),
+            vec![
+                "~ plugin 'bar': now official, tag: v2.0 (locked at abc1234)",
+                "+ plugin 'baz' (official, latest)",
+                "- plugin 'foo'",
+            ]
+        );
+    }
 }
@@ -45,20 +45,32 @@ pub fn run() -> Result<()> {
 
 fn list_plugins(manifest: &Manifest, lock: Option<&LockFile>) -> Result<()> {
     for (name, plugin) in &manifest.plugins {
-        let mut parts = vec![format!("  {} ({})", name, plugin.marketplace)];
+        let mut parts = vec![format!("  {} ({})", name, plugin.origin())];
 
         // Show version from manifest if specified
         if let Some(ref tag) = plugin.tag {
             parts.push(format!("tag: {}", tag));
         } else if let Some(ref commit) = plugin.commit {
             parts.push(format!("commit: {}", &commit[..7.min(commit.len())]));
+        } else if let Some(ref branch) = plugin.branch {
+            parts.push(format!("branch: {}", branch));
+        } else if let Some(ref version) = plugin.version {
+            parts.push(format!("version: {}", version));
         }
 
         // Show lock status
-        if let Some(ref lock) = lock {
+        if let Some(lock) = lock {
             if let Some(pkg) = lock.find_package(name) {
                 parts.push(format!("v{}", pkg.resolved_version));
+                if plugin.tag.is_none()
+                    && let Some(ref tag) = pkg.tag
+                {
+                    parts.push(format!("({})", tag));
+                }
                 parts.push(format!("[locked: {}]", &pkg.plugin_commit[..7.min(pkg.plugin_commit.len())]));
+                if !pkg.dependencies.is_empty() {
+                    parts.push(format!("needs: {}", pkg.dependencies.join(", ")));
+                }
             } else {
                 parts.push("[not locked]".to_string());
             }
@@ -69,5 +81,25 @@ fn list_plugins(manifest: &Manifest, lock: Option<&LockFile>) -> Result<()> {
         println!("{}", parts.join(" "));
     }
 
+    // Show plugins pulled in as dependencies
+    if let Some(lock) = lock {
+        for pkg in lock.packages.iter().filter(|p| !p.direct) {
+            let dependents: Vec<&str> = lock
+                .packages
+                .iter()
+                .filter(|p| p.dependencies.contains(&pkg.name))
+                .map(|p| p.name.as_str())
+                .collect();
+            println!(
+                "  {} ({}) v{} [locked: {}] (dependency of {})",
+                pkg.name,
+                pkg.marketplace,
+                pkg.resolved_version,
+                &pkg.plugin_commit[..7.min(pkg.plugin_commit.len())],
+                dependents.join(", ")
+            );
+        }
+    }
+
     Ok(())
 }
@@ -1,11 +1,25 @@
 mod add;
+mod cache;
+mod conflict;
+mod dependencies;
+mod doctor;
+mod export;
 mod init;
 mod install;
 mod list;
+mod outdated;
 mod remove;
+mod search;
+mod tree;
+mod update;
+
+use std::path::PathBuf;
+use std::time::Duration;
 
 use clap::{Parser, Subcommand};
 
+use crate::config::Manifest;
+use crate::resolver::FetchPolicy;
 use crate::Result;
 
 #[derive(Parser)]
@@ -13,6 +27,10 @@ use crate::Result;
 #[command(about = "Reproducible plugin management for Claude Code")]
 #[command(version)]
 pub struct Cli {
+    /// Never touch the network; resolve only from cached clones
+    #[arg(long, global = true)]
+    pub offline: bool,
+
     #[command(subcommand)]
     pub command: Commands,
 }
@@ -42,6 +60,18 @@ pub enum Commands {
         /// Pin to a specific commit
         #[arg(long)]
         commit: Option<String>,
+
+        /// Follow a branch of the plugin repository
+        #[arg(long, conflicts_with_all = ["tag", "commit"])]
+        branch: Option<String>,
+
+        /// Semver requirement matched against the plugin's tags (e.g. "^4.1")
+        #[arg(long, conflicts_with_all = ["tag", "commit", "branch"])]
+        version: Option<String>,
+
+        /// Allow prerelease versions to match the version requirement
+        #[arg(long, requires = "version")]
+        prerelease: bool,
     },
 
     /// Install plugins from the manifest
@@ -57,6 +87,22 @@ pub enum Commands {
         /// Prefer project versions when conflicts occur
         #[arg(long, conflicts_with = "prefer_global")]
         prefer_project: bool,
+
+        /// Remove plugins installed by skill-manager that are no longer in the manifest
+        #[arg(long)]
+        prune: bool,
+
+        /// Fail instead of re-resolving when plugins.lock is missing or out of date
+        #[arg(long, conflicts_with = "update")]
+        locked: bool,
+
+        /// Like --locked, and install only from the cache without fetching
+        #[arg(long, conflicts_with = "update")]
+        frozen: bool,
+
+        /// Maximum number of repositories to fetch in parallel (defaults to the number of cores)
+        #[arg(short, long)]
+        jobs: Option<usize>,
     },
 
     /// Remove a plugin from the manifest
@@ -67,10 +113,95 @@ pub enum Commands {
 
     /// List installed plugins
     List,
+
+    /// Re-resolve selected plugins or marketplaces in the lock file
+    Update {
+        /// Plugins to re-resolve
+        #[arg(required_unless_present = "marketplace")]
+        plugins: Vec<String>,
+
+        /// Also move the marketplaces of the named plugins
+        #[arg(long, requires = "plugins")]
+        with_marketplace: bool,
+
+        /// Marketplace to move to its latest allowed commit (repeatable)
+        #[arg(long)]
+        marketplace: Vec<String>,
+    },
+
+    /// Show locked plugins and their dependencies as a tree
+    Tree,
+
+    /// Show locked plugins that have newer versions upstream
+    Outdated,
+
+    /// Search declared marketplaces for plugins
+    Search {
+        /// Text to match against plugin names and descriptions
+        query: String,
+    },
+
+    /// Generate a plugins.toml from the plugins installed in Claude Code
+    Export {
+        /// Export user-scope plugins instead of the current project's
+        #[arg(long)]
+        global: bool,
+
+        /// Export plugins installed for this project path (defaults to the current directory)
+        #[arg(long, conflicts_with = "global")]
+        project: Option<PathBuf>,
+
+        /// Write the manifest to a file instead of stdout
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+    },
+
+    /// Inspect and clean up the cache
+    Cache {
+        #[command(subcommand)]
+        command: CacheCommands,
+    },
+
+    /// Check manifests, locks, the cache and Claude Code's files against each other
+    Doctor {
+        /// Repair what can be repaired without resolving or fetching anything
+        #[arg(long)]
+        fix: bool,
+    },
+}
+
+#[derive(Subcommand)]
+pub enum CacheCommands {
+    /// Remove cached plugins and repositories no lock file or Claude Code uses
+    Gc {
+        /// Show what would be removed without removing it
+        #[arg(long)]
+        dry_run: bool,
+
+        /// Also evict the oldest locked entries until the cache fits (e.g. "2G")
+        #[arg(long, value_parser = cache::parse_size)]
+        max_size: Option<u64>,
+
+        /// Also evict locked entries not modified for this long (e.g. "30d", "12h", "2w")
+        #[arg(long, value_parser = cache::parse_age)]
+        max_age: Option<Duration>,
+    },
+
+    /// Show how much space the cache takes
+    Size,
+
+    /// List cached plugins, repositories and marketplaces
+    Ls,
 }
 
 impl Cli {
     pub fn run(self) -> Result<()> {
+        let offline = self.offline;
+        self.run_command().map_err(|e| if offline { e.offline() } else { e })
+    }
+
+    fn run_command(self) -> Result<()> {
+        let offline = self.offline;
         match self.command {
             Commands::Init { global } => init::run(global),
             Commands::Add {
@@ -78,14 +209,66 @@ impl Cli {
                 marketplace,
                 tag,
                 commit,
-            } => add::run(name, marketplace, tag, commit),
+                branch,
+                version,
+                prerelease,
+            } => add::run(name, marketplace, tag, commit, branch, version, prerelease, offline),
             Commands::Install {
                 update,
                 prefer_global,
                 prefer_project,
-            } => install::run(update, prefer_global, prefer_project),
+                prune,
+                locked,
+                frozen,
+                jobs,
+            } => install::run(
+                update,
+                prefer_global,
+                prefer_project,
+                prune,
+                install::LockMode::from_flags(locked, frozen),
+                jobs,
+                offline,
+            ),
             Commands::Remove { name } => remove::run(name),
             Commands::List => list::run(),
+            Commands::Update {
+                plugins,
+                with_marketplace,
+                marketplace,
+            } => update::run(plugins, with_marketplace, marketplace, offline),
+            Commands::Tree => tree::run(),
+            Commands::Outdated => outdated::run(offline),
+            Commands::Search { query } => search::run(query, offline),
+            Commands::Export {
+                global,
+                project,
+                output,
+            } => export::run(global, project, output),
+            Commands::Cache { command } => match command {
+                CacheCommands::Gc {
+                    dry_run,
+                    max_size,
+                    max_age,
+                } => cache::gc(dry_run, max_size, max_age),
+                CacheCommands::Size => cache::size(),
+                CacheCommands::Ls => cache::ls(),
+            },
+            Commands::Doctor { fix } => doctor::run(fix),
         }
     }
 }
+
+/// How cached repositories are fetched: never with `--offline`, otherwise
+/// honoring the `fetch_ttl` of the first manifest that sets one.
+fn fetch_policy<'a>(offline: bool, manifests: impl IntoIterator<Item = &'a Manifest>) -> FetchPolicy {
+    if offline {
+        return FetchPolicy::Offline;
+    }
+    manifests
+        .into_iter()
+        .find_map(|m| m.settings.fetch_ttl)
+        .map_or(FetchPolicy::Always, |minutes| {
+            FetchPolicy::Ttl(Duration::from_secs(minutes * 60))
+        })
+}
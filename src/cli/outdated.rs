@@ -0,0 +1,342 @@
+use git2::Repository;
+use semver::VersionReq;
+
+use super::fetch_policy;
+use crate::config::{LockFile, LockedMarketplace, LockedPackage, Manifest, PluginEntry};
+use crate::installer::CacheManager;
+use crate::resolver::{sparse_path, MarketplaceResolver, PluginSource, ResolvedPlugin};
+use crate::{Error, Result};
+
+/// Show locked plugins next to the newest versions upstream.
+///
+/// Marketplaces and plugin repositories are fetched, but lock files are not
+/// changed. Marketplace clones are checked out at their locked commit again
+/// afterwards.
+pub fn run(offline: bool) -> Result<()> {
+    // Load manifests
+    let global_manifest = Manifest::load_global()?;
+    let project_manifest = Manifest::load_project()?;
+
+    if global_manifest.is_none() && project_manifest.is_none() {
+        return Err(Error::NoManifest);
+    }
+
+    let cache = CacheManager::new()?;
+    cache.ensure_cache_dir()?;
+    let policy = fetch_policy(offline, project_manifest.iter().chain(&global_manifest));
+    let resolver = MarketplaceResolver::new(cache.cache_dir().to_path_buf()).with_fetch_policy(policy);
+
+    let manifests = [("Project", project_manifest), ("Global", global_manifest)];
+    let mut first = true;
+    for (label, manifest) in manifests {
+        let Some(manifest) = manifest else {
+            continue;
+        };
+        if !first {
+            println!();
+        }
+        first = false;
+
+        let manifest_path = manifest.path.clone().ok_or(Error::NoManifest)?;
+        println!("{} plugins ({}):", label, manifest_path.display());
+
+        let lock_path = LockFile::path_for_manifest(&manifest_path);
+        let Some(lock) = LockFile::load_if_exists(&lock_path)? else {
+            println!("  [no lock file] Run `skill-manager install` first.");
+            continue;
+        };
+
+        let rows = outdated_rows(&manifest, &lock, &resolver.for_manifest(&manifest))?;
+        if rows.is_empty() {
+            println!("  (none)");
+        } else {
+            print_table(&rows);
+        }
+    }
+
+    Ok(())
+}
+
+/// A locked plugin version: display version and commit.
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct PluginVersion {
+    version: String,
+    commit: String,
+}
+
+impl PluginVersion {
+    fn locked(pkg: &LockedPackage) -> Self {
+        Self {
+            version: pkg.resolved_version.clone(),
+            commit: pkg.plugin_commit.clone(),
+        }
+    }
+
+    fn resolved(plugin: &ResolvedPlugin) -> Self {
+        Self {
+            version: plugin.resolved_version.clone(),
+            commit: plugin.plugin_commit.clone(),
+        }
+    }
+
+    fn describe(&self) -> String {
+        format!("{} ({})", self.version, &self.commit[..7.min(self.commit.len())])
+    }
+}
+
+/// One row of the outdated table.
+struct OutdatedRow {
+    plugin: String,
+    locked: PluginVersion,
+    /// Newest version allowed by the manifest pin, if it could be resolved.
+    wanted: Option<PluginVersion>,
+    /// Newest tag or default-branch HEAD, if it could be resolved.
+    latest: Option<PluginVersion>,
+}
+
+/// Resolve the wanted and latest version of every locked package in the manifest.
+fn outdated_rows(manifest: &Manifest, lock: &LockFile, resolver: &MarketplaceResolver) -> Result<Vec<OutdatedRow>> {
+    let mut rows = Vec::new();
+
+    for locked_marketplace in &lock.marketplaces {
+        let Some(entry) = manifest.marketplaces.get(&locked_marketplace.name) else {
+            continue;
+        };
+
+        let mut packages: Vec<&LockedPackage> = lock
+            .packages
+            .iter()
+            .filter(|pkg| pkg.marketplace == locked_marketplace.name && manifest.plugins.contains_key(&pkg.name))
+            .collect();
+        if packages.is_empty() {
+            continue;
+        }
+        packages.sort_by(|a, b| a.name.cmp(&b.name));
+
+        // A directory marketplace's wanted and latest state is what is on disk now
+        let (repo, wanted_commit, latest_commit) = match entry.directory() {
+            Some(dir) => {
+                let commit = resolver.resolve_directory(&locked_marketplace.name, dir)?;
+                (None, commit.clone(), commit)
+            }
+            None => {
+                let (repo, wanted_commit) = resolver.resolve_marketplace(&locked_marketplace.name, entry)?;
+                let latest_commit = resolver.resolve_head(&repo)?;
+                (Some(repo), wanted_commit, latest_commit)
+            }
+        };
+
+        for pkg in packages {
+            let plugin_entry = &manifest.plugins[&pkg.name];
+            let versions = MarketplaceVersions {
+                resolver,
+                repo: repo.as_ref(),
+                locked: locked_marketplace,
+            };
+
+            let wanted = versions
+                .resolve(pkg, &wanted_commit, plugin_entry)
+                .inspect_err(|e| eprintln!("Warning: could not resolve wanted version of '{}': {}", pkg.name, e))
+                .ok();
+            let latest_entry = PluginEntry {
+                marketplace: pkg.marketplace.clone(),
+                version: Some(VersionReq::STAR),
+                ..PluginEntry::default()
+            };
+            let latest = versions
+                .resolve(pkg, &latest_commit, &latest_entry)
+                .inspect_err(|e| eprintln!("Warning: could not resolve latest version of '{}': {}", pkg.name, e))
+                .ok();
+
+            rows.push(OutdatedRow {
+                plugin: pkg.name.clone(),
+                locked: PluginVersion::locked(pkg),
+                wanted,
+                latest,
+            });
+        }
+
+        // Leave the clone Claude Code reads from at the locked commit
+        if let Some(ref repo) = repo {
+            resolver.checkout_commit(repo, &locked_marketplace.name, &locked_marketplace.commit)?;
+        }
+    }
+
+    // Git plugins have no marketplace; their repository is checked directly
+    for pkg in &lock.packages {
+        let (Some(url), Some(entry)) = (&pkg.git, manifest.plugins.get(&pkg.name)) else {
+            continue;
+        };
+
+        let wanted = resolve_git(resolver, pkg, url, entry)
+            .inspect_err(|e| eprintln!("Warning: could not resolve wanted version of '{}': {}", pkg.name, e))
+            .ok();
+        let latest_entry = PluginEntry {
+            version: Some(VersionReq::STAR),
+            ..PluginEntry::default()
+        };
+        let latest = resolve_git(resolver, pkg, url, &latest_entry)
+            .inspect_err(|e| eprintln!("Warning: could not resolve latest version of '{}': {}", pkg.name, e))
+            .ok();
+
+        rows.push(OutdatedRow {
+            plugin: pkg.name.clone(),
+            locked: PluginVersion::locked(pkg),
+            wanted,
+            latest,
+        });
+    }
+
+    rows.sort_by(|a, b| a.plugin.cmp(&b.plugin));
+    Ok(rows)
+}
+
+/// Resolve a git plugin, falling back to its default-branch HEAD when the
+/// entry asks for any version but the repository has no version tags.
+fn resolve_git(resolver: &MarketplaceResolver, pkg: &LockedPackage, url: &str, entry: &PluginEntry) -> Result<PluginVersion> {
+    let subdir = pkg.subdir.as_deref();
+    let resolved = match resolver.resolve_git_plugin(&pkg.name, url, subdir, entry) {
+        Err(Error::NoMatchingVersion { .. }) if entry.version == Some(VersionReq::STAR) => {
+            resolver.resolve_git_plugin(&pkg.name, url, subdir, &PluginEntry::default())?
+        }
+        result => result?,
+    };
+    Ok(PluginVersion::resolved(&resolved))
+}
+
+/// Resolves plugin versions at different commits of one marketplace.
+struct MarketplaceVersions<'a> {
+    resolver: &'a MarketplaceResolver,
+    /// The marketplace clone; `None` for directory marketplaces.
+    repo: Option<&'a Repository>,
+    locked: &'a LockedMarketplace,
+}
+
+impl MarketplaceVersions<'_> {
+    /// Resolve a package at a marketplace commit.
+    ///
+    /// External plugins fall back to their default-branch HEAD when the entry
+    /// asks for a version but the repository has no version tags. Local
+    /// plugins whose subdirectory is unchanged since the locked marketplace
+    /// commit keep their locked version.
+    fn resolve(&self, pkg: &LockedPackage, marketplace_commit: &str, entry: &PluginEntry) -> Result<PluginVersion> {
+        let name = &self.locked.name;
+        let mkt_json = self.resolver.marketplace_json_at(name, &self.locked.url, marketplace_commit)?;
+        let plugin_info = self.resolver.find_plugin(&mkt_json, name, &pkg.name)?;
+
+        if let (PluginSource::Local(path), Some(repo)) = (&plugin_info.source, self.repo)
+            && !subdir_changed(self.resolver, repo, name, path, &pkg.marketplace_commit, marketplace_commit)?
+        {
+            return Ok(PluginVersion::locked(pkg));
+        }
+
+        let resolved = match self
+            .resolver
+            .resolve_plugin(name, marketplace_commit, &pkg.name, plugin_info, entry)
+        {
+            Err(Error::NoMatchingVersion { .. }) if entry.version == Some(VersionReq::STAR) => {
+                let head = PluginEntry {
+                    marketplace: pkg.marketplace.clone(),
+                    ..PluginEntry::default()
+                };
+                self.resolver
+                    .resolve_plugin(name, marketplace_commit, &pkg.name, plugin_info, &head)?
+            }
+            result => result?,
+        };
+
+        Ok(PluginVersion::resolved(&resolved))
+    }
+}
+
+/// Check whether a subdirectory differs between two commits of a marketplace.
+fn subdir_changed(
+    resolver: &MarketplaceResolver,
+    repo: &Repository,
+    marketplace: &str,
+    path: &str,
+    from: &str,
+    to: &str,
+) -> Result<bool> {
+    if from == to {
+        return Ok(false);
+    }
+
+    // A path outside the repository cannot be resolved at either commit
+    let Some(path) = sparse_path(path) else {
+        return Ok(false);
+    };
+    let tree_id = |commit: &str| -> Result<Option<git2::Oid>> {
+        let tree = resolver.find_commit(repo, marketplace, commit)?.tree()?;
+        Ok(tree.get_path(&path).ok().map(|entry| entry.id()))
+    };
+
+    Ok(tree_id(from)? != tree_id(to)?)
+}
+
+/// Print the rows as an aligned table, marking outdated plugins.
+fn print_table(rows: &[OutdatedRow]) {
+    let cell = |v: &Option<PluginVersion>| v.as_ref().map_or_else(|| "-".to_string(), PluginVersion::describe);
+    let table: Vec<[String; 4]> = rows
+        .iter()
+        .map(|row| {
+            [
+                row.plugin.clone(),
+                row.locked.describe(),
+                cell(&row.wanted),
+                cell(&row.latest),
+            ]
+        })
+        .collect();
+
+    let header = ["Plugin", "Locked", "Wanted", "Latest"].map(str::to_string);
+    let mut widths = header.clone().map(|h| h.len());
+    for cells in &table {
+        for (width, cell) in widths.iter_mut().zip(cells) {
+            *width = (*width).max(cell.len());
+        }
+    }
+
+    let format_row = |cells: &[String; 4]| {
+        let padded: Vec<String> = cells
+            .iter()
+            .zip(widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        format!("  {}", padded.join("  ")).trim_end().to_string()
+    };
+
+    println!("{}", format_row(&header));
+    for (row, cells) in rows.iter().zip(&table) {
+        let outdated = [&row.wanted, &row.latest]
+            .iter()
+            .any(|v| v.as_ref().is_some_and(|v| v.commit != row.locked.commit));
+        let line = format_row(cells);
+        if outdated {
+            println!("{}  *", line);
+        } else {
+            println!("{}", line);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::commit_files;
+
+    #[test]
+    fn test_subdir_changed() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo = Repository::init(temp_dir.path()).unwrap();
+
+        let first = commit_files(&repo, &[("plugins/foo/plugin.json", "1"), ("plugins/bar/plugin.json", "1")], "first");
+        let second = commit_files(&repo, &[("plugins/bar/plugin.json", "2")], "second");
+
+        let resolver = MarketplaceResolver::new(temp_dir.path().join("cache"));
+        let changed = |path, from, to| subdir_changed(&resolver, &repo, "test", path, from, to).unwrap();
+        assert!(!changed("./plugins/foo", &first, &second));
+        assert!(changed("./plugins/bar", &first, &second));
+        assert!(!changed("plugins/bar", &second, &second));
+    }
+}
@@ -40,7 +40,7 @@ pub fn run(name: String) -> Result<()> {
     })?;
 
     println!("Removed {} from {}", name, manifest_path.display());
-    println!("Note: The plugin is still installed. Run `skill-manager install` to sync.");
+    println!("Note: The plugin is still installed. Run `skill-manager install --prune` to uninstall it.");
 
     Ok(())
 }
@@ -52,10 +52,10 @@ fn find_manifest() -> Result<std::path::PathBuf> {
         return Ok(project_path);
     }
 
-    if let Some(global_path) = Manifest::global_path() {
-        if global_path.exists() {
-            return Ok(global_path);
-        }
+    if let Some(global_path) = Manifest::global_path()
+        && global_path.exists()
+    {
+        return Ok(global_path);
     }
 
     Err(Error::NoManifest)
@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+
+use super::fetch_policy;
+use crate::config::{Manifest, MarketplaceEntry};
+use crate::installer::CacheManager;
+use crate::resolver::{FetchPolicy, MarketplaceResolver, SearchIndex};
+use crate::{Error, Result};
+
+/// Search the declared marketplaces for plugins matching a query.
+pub fn run(query: String, offline: bool) -> Result<()> {
+    // Load manifests
+    let global_manifest = Manifest::load_global()?;
+    let project_manifest = Manifest::load_project()?;
+
+    if global_manifest.is_none() && project_manifest.is_none() {
+        return Err(Error::NoManifest);
+    }
+
+    let policy = fetch_policy(offline, project_manifest.iter().chain(&global_manifest));
+
+    // Project declarations take precedence over global ones with the same name
+    let mut marketplaces = HashMap::new();
+    for manifest in [global_manifest, project_manifest].into_iter().flatten() {
+        marketplaces.extend(manifest.marketplaces);
+    }
+
+    let index = load_index(&marketplaces, policy)?;
+
+    let matches: Vec<_> = index
+        .search(&query)
+        .into_iter()
+        .filter(|m| marketplaces.contains_key(m.marketplace))
+        .collect();
+
+    if matches.is_empty() {
+        println!("No plugins matching '{}'", query);
+        return Ok(());
+    }
+
+    for m in matches {
+        match m.plugin.description {
+            Some(ref description) => println!("{} ({}) - {}", m.plugin.name, m.marketplace, description),
+            None => println!("{} ({})", m.plugin.name, m.marketplace),
+        }
+    }
+
+    Ok(())
+}
+
+/// Refresh the search index for the given marketplaces and save it.
+///
+/// Marketplaces that cannot be fetched keep their cached index entry, so
+/// searching works offline once a marketplace has been indexed.
+pub(super) fn load_index(marketplaces: &HashMap<String, MarketplaceEntry>, policy: FetchPolicy) -> Result<SearchIndex> {
+    let cache = CacheManager::new()?;
+    cache.ensure_cache_dir()?;
+
+    let resolver = MarketplaceResolver::new(cache.cache_dir().to_path_buf()).with_fetch_policy(policy);
+    let index_path = SearchIndex::path(cache.cache_dir());
+    let mut index = SearchIndex::load(&index_path)?;
+
+    let mut names: Vec<&String> = marketplaces.keys().collect();
+    names.sort();
+
+    for name in names {
+        let entry = &marketplaces[name];
+        match resolver.index_marketplace(name, entry) {
+            Ok(indexed) => {
+                index.marketplaces.insert(name.clone(), indexed);
+            }
+            Err(e) => match index.marketplaces.get(name) {
+                Some(cached) if cached.url == entry.url => {
+                    eprintln!("Warning: could not fetch marketplace '{}', using cached index: {}", name, e);
+                }
+                _ => {
+                    index.marketplaces.remove(name);
+                    eprintln!("Warning: could not index marketplace '{}': {}", name, e);
+                }
+            },
+        }
+    }
+
+    index.save(&index_path)?;
+    Ok(index)
+}
@@ -0,0 +1,154 @@
+use std::collections::HashSet;
+
+use crate::config::{LockFile, LockedPackage, Manifest};
+use crate::Result;
+
+/// Show the locked plugins of each manifest as a dependency tree.
+pub fn run() -> Result<()> {
+    // Load manifests
+    let global_manifest = Manifest::load_global()?;
+    let project_manifest = Manifest::load_project()?;
+
+    if global_manifest.is_none() && project_manifest.is_none() {
+        println!("No plugins.toml found. Run `skill-manager init` to create one.");
+        return Ok(());
+    }
+
+    let manifests = [("Project", project_manifest), ("Global", global_manifest)];
+    let mut first = true;
+    for (label, manifest) in manifests {
+        let Some(manifest) = manifest else {
+            continue;
+        };
+        if !first {
+            println!();
+        }
+        first = false;
+
+        let manifest_path = manifest.path.as_ref().unwrap();
+        println!("{} plugins ({}):", label, manifest_path.display());
+
+        let lock_path = LockFile::path_for_manifest(manifest_path);
+        let Some(lock) = LockFile::load_if_exists(&lock_path)? else {
+            println!("  [no lock file] Run `skill-manager install` first.");
+            continue;
+        };
+
+        let lines = render_tree(&lock);
+        if lines.is_empty() {
+            println!("  (none)");
+        }
+        for line in lines {
+            println!("  {}", line);
+        }
+    }
+
+    Ok(())
+}
+
+/// Render the lock's packages as a tree rooted at the directly declared ones.
+///
+/// A package whose dependencies were already shown is marked with `(*)`
+/// instead of repeating its subtree.
+fn render_tree(lock: &LockFile) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut shown = HashSet::new();
+    for pkg in lock.packages.iter().filter(|p| p.direct) {
+        render_package(lock, pkg, "", "", &mut shown, &mut lines);
+    }
+    lines
+}
+
+fn render_package<'a>(
+    lock: &'a LockFile,
+    pkg: &'a LockedPackage,
+    prefix: &str,
+    child_prefix: &str,
+    shown: &mut HashSet<&'a str>,
+    lines: &mut Vec<String>,
+) {
+    let repeated = !pkg.dependencies.is_empty() && !shown.insert(pkg.name.as_str());
+    lines.push(format!(
+        "{}{} v{} ({}){}",
+        prefix,
+        pkg.name,
+        pkg.resolved_version,
+        pkg.marketplace,
+        if repeated { " (*)" } else { "" }
+    ));
+    if repeated {
+        return;
+    }
+
+    let dependencies: Vec<&LockedPackage> = pkg
+        .dependencies
+        .iter()
+        .filter_map(|name| lock.find_package(name))
+        .collect();
+    for (index, dependency) in dependencies.iter().enumerate() {
+        let last = index + 1 == dependencies.len();
+        let (branch, indent) = if last { ("└── ", "    ") } else { ("├── ", "│   ") };
+        render_package(
+            lock,
+            dependency,
+            &format!("{}{}", child_prefix, branch),
+            &format!("{}{}", child_prefix, indent),
+            shown,
+            lines,
+        );
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::SourceType;
+    use std::collections::BTreeMap;
+
+    fn package(name: &str, dependencies: &[&str], direct: bool) -> LockedPackage {
+        LockedPackage {
+            name: name.to_string(),
+            marketplace: "dev".to_string(),
+            source_type: SourceType::Local,
+            marketplace_commit: "abc1234".to_string(),
+            plugin_commit: "abc1234".to_string(),
+            resolved_version: "1.0.0".to_string(),
+            version_req: None,
+            tag: None,
+            branch: None,
+            git: None,
+            subdir: None,
+            content_hash: None,
+            fingerprint: None,
+            dependencies: dependencies.iter().map(|d| d.to_string()).collect(),
+            requirements: BTreeMap::new(),
+            direct,
+        }
+    }
+
+    #[test]
+    fn test_render_tree() {
+        let lock = LockFile {
+            packages: vec![
+                package("lsp", &["util"], false),
+                package("review", &["lsp"], true),
+                package("skills", &["lsp", "snippets"], true),
+                package("snippets", &[], false),
+                package("util", &[], false),
+            ],
+            ..LockFile::default()
+        };
+
+        assert_eq!(
+            render_tree(&lock),
+            vec![
+                "review v1.0.0 (dev)",
+                "└── lsp v1.0.0 (dev)",
+                "    └── util v1.0.0 (dev)",
+                "skills v1.0.0 (dev)",
+                "├── lsp v1.0.0 (dev) (*)",
+                "└── snippets v1.0.0 (dev)",
+            ]
+        );
+    }
+}
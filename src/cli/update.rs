@@ -0,0 +1,239 @@
+use std::collections::BTreeSet;
+
+use super::dependencies::{self, PendingDependencies};
+use super::fetch_policy;
+use super::install::{lock_diff, record_fingerprints, resolve_entry, resolve_marketplace_entry, short_commit};
+use crate::config::{LockFile, LockedPackage, Manifest};
+use crate::installer::CacheManager;
+use crate::resolver::MarketplaceResolver;
+use crate::{Error, Result};
+
+/// Re-resolve the named plugins and marketplaces in the lock files.
+///
+/// Every other lock entry is left untouched. With `with_marketplace`, the
+/// marketplaces of the named plugins are moved as well, and the plugins are
+/// resolved at the new marketplace commit.
+pub fn run(plugins: Vec<String>, with_marketplace: bool, marketplaces: Vec<String>, offline: bool) -> Result<()> {
+    // Load manifests
+    let global_manifest = Manifest::load_global()?;
+    let project_manifest = Manifest::load_project()?;
+
+    if global_manifest.is_none() && project_manifest.is_none() {
+        return Err(Error::NoManifest);
+    }
+
+    let manifests: Vec<Manifest> = [project_manifest, global_manifest].into_iter().flatten().collect();
+
+    // Every name must be declared in at least one manifest
+    for name in &plugins {
+        if !manifests.iter().any(|m| m.plugins.contains_key(name)) {
+            return Err(Error::PluginNotInManifest(name.clone()));
+        }
+    }
+    for name in &marketplaces {
+        if !manifests.iter().any(|m| m.marketplaces.contains_key(name)) {
+            return Err(Error::UndeclaredMarketplace(name.clone()));
+        }
+    }
+
+    let cache = CacheManager::new()?;
+    cache.ensure_cache_dir()?;
+    let resolver =
+        MarketplaceResolver::new(cache.cache_dir().to_path_buf()).with_fetch_policy(fetch_policy(offline, &manifests));
+
+    let mut updated = false;
+    for manifest in &manifests {
+        let selected_plugins: BTreeSet<&str> = plugins
+            .iter()
+            .filter(|name| manifest.plugins.contains_key(*name))
+            .map(String::as_str)
+            .collect();
+        let mut selected_marketplaces: BTreeSet<&str> = marketplaces
+            .iter()
+            .filter(|name| manifest.marketplaces.contains_key(*name))
+            .map(String::as_str)
+            .collect();
+        if with_marketplace {
+            selected_marketplaces.extend(
+                selected_plugins
+                    .iter()
+                    .filter(|name| manifest.plugins[**name].git.is_none())
+                    .map(|name| manifest.plugins[*name].marketplace.as_str()),
+            );
+        }
+        if selected_plugins.is_empty() && selected_marketplaces.is_empty() {
+            continue;
+        }
+
+        let manifest_path = manifest.path.clone().ok_or(Error::NoManifest)?;
+        manifest.validate()?;
+        let lock_path = LockFile::path_for_manifest(&manifest_path);
+        let mut lock = LockFile::load_if_exists(&lock_path)?.ok_or_else(|| Error::LockNotFound(lock_path.clone()))?;
+        let original = lock.to_string()?;
+
+        // Only a lock matching the manifest can be updated entry by entry
+        let current_hash = manifest.compute_hash();
+        if lock.config_hash.as_ref() != Some(&current_hash) {
+            if lock.config_hash != Some(manifest.compute_legacy_hash()) {
+                return Err(Error::LockOutdated {
+                    path: lock_path,
+                    changes: lock_diff(manifest, &lock).join("\n"),
+                });
+            }
+            record_fingerprints(manifest, &mut lock);
+            lock.config_hash = Some(current_hash);
+        }
+
+        println!("Updating {}...", lock_path.display());
+        let manifest_resolver = resolver.for_manifest(manifest);
+        for change in update_lock(manifest, &mut lock, &selected_plugins, &selected_marketplaces, &manifest_resolver)? {
+            println!("  {}", change);
+        }
+
+        if lock.to_string()? != original {
+            lock.save(&lock_path)?;
+            println!("Wrote {}", lock_path.display());
+            updated = true;
+        }
+    }
+
+    if updated {
+        println!("\nRun `skill-manager install` to install the updated plugins.");
+    }
+
+    Ok(())
+}
+
+/// Re-resolve the selected entries of a lock in place, marketplaces first.
+///
+/// Entries whose resolved commits did not change are kept as they are, so
+/// their recorded content hashes survive. Dependencies of updated plugins are
+/// resolved afterwards. Returns one line per selected entry describing the
+/// change.
+fn update_lock(
+    manifest: &Manifest,
+    lock: &mut LockFile,
+    plugins: &BTreeSet<&str>,
+    marketplaces: &BTreeSet<&str>,
+    resolver: &MarketplaceResolver,
+) -> Result<Vec<String>> {
+    let previous = lock.clone();
+    let mut changes = Vec::new();
+
+    for &name in marketplaces {
+        let entry = &manifest.marketplaces[name];
+        let updated = resolve_marketplace_entry(name, entry, resolver)?;
+        let locked = lock
+            .marketplaces
+            .iter_mut()
+            .find(|m| m.name == name)
+            .ok_or_else(|| Error::UndeclaredMarketplace(name.to_string()))?;
+
+        if locked.commit == updated.commit {
+            changes.push(format!("marketplace {}: {} (up to date)", name, short_commit(&locked.commit)));
+        } else {
+            changes.push(format!(
+                "marketplace {}: {} → {}",
+                name,
+                short_commit(&locked.commit),
+                short_commit(&updated.commit)
+            ));
+            *locked = updated;
+        }
+    }
+
+    let mut pending: PendingDependencies = Vec::new();
+    for &name in plugins {
+        let entry = &manifest.plugins[name];
+        let (updated, dependencies) = resolve_entry(name, entry, &lock.marketplaces, resolver)?;
+        let locked = lock
+            .packages
+            .iter_mut()
+            .find(|p| p.name == name)
+            .ok_or_else(|| Error::PluginNotInManifest(name.to_string()))?;
+
+        if locked.plugin_commit == updated.plugin_commit && locked.marketplace_commit == updated.marketplace_commit {
+            changes.push(format!("{}: {} (up to date)", name, describe_version(locked)));
+        } else {
+            changes.push(format!("{}: {} → {}", name, describe_version(locked), describe_version(&updated)));
+            *locked = updated;
+            pending.push((name.to_string(), dependencies));
+        }
+    }
+
+    // New dependencies are pulled in; ones nothing needs anymore are dropped
+    dependencies::resolve_dependencies(&lock.marketplaces, &mut lock.packages, pending, Some(&previous), resolver)?;
+
+    Ok(changes)
+}
+
+/// Describe a locked package's version and commit, e.g. `1.0.0 (abc1234)`.
+fn describe_version(pkg: &LockedPackage) -> String {
+    format!("{} ({})", pkg.resolved_version, short_commit(&pkg.plugin_commit))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::{commit_files, marketplace_files};
+    use git2::Repository;
+
+    /// Commit local plugins `foo` and `bar` at the given versions.
+    fn commit_plugins(repo: &Repository, foo: &str, bar: &str) -> String {
+        commit_files(repo, &marketplace_files(&[("foo", foo, ""), ("bar", bar, "")]), "update")
+    }
+
+    #[test]
+    fn test_update_lock_only_changes_selected_entries() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let upstream_dir = temp_dir.path().join("upstream");
+        let upstream = Repository::init(&upstream_dir).unwrap();
+        let first = commit_plugins(&upstream, "1.0.0", "1.0.0");
+
+        let manifest = Manifest::parse(&format!(
+            "[marketplaces]\ndev = \"file://{}\"\n\n[plugins]\nfoo = {{ marketplace = \"dev\" }}\nbar = {{ marketplace = \"dev\" }}\n",
+            upstream_dir.display()
+        ))
+        .unwrap();
+        let resolver = MarketplaceResolver::new(temp_dir.path().join("cache"));
+        let mut lock = LockFile::default();
+        for (name, entry) in &manifest.marketplaces {
+            lock.marketplaces
+                .push(resolve_marketplace_entry(name, entry, &resolver).unwrap());
+        }
+        for name in ["bar", "foo"] {
+            let (mut pkg, _) = resolve_entry(name, &manifest.plugins[name], &lock.marketplaces, &resolver).unwrap();
+            pkg.content_hash = Some(format!("sha256-{}", name));
+            lock.packages.push(pkg);
+        }
+        let second = commit_plugins(&upstream, "1.1.0", "1.1.0");
+        let resolver = MarketplaceResolver::new(temp_dir.path().join("cache"));
+
+        // Without the marketplace, a local plugin stays at the locked marketplace commit
+        let original = lock.clone();
+        let changes = update_lock(&manifest, &mut lock, &BTreeSet::from(["foo"]), &BTreeSet::new(), &resolver).unwrap();
+        assert_eq!(changes, vec![format!("foo: 1.0.0 ({}) (up to date)", &first[..7])]);
+        assert_eq!(lock.to_string().unwrap(), original.to_string().unwrap());
+
+        // With it, only the marketplace and the named plugin move
+        let changes = update_lock(
+            &manifest,
+            &mut lock,
+            &BTreeSet::from(["foo"]),
+            &BTreeSet::from(["dev"]),
+            &resolver,
+        )
+        .unwrap();
+        assert_eq!(
+            changes,
+            vec![
+                format!("marketplace dev: {} → {}", &first[..7], &second[..7]),
+                format!("foo: 1.0.0 ({}) → 1.1.0 ({})", &first[..7], &second[..7]),
+            ]
+        );
+        assert_eq!(lock.marketplaces[0].commit, second);
+        assert_eq!(lock.packages[0], original.packages[0]);
+        assert_eq!(lock.packages[1].plugin_commit, second);
+        assert_eq!(lock.packages[1].content_hash, None);
+    }
+}
@@ -1,4 +1,5 @@
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
 use crate::{Error, Result};
@@ -14,8 +15,20 @@ const LOCK_HEADER: &str = "# DO NOT EDIT - Generated by skill-manager\n\
 #[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct LockedMarketplace {
     pub name: String,
+    /// Git URL; empty for directory marketplaces.
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub url: String,
+    /// Directory the marketplace is read from. Its `commit` is then a hash
+    /// of the directory's contents.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub path: Option<PathBuf>,
     pub commit: String,
+    /// Branch the commit was resolved from, if the marketplace follows one.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Fingerprint of the manifest entry this marketplace was resolved from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
 }
 
 /// Source type for a plugin.
@@ -38,6 +51,58 @@ pub struct LockedPackage {
     pub plugin_commit: String,
     /// Human-readable version from plugin.json (display only).
     pub resolved_version: String,
+    /// Semver requirement from the manifest, if the plugin was resolved by version.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version_req: Option<String>,
+    /// Tag the plugin commit was resolved from, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    /// Branch the plugin commit was resolved from, if any.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub branch: Option<String>,
+    /// Repository of a plugin installed straight from git rather than from a
+    /// marketplace. Its `marketplace` is then the generated git marketplace.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub git: Option<String>,
+    /// Directory of a git plugin within its repository.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub subdir: Option<String>,
+    /// Hash of the extracted plugin files, recorded on first install.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_hash: Option<String>,
+    /// Fingerprint of the manifest entry this package was resolved from.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub fingerprint: Option<String>,
+    /// Names of the plugins this package depends on.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub dependencies: Vec<String>,
+    /// What this package's plugin.json asks of each dependency, so the
+    /// dependency can be checked again when it is re-resolved.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub requirements: BTreeMap<String, LockedRequirement>,
+    /// Whether the package is declared in the manifest rather than pulled in
+    /// as a dependency. Only written when false.
+    #[serde(default = "default_direct", skip_serializing_if = "is_direct")]
+    pub direct: bool,
+}
+
+/// A dependency declaration, with its marketplace defaulted to the
+/// dependent's.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LockedRequirement {
+    pub marketplace: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub tag: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub version: Option<String>,
+}
+
+fn default_direct() -> bool {
+    true
+}
+
+fn is_direct(direct: &bool) -> bool {
+    *direct
 }
 
 /// The plugins.lock file structure.
@@ -151,6 +216,10 @@ resolved_version = "1.0.0"
         let pkg = &lockfile.packages[0];
         assert_eq!(pkg.name, "typescript-lsp");
         assert_eq!(pkg.source_type, SourceType::Local);
+        assert_eq!(pkg.version_req, None);
+        assert_eq!(pkg.tag, None);
+        assert!(pkg.dependencies.is_empty());
+        assert!(pkg.direct);
     }
 
     #[test]
@@ -160,7 +229,10 @@ resolved_version = "1.0.0"
             marketplaces: vec![LockedMarketplace {
                 name: "official".to_string(),
                 url: "https://github.com/anthropics/claude-plugins-official.git".to_string(),
+                path: None,
                 commit: "abc123".to_string(),
+                branch: None,
+                fingerprint: None,
             }],
             packages: vec![LockedPackage {
                 name: "superpowers".to_string(),
@@ -169,6 +241,16 @@ resolved_version = "1.0.0"
                 marketplace_commit: "abc123".to_string(),
                 plugin_commit: "def456".to_string(),
                 resolved_version: "4.1.1".to_string(),
+                version_req: Some("^4.1".to_string()),
+                tag: Some("v4.1.1".to_string()),
+                branch: None,
+                git: None,
+                subdir: None,
+                content_hash: None,
+                fingerprint: None,
+                dependencies: vec![],
+                requirements: BTreeMap::new(),
+                direct: true,
             }],
             path: None,
         };
@@ -177,6 +259,10 @@ resolved_version = "1.0.0"
         assert!(content.starts_with("# DO NOT EDIT"));
         assert!(content.contains("[[marketplace]]"));
         assert!(content.contains("[[package]]"));
+        assert!(content.contains("version_req = \"^4.1\""));
+        assert!(content.contains("tag = \"v4.1.1\""));
+        assert!(!content.contains("dependencies"));
+        assert!(!content.contains("direct"));
     }
 
     #[test]
@@ -186,7 +272,10 @@ resolved_version = "1.0.0"
             marketplaces: vec![LockedMarketplace {
                 name: "test".to_string(),
                 url: "https://example.com/repo.git".to_string(),
+                path: None,
                 commit: "abc123".to_string(),
+                branch: Some("staging".to_string()),
+                fingerprint: Some("0123456789abcdef".to_string()),
             }],
             packages: vec![LockedPackage {
                 name: "plugin1".to_string(),
@@ -195,6 +284,23 @@ resolved_version = "1.0.0"
                 marketplace_commit: "abc123".to_string(),
                 plugin_commit: "abc123".to_string(),
                 resolved_version: "1.0.0".to_string(),
+                version_req: None,
+                tag: None,
+                branch: None,
+                git: None,
+                subdir: None,
+                content_hash: None,
+                fingerprint: None,
+                dependencies: vec!["lsp".to_string()],
+                requirements: BTreeMap::from([(
+                    "lsp".to_string(),
+                    LockedRequirement {
+                        marketplace: "test".to_string(),
+                        tag: None,
+                        version: Some("^1.2".to_string()),
+                    },
+                )]),
+                direct: false,
             }],
             path: None,
         };
@@ -1,25 +1,58 @@
 use rustc_hash::FxHasher;
+use semver::VersionReq;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::{BTreeMap, HashMap};
 use std::hash::Hasher;
-use std::path::{Path, PathBuf};
+use std::path::{Component, Path, PathBuf};
 
 use crate::{Error, Result};
 
-/// A marketplace URL with optional version pinning.
-#[derive(Debug, Clone, PartialEq, Eq)]
+/// A marketplace URL with optional version pinning, or a local directory.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct MarketplaceEntry {
+    /// Git URL; empty for directory marketplaces.
     pub url: String,
     pub tag: Option<String>,
     pub commit: Option<String>,
+    /// Branch to follow; resolves to the tip of `origin/<branch>`.
+    pub branch: Option<String>,
+    /// Directory the marketplace is read from instead of a git remote, as
+    /// written in the manifest. It is hashed and locked as written, so a
+    /// relative path keeps the lock the same wherever the project is checked out.
+    pub path: Option<PathBuf>,
+    /// `path` resolved against the manifest's directory, set on load.
+    /// Use [`Self::directory`] to read the marketplace.
+    pub resolved_path: Option<PathBuf>,
 }
 
 /// A plugin entry with marketplace reference and optional version pinning.
-#[derive(Debug, Clone, PartialEq, Eq)]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
 pub struct PluginEntry {
+    /// Marketplace the plugin is listed in; empty for git plugins.
     pub marketplace: String,
+    /// Repository the plugin is fetched from directly, without a marketplace.
+    pub git: Option<String>,
+    /// Directory of a git plugin within its repository.
+    pub subdir: Option<String>,
     pub tag: Option<String>,
     pub commit: Option<String>,
+    /// Branch of the plugin repository to follow.
+    pub branch: Option<String>,
+    /// Semver requirement matched against the plugin repository's tags.
+    pub version: Option<VersionReq>,
+    /// Allow prerelease tags to satisfy `version`.
+    pub prerelease: bool,
+}
+
+/// Settings from the manifest's `[settings]` table.
+///
+/// Settings only affect how skill-manager runs, not what gets resolved, so
+/// they are not part of the manifest hash.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+pub struct Settings {
+    /// Skip fetching repositories fetched within this many minutes.
+    pub fetch_ttl: Option<u64>,
 }
 
 /// The parsed plugins.toml manifest.
@@ -27,6 +60,7 @@ pub struct PluginEntry {
 pub struct Manifest {
     pub marketplaces: HashMap<String, MarketplaceEntry>,
     pub plugins: HashMap<String, PluginEntry>,
+    pub settings: Settings,
     pub path: Option<PathBuf>,
 }
 
@@ -37,6 +71,8 @@ struct RawManifest {
     marketplaces: HashMap<String, RawMarketplace>,
     #[serde(default)]
     plugins: HashMap<String, RawPlugin>,
+    #[serde(default)]
+    settings: Settings,
 }
 
 #[derive(Debug, Deserialize)]
@@ -48,35 +84,65 @@ enum RawMarketplace {
 
 #[derive(Debug, Deserialize)]
 struct MarketplaceDetails {
-    url: String,
+    url: Option<String>,
+    path: Option<PathBuf>,
     tag: Option<String>,
     commit: Option<String>,
+    branch: Option<String>,
 }
 
 #[derive(Debug, Deserialize)]
 struct RawPlugin {
-    marketplace: String,
+    marketplace: Option<String>,
+    git: Option<String>,
+    subdir: Option<String>,
     tag: Option<String>,
     commit: Option<String>,
+    branch: Option<String>,
+    version: Option<String>,
+    #[serde(default)]
+    prerelease: bool,
 }
 
 /// Manifest file locations.
 pub const MANIFEST_FILENAME: &str = "plugins.toml";
 
+/// Name of the marketplace skill-manager generates for git plugins, which
+/// Claude Code needs because it keys plugins as `name@marketplace`.
+pub const GIT_MARKETPLACE: &str = "skill-manager-git";
+
 /// Normalized marketplace entry for deterministic hashing.
+/// Optional fields added after the first release are skipped when unset,
+/// so existing manifests keep their hash.
 #[derive(Debug, Clone, Serialize)]
 struct NormalizedMarketplaceEntry {
     url: String,
     tag: Option<String>,
     commit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    branch: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    path: Option<PathBuf>,
 }
 
 /// Normalized plugin entry for deterministic hashing.
+/// Optional fields added after the first release are skipped when unset,
+/// so existing manifests keep their hash.
 #[derive(Debug, Clone, Serialize)]
 struct NormalizedPluginEntry {
     marketplace: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    git: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    subdir: Option<String>,
     tag: Option<String>,
     commit: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    branch: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    version: Option<String>,
+    #[serde(skip_serializing_if = "std::ops::Not::not")]
+    prerelease: bool,
 }
 
 /// Normalized manifest with sorted keys for deterministic hashing.
@@ -100,10 +166,10 @@ impl Manifest {
 
     /// Load the global manifest if it exists.
     pub fn load_global() -> Result<Option<Self>> {
-        if let Some(path) = Self::global_path() {
-            if path.exists() {
-                return Ok(Some(Self::load(&path)?));
-            }
+        if let Some(path) = Self::global_path()
+            && path.exists()
+        {
+            return Ok(Some(Self::load(&path)?));
         }
         Ok(None)
     }
@@ -129,13 +195,15 @@ impl Manifest {
                 let entry = match raw {
                     RawMarketplace::Simple(url) => MarketplaceEntry {
                         url: expand_github_shorthand(&url),
-                        tag: None,
-                        commit: None,
+                        ..MarketplaceEntry::default()
                     },
                     RawMarketplace::Detailed(details) => MarketplaceEntry {
-                        url: expand_github_shorthand(&details.url),
+                        url: details.url.as_deref().map(expand_github_shorthand).unwrap_or_default(),
                         tag: details.tag,
                         commit: details.commit,
+                        branch: details.branch,
+                        path: details.path,
+                        resolved_path: None,
                     },
                 };
                 (name, entry)
@@ -146,18 +214,35 @@ impl Manifest {
             .plugins
             .into_iter()
             .map(|(name, raw)| {
+                let version = raw
+                    .version
+                    .map(|v| {
+                        VersionReq::parse(&v).map_err(|e| {
+                            Error::ManifestParse(format!(
+                                "invalid version requirement '{}' for plugin '{}': {}",
+                                v, name, e
+                            ))
+                        })
+                    })
+                    .transpose()?;
                 let entry = PluginEntry {
-                    marketplace: raw.marketplace,
+                    marketplace: raw.marketplace.unwrap_or_default(),
+                    git: raw.git.as_deref().map(expand_github_shorthand),
+                    subdir: raw.subdir,
                     tag: raw.tag,
                     commit: raw.commit,
+                    branch: raw.branch,
+                    version,
+                    prerelease: raw.prerelease,
                 };
-                (name, entry)
+                Ok((name, entry))
             })
-            .collect();
+            .collect::<Result<_>>()?;
 
         Ok(Manifest {
             marketplaces,
             plugins,
+            settings: raw.settings,
             path: None,
         })
     }
@@ -169,15 +254,107 @@ impl Manifest {
             source: e,
         })?;
         let mut manifest = Self::parse(&content)?;
+
+        // Directory marketplaces are relative to the manifest, not the working directory
+        let base = path.parent().unwrap_or(Path::new(""));
+        for entry in manifest.marketplaces.values_mut() {
+            if let Some(ref dir) = entry.path {
+                entry.resolved_path = Some(resolve_dir(base, dir));
+            }
+        }
+
         manifest.path = Some(path.to_path_buf());
         Ok(manifest)
     }
 
-    /// Validate that all plugins reference declared marketplaces.
+    /// Validate that all plugins reference declared marketplaces or a git
+    /// repository, that each marketplace has either a URL or a path, and that
+    /// marketplaces and plugins pin their version at most one way.
     pub fn validate(&self) -> Result<()> {
-        for (_plugin_name, plugin) in &self.plugins {
-            if !self.marketplaces.contains_key(&plugin.marketplace) {
-                return Err(Error::UndeclaredMarketplace(plugin.marketplace.clone()));
+        for (name, marketplace) in &self.marketplaces {
+            if name == GIT_MARKETPLACE {
+                return Err(Error::ManifestParse(format!(
+                    "marketplace name '{}' is reserved for git plugins",
+                    name
+                )));
+            }
+
+            match (marketplace.url.is_empty(), &marketplace.path) {
+                (true, None) => {
+                    return Err(Error::ManifestParse(format!("marketplace '{}' needs a url or a path", name)));
+                }
+                (false, Some(_)) => {
+                    return Err(Error::ManifestParse(format!(
+                        "marketplace '{}' may set only one of url and path",
+                        name
+                    )));
+                }
+                _ => {}
+            }
+
+            let pinned = marketplace.tag.is_some() || marketplace.commit.is_some() || marketplace.branch.is_some();
+            if marketplace.path.is_some() && pinned {
+                return Err(Error::ManifestParse(format!(
+                    "marketplace '{}' is a local directory and cannot pin a tag, commit or branch",
+                    name
+                )));
+            }
+
+            let pins = [
+                marketplace.tag.is_some(),
+                marketplace.commit.is_some(),
+                marketplace.branch.is_some(),
+            ];
+            if pins.iter().filter(|&&pinned| pinned).count() > 1 {
+                return Err(Error::ManifestParse(format!(
+                    "marketplace '{}' may set only one of tag, commit and branch",
+                    name
+                )));
+            }
+        }
+
+        for (name, plugin) in &self.plugins {
+            match (&plugin.git, plugin.marketplace.is_empty()) {
+                (None, true) => {
+                    return Err(Error::ManifestParse(format!("plugin '{}' needs a marketplace or git", name)));
+                }
+                (Some(_), false) => {
+                    return Err(Error::ManifestParse(format!(
+                        "plugin '{}' may set only one of marketplace and git",
+                        name
+                    )));
+                }
+                (None, false) if !self.marketplaces.contains_key(&plugin.marketplace) => {
+                    return Err(Error::UndeclaredMarketplace(plugin.marketplace.clone()));
+                }
+                _ => {}
+            }
+            if let Some(ref subdir) = plugin.subdir {
+                if plugin.git.is_none() {
+                    return Err(Error::ManifestParse(format!("plugin '{}' sets subdir without git", name)));
+                }
+                let inside = Path::new(subdir)
+                    .components()
+                    .all(|c| matches!(c, Component::Normal(_) | Component::CurDir));
+                if !inside {
+                    return Err(Error::ManifestParse(format!(
+                        "plugin '{}' has a subdir outside its repository",
+                        name
+                    )));
+                }
+            }
+
+            let pins = [
+                plugin.tag.is_some(),
+                plugin.commit.is_some(),
+                plugin.branch.is_some(),
+                plugin.version.is_some(),
+            ];
+            if pins.iter().filter(|&&pinned| pinned).count() > 1 {
+                return Err(Error::ManifestParse(format!(
+                    "plugin '{}' may set only one of tag, commit, branch and version",
+                    name
+                )));
             }
         }
         Ok(())
@@ -185,45 +362,31 @@ impl Manifest {
 
     /// Convert to a normalized form with sorted keys for deterministic hashing.
     fn to_normalized(&self) -> NormalizedManifest {
-        let marketplaces = self
-            .marketplaces
-            .iter()
-            .map(|(name, entry)| {
-                (
-                    name.clone(),
-                    NormalizedMarketplaceEntry {
-                        url: entry.url.clone(),
-                        tag: entry.tag.clone(),
-                        commit: entry.commit.clone(),
-                    },
-                )
-            })
-            .collect();
-
-        let plugins = self
-            .plugins
-            .iter()
-            .map(|(name, entry)| {
-                (
-                    name.clone(),
-                    NormalizedPluginEntry {
-                        marketplace: entry.marketplace.clone(),
-                        tag: entry.tag.clone(),
-                        commit: entry.commit.clone(),
-                    },
-                )
-            })
-            .collect();
-
         NormalizedManifest {
-            marketplaces,
-            plugins,
+            marketplaces: self
+                .marketplaces
+                .iter()
+                .map(|(name, entry)| (name.clone(), NormalizedMarketplaceEntry::from(entry)))
+                .collect(),
+            plugins: self
+                .plugins
+                .iter()
+                .map(|(name, entry)| (name.clone(), NormalizedPluginEntry::from(entry)))
+                .collect(),
         }
     }
 
     /// Compute a deterministic hash of the manifest content.
-    /// The hash is stable across runs and independent of HashMap iteration order.
+    /// The hash is stable across runs, toolchains and crate versions, and
+    /// independent of HashMap iteration order.
     pub fn compute_hash(&self) -> String {
+        stable_hash(&self.to_normalized())
+    }
+
+    /// Compute the manifest hash written by lock files before per-entry
+    /// fingerprints were recorded, so those lock files are still recognized
+    /// as up to date.
+    pub fn compute_legacy_hash(&self) -> String {
         let normalized = self.to_normalized();
         let json = serde_json::to_string(&normalized).expect("manifest serialization should not fail");
 
@@ -233,9 +396,116 @@ impl Manifest {
     }
 }
 
+impl MarketplaceEntry {
+    /// Where the marketplace comes from, for display: its URL or directory.
+    pub fn location(&self) -> String {
+        match self.path {
+            Some(ref dir) => dir.display().to_string(),
+            None => self.url.clone(),
+        }
+    }
+
+    /// The directory a directory marketplace is read from: `path` resolved
+    /// against the manifest's directory, or as written if the manifest was
+    /// not loaded from a file.
+    pub fn directory(&self) -> Option<&Path> {
+        self.resolved_path.as_deref().or(self.path.as_deref())
+    }
+
+    /// Fingerprint of this entry, recorded in the lock to detect changes.
+    pub fn fingerprint(&self) -> String {
+        stable_hash(&NormalizedMarketplaceEntry::from(self))
+    }
+}
+
+impl PluginEntry {
+    /// The marketplace the plugin is installed under: its declared
+    /// marketplace, or [`GIT_MARKETPLACE`] for git plugins.
+    pub fn marketplace_name(&self) -> &str {
+        if self.git.is_some() {
+            GIT_MARKETPLACE
+        } else {
+            &self.marketplace
+        }
+    }
+
+    /// Where the plugin comes from, for display: its marketplace, or its
+    /// repository and subdirectory.
+    pub fn origin(&self) -> String {
+        match (&self.git, &self.subdir) {
+            (Some(url), Some(subdir)) => format!("{}, subdir: {}", url, subdir),
+            (Some(url), None) => url.clone(),
+            (None, _) => self.marketplace.clone(),
+        }
+    }
+
+    /// Fingerprint of this entry, recorded in the lock to detect changes.
+    pub fn fingerprint(&self) -> String {
+        stable_hash(&NormalizedPluginEntry::from(self))
+    }
+
+    /// Check the entry of a plugin that lives in its marketplace's own
+    /// repository, which [`Manifest::validate`] cannot tell apart before the
+    /// marketplace.json is read. Such a plugin follows the marketplace
+    /// commit, so a branch of its own means nothing.
+    pub fn validate_local(&self, name: &str) -> Result<()> {
+        if self.branch.is_some() {
+            return Err(Error::ManifestParse(format!(
+                "plugin '{}' lives in marketplace '{}' and cannot follow a branch; set the branch on the marketplace",
+                name, self.marketplace
+            )));
+        }
+        Ok(())
+    }
+}
+
+impl From<&MarketplaceEntry> for NormalizedMarketplaceEntry {
+    fn from(entry: &MarketplaceEntry) -> Self {
+        Self {
+            url: entry.url.clone(),
+            tag: entry.tag.clone(),
+            commit: entry.commit.clone(),
+            branch: entry.branch.clone(),
+            path: entry.path.clone(),
+        }
+    }
+}
+
+impl From<&PluginEntry> for NormalizedPluginEntry {
+    fn from(entry: &PluginEntry) -> Self {
+        Self {
+            marketplace: entry.marketplace.clone(),
+            git: entry.git.clone(),
+            subdir: entry.subdir.clone(),
+            tag: entry.tag.clone(),
+            commit: entry.commit.clone(),
+            branch: entry.branch.clone(),
+            version: entry.version.as_ref().map(|v| v.to_string()),
+            prerelease: entry.prerelease,
+        }
+    }
+}
+
+/// Hash the JSON form of a normalized value with SHA-256, truncated to 16 hex characters.
+fn stable_hash<T: Serialize>(value: &T) -> String {
+    let json = serde_json::to_string(value).expect("manifest serialization should not fail");
+    let digest = Sha256::digest(json.as_bytes());
+    format!("{:x}", digest)[..16].to_string()
+}
+
+/// Resolve a directory against `base` to an absolute path, canonicalized
+/// when it exists.
+fn resolve_dir(base: &Path, dir: &Path) -> PathBuf {
+    let joined = base.join(dir);
+    joined
+        .canonicalize()
+        .or_else(|_| std::path::absolute(&joined))
+        .unwrap_or(joined)
+}
+
 /// Expand GitHub shorthand (owner/repo) to full HTTPS URL.
 /// SSH and HTTPS URLs are passed through unchanged.
-fn expand_github_shorthand(url: &str) -> String {
+pub(crate) fn expand_github_shorthand(url: &str) -> String {
     if url.starts_with("git@") || url.starts_with("https://") || url.starts_with("http://") {
         url.to_string()
     } else if url.contains('/') && !url.contains(':') {
@@ -332,6 +602,238 @@ sourceatlas = { marketplace = "official", commit = "def456" }
         assert_eq!(sa.commit, Some("def456".to_string()));
     }
 
+    #[test]
+    fn test_parse_plugin_version_requirement() {
+        let content = r#"
+[marketplaces]
+official = "anthropics/claude-plugins-official"
+
+[plugins]
+superpowers = { marketplace = "official", version = "^4.1" }
+nightly = { marketplace = "official", version = ">=2.0", prerelease = true }
+"#;
+        let manifest = Manifest::parse(content).unwrap();
+
+        let sp = &manifest.plugins["superpowers"];
+        assert_eq!(sp.version, Some(VersionReq::parse("^4.1").unwrap()));
+        assert!(!sp.prerelease);
+
+        let nightly = &manifest.plugins["nightly"];
+        assert_eq!(nightly.version, Some(VersionReq::parse(">=2.0").unwrap()));
+        assert!(nightly.prerelease);
+    }
+
+    #[test]
+    fn test_parse_invalid_version_requirement() {
+        let content = r#"
+[marketplaces]
+official = "anthropics/claude-plugins-official"
+
+[plugins]
+superpowers = { marketplace = "official", version = "not-a-version" }
+"#;
+        let result = Manifest::parse(content);
+        assert!(matches!(result, Err(Error::ManifestParse(msg)) if msg.contains("superpowers")));
+    }
+
+    #[test]
+    fn test_parse_branch() {
+        let content = r#"
+[marketplaces]
+internal = { url = "git@github.com:mycompany/plugins.git", branch = "staging" }
+
+[plugins]
+superpowers = { marketplace = "internal", branch = "develop" }
+"#;
+        let manifest = Manifest::parse(content).unwrap();
+        assert!(manifest.validate().is_ok());
+        assert_eq!(manifest.marketplaces["internal"].branch, Some("staging".to_string()));
+        assert_eq!(manifest.plugins["superpowers"].branch, Some("develop".to_string()));
+    }
+
+    #[test]
+    fn test_load_directory_marketplace() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let project = temp_dir.path().join("project/.claude");
+        std::fs::create_dir_all(&project).unwrap();
+        std::fs::create_dir_all(temp_dir.path().join("project/my-marketplace")).unwrap();
+        let path = project.join(MANIFEST_FILENAME);
+        std::fs::write(
+            &path,
+            r#"
+[marketplaces]
+dev = { path = "../my-marketplace" }
+"#,
+        )
+        .unwrap();
+
+        let manifest = Manifest::load(&path).unwrap();
+        assert!(manifest.validate().is_ok());
+        let entry = &manifest.marketplaces["dev"];
+        assert!(entry.url.is_empty());
+        assert_eq!(entry.path, Some(PathBuf::from("../my-marketplace")));
+        assert_eq!(
+            entry.directory(),
+            Some(temp_dir.path().join("project/my-marketplace").canonicalize().unwrap().as_path())
+        );
+
+        for (content, message) in [
+            (r#"dev = { path = "../m", tag = "v1" }"#, "cannot pin"),
+            (r#"dev = { path = "../m", url = "owner/repo" }"#, "only one of url and path"),
+            (r#"dev = { tag = "v1" }"#, "needs a url or a path"),
+        ] {
+            let manifest = Manifest::parse(&format!("[marketplaces]\n{}", content)).unwrap();
+            assert!(
+                matches!(manifest.validate(), Err(Error::ManifestParse(ref msg)) if msg.contains(message)),
+                "{}",
+                content
+            );
+        }
+    }
+
+    #[test]
+    fn test_parse_git_plugin() {
+        let content = r#"
+[plugins]
+tools = { git = "owner/tools", subdir = "plugins/tools", tag = "v1.0" }
+"#;
+        let manifest = Manifest::parse(content).unwrap();
+        assert!(manifest.validate().is_ok());
+        let entry = &manifest.plugins["tools"];
+        assert_eq!(entry.git.as_deref(), Some("https://github.com/owner/tools.git"));
+        assert_eq!(entry.subdir.as_deref(), Some("plugins/tools"));
+        assert_eq!(entry.marketplace_name(), GIT_MARKETPLACE);
+
+        for (content, message) in [
+            (r#"tools = { git = "owner/tools", marketplace = "official" }"#, "only one of marketplace and git"),
+            (r#"tools = { tag = "v1.0" }"#, "needs a marketplace or git"),
+            (r#"tools = { git = "owner/tools", subdir = "../elsewhere" }"#, "outside its repository"),
+        ] {
+            let manifest = Manifest::parse(&format!("[marketplaces]\nofficial = \"owner/repo\"\n[plugins]\n{}", content)).unwrap();
+            assert!(
+                matches!(manifest.validate(), Err(Error::ManifestParse(ref msg)) if msg.contains(message)),
+                "{}",
+                content
+            );
+        }
+
+        let reserved = Manifest::parse(&format!("[marketplaces]\n{} = \"owner/repo\"", GIT_MARKETPLACE)).unwrap();
+        assert!(matches!(reserved.validate(), Err(Error::ManifestParse(ref msg)) if msg.contains("reserved")));
+    }
+
+    #[test]
+    fn test_parse_settings() {
+        let content = r#"
+[marketplaces]
+official = "anthropics/claude-plugins-official"
+
+[settings]
+fetch_ttl = 30
+"#;
+        let manifest = Manifest::parse(content).unwrap();
+        assert_eq!(manifest.settings.fetch_ttl, Some(30));
+
+        // Settings do not affect resolution, so they leave the hash alone
+        let without = Manifest::parse("[marketplaces]\nofficial = \"anthropics/claude-plugins-official\"\n").unwrap();
+        assert_eq!(without.settings, Settings::default());
+        assert_eq!(manifest.compute_hash(), without.compute_hash());
+    }
+
+    #[test]
+    fn test_validate_marketplace_multiple_pins() {
+        let content = r#"
+[marketplaces]
+internal = { url = "owner/repo", tag = "v1.0", branch = "staging" }
+
+[plugins]
+"#;
+        let manifest = Manifest::parse(content).unwrap();
+        assert!(matches!(manifest.validate(), Err(Error::ManifestParse(msg)) if msg.contains("internal")));
+    }
+
+    #[test]
+    fn test_validate_multiple_pins() {
+        let content = r#"
+[marketplaces]
+official = "anthropics/claude-plugins-official"
+
+[plugins]
+superpowers = { marketplace = "official", tag = "v4.1.1", version = "^4.1" }
+"#;
+        let manifest = Manifest::parse(content).unwrap();
+        assert!(matches!(manifest.validate(), Err(Error::ManifestParse(_))));
+    }
+
+    #[test]
+    fn test_hash_unchanged_without_version() {
+        // Manifests without the version fields must keep the hash they had before
+        // those fields existed, so existing lock files stay valid.
+        let content = r#"
+[marketplaces]
+official = "owner/repo"
+
+[plugins]
+plugin-a = { marketplace = "official", tag = "v1.0" }
+"#;
+        let manifest = Manifest::parse(content).unwrap();
+        let json = serde_json::to_string(&manifest.to_normalized()).unwrap();
+        assert!(!json.contains("version"));
+        assert!(!json.contains("prerelease"));
+    }
+
+    #[test]
+    fn test_hash_is_stable() {
+        // The hash is recorded in lock files and must not change between releases
+        let content = r#"
+[marketplaces]
+official = "owner/repo"
+
+[plugins]
+plugin-a = { marketplace = "official", tag = "v1.0" }
+"#;
+        let manifest = Manifest::parse(content).unwrap();
+        assert_eq!(manifest.compute_hash(), "8bb8b2000f07e5e5");
+    }
+
+    #[test]
+    fn test_fingerprints_change_only_with_their_entry() {
+        let manifest1 = Manifest::parse(
+            r#"
+[marketplaces]
+official = "owner/repo"
+
+[plugins]
+plugin-a = { marketplace = "official" }
+"#,
+        )
+        .unwrap();
+        let manifest2 = Manifest::parse(
+            r#"
+[marketplaces]
+official = "owner/repo"
+
+[plugins]
+plugin-a = { marketplace = "official" }
+plugin-b = { marketplace = "official", tag = "v1.0" }
+"#,
+        )
+        .unwrap();
+
+        assert_ne!(manifest1.compute_hash(), manifest2.compute_hash());
+        assert_eq!(
+            manifest1.marketplaces["official"].fingerprint(),
+            manifest2.marketplaces["official"].fingerprint()
+        );
+        assert_eq!(
+            manifest1.plugins["plugin-a"].fingerprint(),
+            manifest2.plugins["plugin-a"].fingerprint()
+        );
+        assert_ne!(
+            manifest2.plugins["plugin-a"].fingerprint(),
+            manifest2.plugins["plugin-b"].fingerprint()
+        );
+    }
+
     #[test]
     fn test_validate_undeclared_marketplace() {
         let content = r#"
@@ -1,5 +1,6 @@
 mod lockfile;
 mod manifest;
 
-pub use lockfile::{LockFile, LockedMarketplace, LockedPackage, SourceType, LOCK_FILENAME};
-pub use manifest::{Manifest, MarketplaceEntry, PluginEntry, MANIFEST_FILENAME};
+pub use lockfile::{LockFile, LockedMarketplace, LockedPackage, LockedRequirement, SourceType, LOCK_FILENAME};
+pub use manifest::{Manifest, MarketplaceEntry, PluginEntry, Settings, GIT_MARKETPLACE, MANIFEST_FILENAME};
+pub(crate) use manifest::expand_github_shorthand;
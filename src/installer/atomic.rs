@@ -0,0 +1,236 @@
+use std::fs::{File, OpenOptions, TryLockError};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+
+use tracing::debug;
+
+use crate::{Error, Result};
+
+/// How many times a read-modify-write cycle starts over when the file
+/// changes underneath it before giving up.
+const MAX_ATTEMPTS: usize = 5;
+
+/// An exclusive advisory lock guarding a file, released on drop.
+///
+/// The lock is taken on a hidden `.<name>.lock` file next to the guarded
+/// file rather than on the file itself, since writes replace the guarded
+/// file with a new one. The lock file is left in place after the lock is
+/// released: removing it would let a waiting process lock a file that is no
+/// longer the one others open. So `~/.claude/settings.json`, for example,
+/// gets a permanent, empty `~/.claude/.settings.json.lock` next to it.
+pub(crate) struct FileLock {
+    _file: File,
+}
+
+impl FileLock {
+    /// Block until the lock for `path` is held.
+    pub(crate) fn acquire(path: &Path) -> Result<Self> {
+        let lock_path = sibling(path, "lock");
+        let write_err = |e| Error::FileWrite {
+            path: lock_path.clone(),
+            source: e,
+        };
+
+        if let Some(parent) = lock_path.parent() {
+            std::fs::create_dir_all(parent).map_err(write_err)?;
+        }
+        let file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .write(true)
+            .open(&lock_path)
+            .map_err(write_err)?;
+
+        match file.try_lock() {
+            Ok(()) => {}
+            Err(TryLockError::WouldBlock) => {
+                debug!(path = %path.display(), "waiting for another skill-manager to release the file");
+                file.lock().map_err(write_err)?;
+            }
+            Err(TryLockError::Error(e)) => return Err(write_err(e)),
+        }
+
+        Ok(Self { _file: file })
+    }
+}
+
+/// Read a file, or `None` if it does not exist.
+pub(crate) fn read(path: &Path) -> Result<Option<Vec<u8>>> {
+    match std::fs::read(path) {
+        Ok(content) => Ok(Some(content)),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(None),
+        Err(e) => Err(Error::FileRead {
+            path: path.to_path_buf(),
+            source: e,
+        }),
+    }
+}
+
+/// Replace a file's contents in one step.
+///
+/// The contents are written to a temporary file in the same directory,
+/// synced, and renamed over the file, so readers see either the old or the
+/// new contents and never a truncated file. A symlink is followed, and the
+/// file keeps its permissions.
+pub(crate) fn write_atomic(path: &Path, content: &[u8]) -> Result<()> {
+    let path = std::fs::canonicalize(path).unwrap_or_else(|_| path.to_path_buf());
+    let write_err = |e| Error::FileWrite {
+        path: path.clone(),
+        source: e,
+    };
+
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent).map_err(write_err)?;
+    }
+
+    let temp = sibling(&path, &format!("{}.tmp", std::process::id()));
+    let result = (|| {
+        let mut file = File::create(&temp)?;
+        file.write_all(content)?;
+        if let Ok(metadata) = std::fs::metadata(&path) {
+            file.set_permissions(metadata.permissions())?;
+        }
+        file.sync_all()?;
+        std::fs::rename(&temp, &path)
+    })();
+
+    if result.is_err() {
+        let _ = std::fs::remove_file(&temp);
+    }
+    result.map_err(write_err)
+}
+
+/// Read, modify and write back a file while holding its lock.
+///
+/// `modify` gets the current contents (`None` if the file does not exist)
+/// and returns the new contents, or `None` to leave the file as it is.
+/// Other programs such as Claude Code do not take the lock, so the file is
+/// read again before writing; if it changed in the meantime, `modify` runs
+/// again on the new contents instead of overwriting them.
+///
+/// This narrows the window for lost edits but does not close it: a program
+/// that ignores the lock and writes between the second read and the rename
+/// still has its edit replaced.
+pub(crate) fn update<F>(path: &Path, mut modify: F) -> Result<()>
+where
+    F: FnMut(Option<&[u8]>) -> Result<Option<Vec<u8>>>,
+{
+    let _lock = FileLock::acquire(path)?;
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        let before = read(path)?;
+        let Some(content) = modify(before.as_deref())? else {
+            return Ok(());
+        };
+
+        if read(path)? != before {
+            debug!(path = %path.display(), attempt, "file changed while updating it, retrying");
+            continue;
+        }
+        return write_atomic(path, &content);
+    }
+
+    Err(Error::ConcurrentModification(path.to_path_buf()))
+}
+
+/// A hidden file next to `path`: `.<name>.<suffix>`.
+fn sibling(path: &Path, suffix: &str) -> PathBuf {
+    let name = path.file_name().unwrap_or_default().to_string_lossy();
+    path.with_file_name(format!(".{}.{}", name, suffix))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_write_atomic() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("nested/settings.json");
+
+        write_atomic(&path, b"first").unwrap();
+        write_atomic(&path, b"second").unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"second");
+
+        // Only the file itself is left behind
+        let entries: Vec<_> = fs::read_dir(path.parent().unwrap()).unwrap().collect();
+        assert_eq!(entries.len(), 1);
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_atomic_follows_symlink() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let target = temp_dir.path().join("dotfiles.json");
+        fs::write(&target, "old").unwrap();
+        let link = temp_dir.path().join("link.json");
+        std::os::unix::fs::symlink(&target, &link).unwrap();
+
+        write_atomic(&link, b"new").unwrap();
+        assert!(fs::symlink_metadata(&link).unwrap().file_type().is_symlink());
+        assert_eq!(fs::read(&target).unwrap(), b"new");
+    }
+
+    #[test]
+    fn test_update_retries_after_concurrent_write() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("file.txt");
+        fs::write(&path, "a").unwrap();
+
+        let mut runs = 0;
+        update(&path, |content| {
+            runs += 1;
+            if runs == 1 {
+                // Someone else writes the file while it is being modified
+                fs::write(&path, "ab").unwrap();
+            }
+            let mut content = content.unwrap().to_vec();
+            content.push(b'c');
+            Ok(Some(content))
+        })
+        .unwrap();
+
+        assert_eq!(runs, 2);
+        assert_eq!(fs::read(&path).unwrap(), b"abc");
+    }
+
+    #[test]
+    fn test_update_gives_up_when_file_keeps_changing() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("file.txt");
+
+        let mut runs = 0;
+        let result = update(&path, |_| {
+            runs += 1;
+            fs::write(&path, runs.to_string()).unwrap();
+            Ok(Some(b"mine".to_vec()))
+        });
+
+        assert!(matches!(result, Err(Error::ConcurrentModification(_))));
+        assert_eq!(runs, MAX_ATTEMPTS);
+        assert_ne!(fs::read(&path).unwrap(), b"mine");
+    }
+
+    #[test]
+    fn test_update_serializes_writers() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = temp_dir.path().join("counter");
+
+        std::thread::scope(|scope| {
+            for _ in 0..4 {
+                scope.spawn(|| {
+                    for _ in 0..25 {
+                        update(&path, |content| {
+                            let count: u32 = content.map_or(0, |c| String::from_utf8_lossy(c).parse().unwrap());
+                            Ok(Some((count + 1).to_string().into_bytes()))
+                        })
+                        .unwrap();
+                    }
+                });
+            }
+        });
+
+        assert_eq!(fs::read_to_string(&path).unwrap(), "100");
+    }
+}
@@ -1,5 +1,11 @@
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
+use std::time::SystemTime;
 
+use super::atomic;
+use super::integrity::content_hash;
+use crate::config::GIT_MARKETPLACE;
+use crate::layout::PluginLayout;
 use crate::{Error, Result};
 
 /// CACHEDIR.TAG content per https://bford.info/cachedir/
@@ -8,6 +14,52 @@ const CACHEDIR_TAG_CONTENT: &str = "Signature: 8a477f597d28d172789f06886806bc55\
 # For information about cache directory tags, see:\n\
 #   https://bford.info/cachedir/\n";
 
+/// File in the cache listing the lock files installs have used.
+const LOCKS_FILENAME: &str = "locks.json";
+
+/// A plugin extracted to the cache, with the content hash of its files.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ExtractedPlugin {
+    pub path: PathBuf,
+    pub content_hash: String,
+}
+
+/// Where a plugin listed in the generated git marketplace comes from.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct GitPluginSource<'a> {
+    pub url: &'a str,
+    /// Directory of the plugin within the repository.
+    pub subdir: Option<&'a str>,
+    /// The locked commit.
+    pub commit: &'a str,
+}
+
+/// What a directory in the cache holds.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum CacheEntryKind {
+    /// An extracted plugin: `plugins/<marketplace>/<plugin>/<commit>`.
+    Plugin {
+        marketplace: String,
+        plugin: String,
+        commit: String,
+    },
+    /// A clone of an external plugin's repository: `plugin-repos/<marketplace>/<plugin>`.
+    PluginRepo { marketplace: String, plugin: String },
+    /// A marketplace clone: `marketplaces/<name>`.
+    Marketplace { name: String },
+}
+
+/// A directory in the cache that can be removed on its own.
+#[derive(Debug, Clone)]
+pub struct CacheEntry {
+    pub kind: CacheEntryKind,
+    pub path: PathBuf,
+    /// Total size of the files in it, in bytes.
+    pub size: u64,
+    /// Latest modification time of anything in it.
+    pub modified: SystemTime,
+}
+
 /// Cache manager for skill-manager.
 pub struct CacheManager {
     cache_dir: PathBuf,
@@ -63,13 +115,152 @@ impl CacheManager {
             .join(commit)
     }
 
-    /// Check if a plugin is already extracted at the given commit.
-    pub fn is_plugin_extracted(&self, marketplace: &str, plugin: &str, commit: &str) -> bool {
+    /// Directory of the marketplace generated for plugins installed straight
+    /// from git.
+    pub fn git_marketplace_path(&self) -> PathBuf {
+        self.cache_dir.join("marketplaces").join(GIT_MARKETPLACE)
+    }
+
+    /// Write the marketplace that lists plugins installed straight from git,
+    /// by name. Each entry pins the locked commit, and the plugin's directory
+    /// if it has one, so Claude Code sees the tree that was extracted.
+    /// Returns the marketplace's directory.
+    pub fn write_git_marketplace(&self, plugins: &BTreeMap<&str, GitPluginSource>) -> Result<PathBuf> {
+        let path = self.git_marketplace_path();
+        let layout = PluginLayout::new(&path);
+        std::fs::create_dir_all(layout.config_dir()).map_err(Error::CacheCreate)?;
+
+        let json = serde_json::json!({
+            "name": GIT_MARKETPLACE,
+            "owner": { "name": "skill-manager" },
+            "description": "Plugins installed by skill-manager from git repositories",
+            "plugins": plugins
+                .iter()
+                .map(|(name, source)| serde_json::json!({
+                    "name": name,
+                    "source": match source.subdir {
+                        Some(subdir) => serde_json::json!({
+                            "source": "git-subdir",
+                            "url": source.url,
+                            "path": subdir,
+                            "sha": source.commit,
+                        }),
+                        None => serde_json::json!({ "source": "url", "url": source.url, "sha": source.commit }),
+                    },
+                }))
+                .collect::<Vec<_>>(),
+        });
+        let json_path = layout.marketplace_json();
+        let content = serde_json::to_vec_pretty(&json).map_err(|e| Error::JsonParse {
+            path: json_path.to_path_buf(),
+            source: e,
+        })?;
+        atomic::write_atomic(json_path, &content)?;
+
+        Ok(path)
+    }
+
+    /// Remove the generated git marketplace, if it exists.
+    pub fn remove_git_marketplace(&self) -> Result<()> {
+        let path = self.git_marketplace_path();
+        match std::fs::remove_dir_all(&path) {
+            Ok(()) => Ok(()),
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => Ok(()),
+            Err(e) => Err(Error::FileWrite { path, source: e }),
+        }
+    }
+
+    /// List the extracted plugins, plugin repositories and marketplaces in the cache.
+    pub fn entries(&self) -> Result<Vec<CacheEntry>> {
+        let mut entries = Vec::new();
+        for (marketplace, marketplace_dir) in subdirectories(&self.cache_dir.join("plugins"))? {
+            for (plugin, plugin_dir) in subdirectories(&marketplace_dir)? {
+                for (commit, path) in subdirectories(&plugin_dir)? {
+                    let kind = CacheEntryKind::Plugin {
+                        marketplace: marketplace.clone(),
+                        plugin: plugin.clone(),
+                        commit,
+                    };
+                    entries.push(cache_entry(kind, path)?);
+                }
+            }
+        }
+        for (marketplace, marketplace_dir) in subdirectories(&self.cache_dir.join("plugin-repos"))? {
+            for (plugin, path) in subdirectories(&marketplace_dir)? {
+                let kind = CacheEntryKind::PluginRepo {
+                    marketplace: marketplace.clone(),
+                    plugin,
+                };
+                entries.push(cache_entry(kind, path)?);
+            }
+        }
+        for (name, path) in subdirectories(&self.cache_dir.join("marketplaces"))? {
+            entries.push(cache_entry(CacheEntryKind::Marketplace { name }, path)?);
+        }
+        Ok(entries)
+    }
+
+    /// Delete an entry, along with the directories above it it leaves empty.
+    pub fn remove_entry(&self, entry: &CacheEntry) -> Result<()> {
+        std::fs::remove_dir_all(&entry.path).map_err(|e| Error::FileWrite {
+            path: entry.path.clone(),
+            source: e,
+        })?;
+
+        // Stop at the category directory, e.g. `plugins`
+        let mut dir = entry.path.parent();
+        while let Some(parent) = dir {
+            if parent.parent() == Some(self.cache_dir.as_path()) || std::fs::remove_dir(parent).is_err() {
+                break;
+            }
+            dir = parent.parent();
+        }
+        Ok(())
+    }
+
+    /// Remember a lock file an install used, so `cache gc` keeps what it references.
+    pub fn record_lock(&self, lock_path: &Path) -> Result<()> {
+        let lock_path = std::path::absolute(lock_path).unwrap_or_else(|_| lock_path.to_path_buf());
+        let registry = self.cache_dir.join(LOCKS_FILENAME);
+        atomic::update(&registry, |content| {
+            let mut locks = parse_locks(&registry, content)?;
+            if locks.contains(&lock_path) {
+                return Ok(None);
+            }
+            locks.push(lock_path.clone());
+            write_locks(&registry, &locks).map(Some)
+        })
+    }
+
+    /// Lock files installs have used that still exist.
+    pub fn known_locks(&self) -> Result<Vec<PathBuf>> {
+        let registry = self.cache_dir.join(LOCKS_FILENAME);
+        let locks = parse_locks(&registry, atomic::read(&registry)?.as_deref())?;
+        Ok(locks.into_iter().filter(|path| path.exists()).collect())
+    }
+
+    /// Forget lock files that no longer exist.
+    pub fn forget_missing_locks(&self) -> Result<()> {
+        let registry = self.cache_dir.join(LOCKS_FILENAME);
+        atomic::update(&registry, |content| {
+            let locks = parse_locks(&registry, content)?;
+            let existing: Vec<PathBuf> = locks.iter().filter(|path| path.exists()).cloned().collect();
+            if existing.len() == locks.len() {
+                return Ok(None);
+            }
+            write_locks(&registry, &existing).map(Some)
+        })
+    }
+
+    /// Check if a plugin is extracted at the given commit with the expected content hash.
+    pub fn is_plugin_extracted(&self, marketplace: &str, plugin: &str, commit: &str, expected_hash: &str) -> bool {
         let path = self.plugin_path(marketplace, plugin, commit);
-        path.exists()
+        path.exists() && content_hash(&path).is_ok_and(|hash| hash == expected_hash)
     }
 
     /// Extract a local plugin from a marketplace to the cache.
+    ///
+    /// See [`Self::extract_plugin`] for how an existing copy and `expected_hash` are handled.
     pub fn extract_local_plugin(
         &self,
         marketplace_path: &Path,
@@ -77,55 +268,169 @@ impl CacheManager {
         marketplace: &str,
         plugin: &str,
         commit: &str,
-    ) -> Result<PathBuf> {
-        let target_path = self.plugin_path(marketplace, plugin, commit);
-
-        if target_path.exists() {
-            return Ok(target_path);
-        }
-
+        expected_hash: Option<&str>,
+    ) -> Result<ExtractedPlugin> {
         let source_path = marketplace_path.join(plugin_source_path);
-        if !source_path.exists() {
-            return Err(Error::PluginExtract(
-                plugin.to_string(),
-                std::io::Error::new(
+
+        self.extract_plugin(marketplace, plugin, commit, expected_hash, |target| {
+            if !source_path.exists() {
+                return Err(std::io::Error::new(
                     std::io::ErrorKind::NotFound,
                     format!("Plugin source not found: {}", source_path.display()),
-                ),
-            ));
-        }
-
-        // Create target directory and copy contents
-        std::fs::create_dir_all(&target_path).map_err(|e| Error::PluginExtract(plugin.to_string(), e))?;
-        copy_dir_recursive(&source_path, &target_path)
-            .map_err(|e| Error::PluginExtract(plugin.to_string(), e))?;
-
-        Ok(target_path)
+                ));
+            }
+            copy_dir_recursive(&source_path, target)
+        })
     }
 
     /// Copy an external plugin repository to the cache.
+    ///
+    /// See [`Self::extract_plugin`] for how an existing copy and `expected_hash` are handled.
     pub fn extract_external_plugin(
         &self,
         repo_path: &Path,
         marketplace: &str,
         plugin: &str,
         commit: &str,
-    ) -> Result<PathBuf> {
+        expected_hash: Option<&str>,
+    ) -> Result<ExtractedPlugin> {
+        self.extract_plugin(marketplace, plugin, commit, expected_hash, |target| {
+            copy_dir_recursive_exclude_git(repo_path, target)
+        })
+    }
+
+    /// Extract a plugin to the cache and hash its contents.
+    ///
+    /// An existing copy is reused when no hash is expected or its hash
+    /// matches; otherwise it is replaced. Files are copied into a staging
+    /// directory that is renamed into place once complete, so an interrupted
+    /// extraction never leaves a half-written plugin behind. Fails with
+    /// [`Error::ContentHashMismatch`] when the fresh copy does not match the
+    /// expected hash either.
+    fn extract_plugin(
+        &self,
+        marketplace: &str,
+        plugin: &str,
+        commit: &str,
+        expected_hash: Option<&str>,
+        copy: impl FnOnce(&Path) -> std::io::Result<()>,
+    ) -> Result<ExtractedPlugin> {
+        let extract_err = |e| Error::PluginExtract(plugin.to_string(), e);
         let target_path = self.plugin_path(marketplace, plugin, commit);
 
         if target_path.exists() {
-            return Ok(target_path);
+            let hash = content_hash(&target_path).map_err(extract_err)?;
+            if expected_hash.is_none_or(|expected| expected == hash) {
+                return Ok(ExtractedPlugin {
+                    path: target_path,
+                    content_hash: hash,
+                });
+            }
+            std::fs::remove_dir_all(&target_path).map_err(extract_err)?;
+        }
+
+        let staging_path = target_path.with_file_name(format!("{}.partial", commit));
+        if staging_path.exists() {
+            std::fs::remove_dir_all(&staging_path).map_err(extract_err)?;
+        }
+        std::fs::create_dir_all(&staging_path).map_err(extract_err)?;
+
+        let hash = copy(&staging_path)
+            .and_then(|()| content_hash(&staging_path))
+            .inspect_err(|_| {
+                let _ = std::fs::remove_dir_all(&staging_path);
+            })
+            .map_err(extract_err)?;
+
+        if let Some(expected) = expected_hash
+            && expected != hash
+        {
+            let _ = std::fs::remove_dir_all(&staging_path);
+            return Err(Error::ContentHashMismatch {
+                plugin: plugin.to_string(),
+                expected: expected.to_string(),
+                actual: hash,
+            });
         }
 
-        // Create target directory and copy contents (excluding .git)
-        std::fs::create_dir_all(&target_path).map_err(|e| Error::PluginExtract(plugin.to_string(), e))?;
-        copy_dir_recursive_exclude_git(repo_path, &target_path)
-            .map_err(|e| Error::PluginExtract(plugin.to_string(), e))?;
+        std::fs::rename(&staging_path, &target_path).map_err(extract_err)?;
 
-        Ok(target_path)
+        Ok(ExtractedPlugin {
+            path: target_path,
+            content_hash: hash,
+        })
     }
 }
 
+fn parse_locks(path: &Path, content: Option<&[u8]>) -> Result<Vec<PathBuf>> {
+    let Some(content) = content else {
+        return Ok(Vec::new());
+    };
+    serde_json::from_slice(content).map_err(|e| Error::JsonParse {
+        path: path.to_path_buf(),
+        source: e,
+    })
+}
+
+fn write_locks(path: &Path, locks: &[PathBuf]) -> Result<Vec<u8>> {
+    serde_json::to_vec_pretty(locks).map_err(|e| Error::JsonParse {
+        path: path.to_path_buf(),
+        source: e,
+    })
+}
+
+/// The subdirectories of `dir` by name, sorted; empty if `dir` does not exist.
+fn subdirectories(dir: &Path) -> Result<Vec<(String, PathBuf)>> {
+    let read_err = |e| Error::FileRead {
+        path: dir.to_path_buf(),
+        source: e,
+    };
+    let read_dir = match std::fs::read_dir(dir) {
+        Ok(read_dir) => read_dir,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(read_err(e)),
+    };
+
+    let mut dirs = Vec::new();
+    for entry in read_dir {
+        let entry = entry.map_err(read_err)?;
+        if entry.file_type().map_err(read_err)?.is_dir() {
+            dirs.push((entry.file_name().to_string_lossy().into_owned(), entry.path()));
+        }
+    }
+    dirs.sort();
+    Ok(dirs)
+}
+
+fn cache_entry(kind: CacheEntryKind, path: PathBuf) -> Result<CacheEntry> {
+    let (size, modified) = disk_usage(&path).map_err(|e| Error::FileRead {
+        path: path.clone(),
+        source: e,
+    })?;
+    Ok(CacheEntry {
+        kind,
+        path,
+        size,
+        modified,
+    })
+}
+
+/// Total size and latest modification time of a directory's contents.
+/// Symlinks are not followed.
+fn disk_usage(path: &Path) -> std::io::Result<(u64, SystemTime)> {
+    let metadata = std::fs::symlink_metadata(path)?;
+    let mut size = if metadata.is_dir() { 0 } else { metadata.len() };
+    let mut modified = metadata.modified()?;
+    if metadata.is_dir() {
+        for entry in std::fs::read_dir(path)? {
+            let (entry_size, entry_modified) = disk_usage(&entry?.path())?;
+            size += entry_size;
+            modified = modified.max(entry_modified);
+        }
+    }
+    Ok((size, modified))
+}
+
 /// Recursively copy a directory.
 fn copy_dir_recursive(src: &Path, dst: &Path) -> std::io::Result<()> {
     for entry in std::fs::read_dir(src)? {
@@ -188,6 +493,62 @@ mod tests {
         assert!(tag_content.starts_with("Signature: 8a477f597d28d172789f06886806bc55"));
     }
 
+    #[test]
+    fn test_entries_and_remove_entry() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache = CacheManager::with_cache_dir(temp_dir.path().to_path_buf());
+        cache.ensure_cache_dir().unwrap();
+
+        let plugin = cache.plugin_path("dev", "foo", "abc123");
+        fs::create_dir_all(plugin.join("skills")).unwrap();
+        fs::write(plugin.join("skills/SKILL.md"), "0123456789").unwrap();
+        fs::create_dir_all(temp_dir.path().join("plugin-repos/dev/bar")).unwrap();
+        fs::create_dir_all(temp_dir.path().join("marketplaces/dev")).unwrap();
+
+        let entries = cache.entries().unwrap();
+        let kinds: Vec<&CacheEntryKind> = entries.iter().map(|e| &e.kind).collect();
+        assert_eq!(
+            kinds,
+            [
+                &CacheEntryKind::Plugin {
+                    marketplace: "dev".to_string(),
+                    plugin: "foo".to_string(),
+                    commit: "abc123".to_string(),
+                },
+                &CacheEntryKind::PluginRepo {
+                    marketplace: "dev".to_string(),
+                    plugin: "bar".to_string(),
+                },
+                &CacheEntryKind::Marketplace { name: "dev".to_string() },
+            ]
+        );
+        assert_eq!(entries[0].size, 10);
+
+        // Empty parents go too, but not the category directory
+        cache.remove_entry(&entries[0]).unwrap();
+        assert!(!temp_dir.path().join("plugins/dev").exists());
+        assert!(temp_dir.path().join("plugins").exists());
+    }
+
+    #[test]
+    fn test_known_locks() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache = CacheManager::with_cache_dir(temp_dir.path().join("cache"));
+        let lock = temp_dir.path().join("plugins.lock");
+        let gone = temp_dir.path().join("gone/plugins.lock");
+        fs::write(&lock, "").unwrap();
+
+        cache.record_lock(&lock).unwrap();
+        cache.record_lock(&lock).unwrap();
+        cache.record_lock(&gone).unwrap();
+        assert_eq!(cache.known_locks().unwrap(), std::slice::from_ref(&lock));
+
+        cache.forget_missing_locks().unwrap();
+        let registry = fs::read_to_string(temp_dir.path().join("cache").join(LOCKS_FILENAME)).unwrap();
+        assert!(!registry.contains("gone"));
+        assert_eq!(cache.known_locks().unwrap(), [lock]);
+    }
+
     #[test]
     fn test_plugin_path() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -205,12 +566,17 @@ mod tests {
         let temp_dir = tempfile::tempdir().unwrap();
         let cache = CacheManager::with_cache_dir(temp_dir.path().to_path_buf());
 
-        assert!(!cache.is_plugin_extracted("test", "plugin", "abc123"));
-
         let path = cache.plugin_path("test", "plugin", "abc123");
         fs::create_dir_all(&path).unwrap();
+        fs::write(path.join("plugin.json"), r#"{"version": "1.0"}"#).unwrap();
+        let hash = content_hash(&path).unwrap();
 
-        assert!(cache.is_plugin_extracted("test", "plugin", "abc123"));
+        assert!(cache.is_plugin_extracted("test", "plugin", "abc123", &hash));
+        assert!(!cache.is_plugin_extracted("test", "plugin", "def456", &hash));
+
+        // A tampered copy no longer counts as extracted
+        fs::write(path.join("plugin.json"), r#"{"version": "2.0"}"#).unwrap();
+        assert!(!cache.is_plugin_extracted("test", "plugin", "abc123", &hash));
     }
 
     #[test]
@@ -233,12 +599,15 @@ mod tests {
                 "official",
                 "test-plugin",
                 "abc123",
+                None,
             )
             .unwrap();
 
-        assert!(result.exists());
-        assert!(result.join("plugin.json").exists());
-        assert!(result.join("init.lua").exists());
+        assert!(result.path.exists());
+        assert!(result.path.join("plugin.json").exists());
+        assert!(result.path.join("init.lua").exists());
+        assert_eq!(result.content_hash, content_hash(&result.path).unwrap());
+        assert!(!result.path.with_file_name("abc123.partial").exists());
 
         // Second extraction should return the same path (skip logic)
         let result2 = cache
@@ -248,11 +617,50 @@ mod tests {
                 "official",
                 "test-plugin",
                 "abc123",
+                Some(&result.content_hash),
             )
             .unwrap();
         assert_eq!(result, result2);
     }
 
+    #[test]
+    fn test_extract_replaces_tampered_copy() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache = CacheManager::with_cache_dir(temp_dir.path().join("cache"));
+
+        let marketplace_path = temp_dir.path().join("marketplace");
+        let plugin_src = marketplace_path.join("plugins/test-plugin");
+        fs::create_dir_all(&plugin_src).unwrap();
+        fs::write(plugin_src.join("plugin.json"), r#"{"version": "1.0"}"#).unwrap();
+
+        let extract = |expected: Option<&str>| {
+            cache.extract_local_plugin(
+                &marketplace_path,
+                "plugins/test-plugin",
+                "official",
+                "test-plugin",
+                "abc123",
+                expected,
+            )
+        };
+
+        let original = extract(None).unwrap();
+
+        // Tampered or half-written copies are re-extracted
+        fs::write(original.path.join("plugin.json"), "tampered").unwrap();
+        fs::write(original.path.join("extra.sh"), "echo hi").unwrap();
+        let repaired = extract(Some(&original.content_hash)).unwrap();
+        assert_eq!(repaired, original);
+        assert!(!repaired.path.join("extra.sh").exists());
+
+        // A source that no longer matches the lock is refused
+        fs::write(plugin_src.join("plugin.json"), r#"{"version": "2.0"}"#).unwrap();
+        fs::write(original.path.join("plugin.json"), "tampered").unwrap();
+        let result = extract(Some(&original.content_hash));
+        assert!(matches!(result, Err(Error::ContentHashMismatch { .. })));
+        assert!(!original.path.with_file_name("abc123.partial").exists());
+    }
+
     #[test]
     fn test_extract_external_plugin_excludes_git() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -266,11 +674,11 @@ mod tests {
 
         // Extract the plugin
         let result = cache
-            .extract_external_plugin(&repo_path, "test", "plugin", "def456")
+            .extract_external_plugin(&repo_path, "test", "plugin", "def456", None)
             .unwrap();
 
-        assert!(result.exists());
-        assert!(result.join("plugin.json").exists());
-        assert!(!result.join(".git").exists()); // .git should be excluded
+        assert!(result.path.exists());
+        assert!(result.path.join("plugin.json").exists());
+        assert!(!result.path.join(".git").exists()); // .git should be excluded
     }
 }
@@ -1,8 +1,10 @@
+use serde::de::DeserializeOwned;
 use serde::{Deserialize, Serialize};
 use serde_json::{json, Map, Value};
 use std::collections::HashMap;
 use std::path::{Path, PathBuf};
 
+use super::atomic::{self, FileLock};
 use crate::{Error, Result};
 
 /// Represents the scope of a plugin installation.
@@ -19,6 +21,16 @@ pub struct InstalledPluginsFile {
     pub plugins: HashMap<String, Vec<InstalledPluginEntry>>,
 }
 
+impl Default for InstalledPluginsFile {
+    /// An empty v2 file.
+    fn default() -> Self {
+        Self {
+            version: 2,
+            plugins: HashMap::new(),
+        }
+    }
+}
+
 /// Entry in known_marketplaces.json.
 /// Uses serde_json::Value for source to preserve any source type (github, url, directory, etc.)
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -48,6 +60,12 @@ pub struct ClaudeCodeIntegration {
     claude_dir: PathBuf,
 }
 
+impl Default for ClaudeCodeIntegration {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
 impl ClaudeCodeIntegration {
     /// Create a new integration using the default ~/.claude directory.
     pub fn new() -> Self {
@@ -80,19 +98,7 @@ impl ClaudeCodeIntegration {
     /// Read existing known_marketplaces.json or return empty HashMap.
     pub fn read_known_marketplaces(&self) -> Result<HashMap<String, KnownMarketplaceEntry>> {
         let path = self.known_marketplaces_path();
-        if !path.exists() {
-            return Ok(HashMap::new());
-        }
-
-        let content = std::fs::read_to_string(&path).map_err(|e| Error::FileRead {
-            path: path.clone(),
-            source: e,
-        })?;
-
-        serde_json::from_str(&content).map_err(|e| Error::JsonParse {
-            path,
-            source: e,
-        })
+        parse_json(&path, atomic::read(&path)?.as_deref())
     }
 
     /// Write known_marketplaces.json.
@@ -100,69 +106,18 @@ impl ClaudeCodeIntegration {
         &self,
         marketplaces: &HashMap<String, KnownMarketplaceEntry>,
     ) -> Result<()> {
-        let path = self.known_marketplaces_path();
-
-        // Ensure parent directory exists
-        if let Some(parent) = path.parent() {
-            std::fs::create_dir_all(parent).map_err(|e| Error::FileWrite {
-                path: path.clone(),
-                source: e,
-            })?;
-        }
-
-        let content = serde_json::to_string_pretty(marketplaces).map_err(|e| Error::JsonParse {
-            path: path.clone(),
-            source: e,
-        })?;
-
-        std::fs::write(&path, content).map_err(|e| Error::FileWrite {
-            path,
-            source: e,
-        })
+        write_json(&self.known_marketplaces_path(), marketplaces)
     }
 
     /// Read existing installed_plugins.json or return empty v2 structure.
     pub fn read_installed_plugins(&self) -> Result<InstalledPluginsFile> {
         let path = self.installed_plugins_path();
-        if !path.exists() {
-            return Ok(InstalledPluginsFile {
-                version: 2,
-                plugins: HashMap::new(),
-            });
-        }
-
-        let content = std::fs::read_to_string(&path).map_err(|e| Error::FileRead {
-            path: path.clone(),
-            source: e,
-        })?;
-
-        serde_json::from_str(&content).map_err(|e| Error::JsonParse {
-            path,
-            source: e,
-        })
+        parse_json(&path, atomic::read(&path)?.as_deref())
     }
 
     /// Write installed_plugins.json in v2 format.
     pub fn write_installed_plugins(&self, file: &InstalledPluginsFile) -> Result<()> {
-        let path = self.installed_plugins_path();
-
-        // Ensure parent directory exists
-        if let Some(parent) = path.parent() {
-            std::fs::create_dir_all(parent).map_err(|e| Error::FileWrite {
-                path: path.clone(),
-                source: e,
-            })?;
-        }
-
-        let content = serde_json::to_string_pretty(file).map_err(|e| Error::JsonParse {
-            path: path.clone(),
-            source: e,
-        })?;
-
-        std::fs::write(&path, content).map_err(|e| Error::FileWrite {
-            path,
-            source: e,
-        })
+        write_json(&self.installed_plugins_path(), file)
     }
 
     /// Add or update a plugin in installed_plugins.json.
@@ -177,22 +132,11 @@ impl ClaudeCodeIntegration {
         commit: &str,
         scope: &PluginScope,
     ) -> Result<()> {
-        let mut file = self.read_installed_plugins()?;
-
         let key = format!("{}@{}", plugin_name, marketplace);
         let now = chrono_iso8601_now();
 
         // Determine scope string and project_path based on PluginScope
-        let (scope_str, project_path) = match scope {
-            PluginScope::User => ("user".to_string(), None),
-            PluginScope::Project(path) => {
-                let canonical = std::fs::canonicalize(path).map_err(|e| Error::FileRead {
-                    path: path.clone(),
-                    source: e,
-                })?;
-                ("project".to_string(), Some(canonical.to_string_lossy().to_string()))
-            }
-        };
+        let (scope_str, project_path) = scope_fields(scope)?;
 
         let new_entry = InstalledPluginEntry {
             scope: scope_str.clone(),
@@ -204,106 +148,216 @@ impl ClaudeCodeIntegration {
             git_commit_sha: commit.to_string(),
         };
 
-        // Get or create the array for this plugin key
-        let entries = file.plugins.entry(key).or_insert_with(Vec::new);
+        update_json(&self.installed_plugins_path(), parse_json, |file: &mut InstalledPluginsFile| {
+            // Get or create the array for this plugin key
+            let entries = file.plugins.entry(key.clone()).or_default();
 
-        // Filter out existing entries with the same scope
-        entries.retain(|entry| {
-            if entry.scope != scope_str {
-                return true; // Keep entries with different scope types
-            }
-            // For project scope, only remove if same project path
-            if scope_str == "project" {
-                return entry.project_path != project_path;
-            }
-            // For user scope, remove the existing entry
-            false
-        });
+            // Filter out existing entries with the same scope
+            entries.retain(|entry| !entry.matches_scope(&scope_str, &project_path));
+
+            // Add the new entry
+            entries.push(new_entry.clone());
+            true
+        })
+        .map(drop)
+    }
 
-        // Add the new entry
-        entries.push(new_entry);
+    /// Remove a plugin's entry for the given scope from installed_plugins.json.
+    /// Entries for other scopes and other project paths are preserved, and the
+    /// plugin key is dropped once no entries remain. Returns whether anything was removed.
+    pub fn remove_installed_plugin(
+        &self,
+        plugin_name: &str,
+        marketplace: &str,
+        scope: &PluginScope,
+    ) -> Result<bool> {
+        let key = format!("{}@{}", plugin_name, marketplace);
+        let (scope_str, project_path) = scope_fields(scope)?;
 
-        self.write_installed_plugins(&file)
+        update_json(&self.installed_plugins_path(), parse_json, |file: &mut InstalledPluginsFile| {
+            let Some(entries) = file.plugins.get_mut(&key) else {
+                return false;
+            };
+
+            let before = entries.len();
+            entries.retain(|entry| !entry.matches_scope(&scope_str, &project_path));
+            let removed = entries.len() != before;
+
+            if entries.is_empty() {
+                file.plugins.remove(&key);
+            }
+            removed
+        })
+    }
+
+    /// List installed_plugins.json entries for the given scope, keyed by `<plugin>@<marketplace>`.
+    pub fn installed_plugins_in_scope(
+        &self,
+        scope: &PluginScope,
+    ) -> Result<Vec<(String, InstalledPluginEntry)>> {
+        let file = self.read_installed_plugins()?;
+        let (scope_str, project_path) = scope_fields(scope)?;
+
+        let mut entries: Vec<(String, InstalledPluginEntry)> = file
+            .plugins
+            .into_iter()
+            .flat_map(|(key, entries)| entries.into_iter().map(move |e| (key.clone(), e)))
+            .filter(|(_, entry)| entry.matches_scope(&scope_str, &project_path))
+            .collect();
+        entries.sort_by(|a, b| a.0.cmp(&b.0));
+        Ok(entries)
     }
 
     /// Read existing settings.json or return empty object.
     pub fn read_settings(&self) -> Result<Map<String, Value>> {
         let path = self.settings_path();
-        if !path.exists() {
-            return Ok(Map::new());
-        }
-
-        let content = std::fs::read_to_string(&path).map_err(|e| Error::FileRead {
-            path: path.clone(),
-            source: e,
-        })?;
-
-        let value: Value = serde_json::from_str(&content).map_err(|e| Error::JsonParse {
-            path,
-            source: e,
-        })?;
-
-        match value {
-            Value::Object(map) => Ok(map),
-            _ => Ok(Map::new()),
-        }
+        parse_settings(&path, atomic::read(&path)?.as_deref())
     }
 
     /// Write settings.json.
     pub fn write_settings(&self, settings: &Map<String, Value>) -> Result<()> {
-        let path = self.settings_path();
-
-        // Ensure parent directory exists
-        if let Some(parent) = path.parent() {
-            std::fs::create_dir_all(parent).map_err(|e| Error::FileWrite {
-                path: path.clone(),
-                source: e,
-            })?;
-        }
-
-        let content = serde_json::to_string_pretty(&Value::Object(settings.clone()))
-            .map_err(|e| Error::JsonParse {
-                path: path.clone(),
-                source: e,
-            })?;
-
-        std::fs::write(&path, content).map_err(|e| Error::FileWrite {
-            path,
-            source: e,
-        })
+        write_json(&self.settings_path(), settings)
     }
 
     /// Enable a plugin in settings.json.
     pub fn enable_plugin(&self, plugin_name: &str, marketplace: &str) -> Result<()> {
-        let mut settings = self.read_settings()?;
-
         let key = format!("{}@{}", plugin_name, marketplace);
 
-        // Get or create enabledPlugins
-        let enabled_plugins = settings
-            .entry("enabledPlugins")
-            .or_insert_with(|| json!({}));
+        update_json(&self.settings_path(), parse_settings, |settings| {
+            // Get or create enabledPlugins
+            let enabled_plugins = settings
+                .entry("enabledPlugins")
+                .or_insert_with(|| json!({}));
 
-        if let Value::Object(map) = enabled_plugins {
-            map.insert(key, json!(true));
-        }
+            if let Value::Object(map) = enabled_plugins {
+                map.insert(key.clone(), json!(true));
+            }
+            true
+        })
+        .map(drop)
+    }
+
+    /// Remove a plugin from enabledPlugins in settings.json.
+    pub fn disable_plugin(&self, plugin_name: &str, marketplace: &str) -> Result<()> {
+        let key = format!("{}@{}", plugin_name, marketplace);
 
-        self.write_settings(&settings)
+        update_json(&self.settings_path(), parse_settings, |settings| {
+            match settings.get_mut("enabledPlugins") {
+                Some(Value::Object(map)) => map.remove(&key).is_some(),
+                _ => false,
+            }
+        })
+        .map(drop)
     }
 
     /// Register a marketplace in known_marketplaces.json.
     /// Uses directory source type pointing to the marketplace cache path.
     pub fn register_marketplace(&self, name: &str, cache_path: &Path) -> Result<()> {
-        let mut marketplaces = self.read_known_marketplaces()?;
-
         let entry = KnownMarketplaceEntry {
             source: make_directory_source(cache_path),
             install_location: cache_path.to_string_lossy().to_string(),
             last_updated: chrono_iso8601_now(),
         };
 
-        marketplaces.insert(name.to_string(), entry);
-        self.write_known_marketplaces(&marketplaces)
+        update_json(&self.known_marketplaces_path(), parse_json, |marketplaces: &mut HashMap<_, _>| {
+            marketplaces.insert(name.to_string(), entry.clone());
+            true
+        })
+        .map(drop)
+    }
+
+    /// Remove a marketplace from known_marketplaces.json.
+    /// Returns whether the marketplace was registered.
+    pub fn unregister_marketplace(&self, name: &str) -> Result<bool> {
+        update_json(
+            &self.known_marketplaces_path(),
+            parse_json,
+            |marketplaces: &mut HashMap<String, KnownMarketplaceEntry>| marketplaces.remove(name).is_some(),
+        )
+    }
+}
+
+impl InstalledPluginEntry {
+    /// Check whether this entry belongs to the given scope.
+    /// Project entries only match when the project path is the same.
+    fn matches_scope(&self, scope: &str, project_path: &Option<String>) -> bool {
+        if self.scope != scope {
+            return false;
+        }
+        if scope == "project" {
+            return self.project_path == *project_path;
+        }
+        true
+    }
+}
+
+/// Parse a JSON file's contents; a missing file is the empty value.
+fn parse_json<T: DeserializeOwned + Default>(path: &Path, content: Option<&[u8]>) -> Result<T> {
+    let Some(content) = content else {
+        return Ok(T::default());
+    };
+    serde_json::from_slice(content).map_err(|e| Error::JsonParse {
+        path: path.to_path_buf(),
+        source: e,
+    })
+}
+
+/// Parse settings.json; anything but an object counts as empty.
+fn parse_settings(path: &Path, content: Option<&[u8]>) -> Result<Map<String, Value>> {
+    match parse_json(path, content)? {
+        Value::Object(map) => Ok(map),
+        _ => Ok(Map::new()),
+    }
+}
+
+fn to_json<T: Serialize>(path: &Path, value: &T) -> Result<Vec<u8>> {
+    serde_json::to_vec_pretty(value).map_err(|e| Error::JsonParse {
+        path: path.to_path_buf(),
+        source: e,
+    })
+}
+
+/// Replace a JSON file atomically while holding its lock.
+fn write_json<T: Serialize>(path: &Path, value: &T) -> Result<()> {
+    let content = to_json(path, value)?;
+    let _lock = FileLock::acquire(path)?;
+    atomic::write_atomic(path, &content)
+}
+
+/// Read, modify and write back a JSON file (see [`atomic::update`]).
+///
+/// `modify` returns whether it changed anything; unchanged files are not
+/// written. It may run more than once if the file changes concurrently.
+/// Returns what its last run returned.
+fn update_json<T, P, F>(path: &Path, parse: P, mut modify: F) -> Result<bool>
+where
+    T: Serialize,
+    P: Fn(&Path, Option<&[u8]>) -> Result<T>,
+    F: FnMut(&mut T) -> bool,
+{
+    let mut changed = false;
+    atomic::update(path, |content| {
+        let mut value = parse(path, content)?;
+        changed = modify(&mut value);
+        if !changed {
+            return Ok(None);
+        }
+        to_json(path, &value).map(Some)
+    })?;
+    Ok(changed)
+}
+
+/// Determine the scope string and canonical project path for a PluginScope.
+fn scope_fields(scope: &PluginScope) -> Result<(String, Option<String>)> {
+    match scope {
+        PluginScope::User => Ok(("user".to_string(), None)),
+        PluginScope::Project(path) => {
+            let canonical = std::fs::canonicalize(path).map_err(|e| Error::FileRead {
+                path: path.clone(),
+                source: e,
+            })?;
+            Ok(("project".to_string(), Some(canonical.to_string_lossy().to_string())))
+        }
     }
 }
 
@@ -316,7 +370,7 @@ fn make_directory_source(path: &Path) -> Value {
 }
 
 /// Get current time in ISO 8601 format (UTC).
-fn chrono_iso8601_now() -> String {
+pub(crate) fn chrono_iso8601_now() -> String {
     use std::time::SystemTime;
 
     const SECONDS_PER_DAY: u64 = 86400;
@@ -621,6 +675,116 @@ mod tests {
         assert_eq!(entries[0].version, "2.0.0");
     }
 
+    #[test]
+    fn test_remove_installed_plugin_only_removes_matching_scope() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let project_a = temp_dir.path().join("project-a");
+        let project_b = temp_dir.path().join("project-b");
+        fs::create_dir_all(&project_a).unwrap();
+        fs::create_dir_all(&project_b).unwrap();
+
+        let integration = ClaudeCodeIntegration::with_claude_dir(temp_dir.path().to_path_buf());
+
+        for scope in [
+            PluginScope::User,
+            PluginScope::Project(project_a.clone()),
+            PluginScope::Project(project_b.clone()),
+        ] {
+            integration
+                .add_installed_plugin(
+                    "test-plugin",
+                    "official",
+                    Path::new("/path/to/plugin"),
+                    "1.0.0",
+                    "abc123",
+                    &scope,
+                )
+                .unwrap();
+        }
+
+        let removed = integration
+            .remove_installed_plugin("test-plugin", "official", &PluginScope::Project(project_a))
+            .unwrap();
+        assert!(removed);
+
+        let file = integration.read_installed_plugins().unwrap();
+        let entries = &file.plugins["test-plugin@official"];
+        assert_eq!(entries.len(), 2);
+        assert!(entries.iter().any(|e| e.scope == "user"));
+
+        let canonical_b = fs::canonicalize(&project_b).unwrap();
+        assert!(entries.iter().any(|e| {
+            e.project_path.as_deref() == Some(canonical_b.to_string_lossy().as_ref())
+        }));
+    }
+
+    #[test]
+    fn test_remove_installed_plugin_drops_empty_key() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let integration = ClaudeCodeIntegration::with_claude_dir(temp_dir.path().to_path_buf());
+
+        integration
+            .add_installed_plugin(
+                "test-plugin",
+                "official",
+                Path::new("/path/to/plugin"),
+                "1.0.0",
+                "abc123",
+                &PluginScope::User,
+            )
+            .unwrap();
+
+        assert!(integration
+            .remove_installed_plugin("test-plugin", "official", &PluginScope::User)
+            .unwrap());
+        assert!(!integration
+            .remove_installed_plugin("test-plugin", "official", &PluginScope::User)
+            .unwrap());
+
+        let file = integration.read_installed_plugins().unwrap();
+        assert!(file.plugins.is_empty());
+    }
+
+    #[test]
+    fn test_installed_plugins_in_scope() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let project_dir = temp_dir.path().join("my-project");
+        fs::create_dir_all(&project_dir).unwrap();
+
+        let integration = ClaudeCodeIntegration::with_claude_dir(temp_dir.path().to_path_buf());
+
+        integration
+            .add_installed_plugin(
+                "user-plugin",
+                "official",
+                Path::new("/path/to/user-plugin"),
+                "1.0.0",
+                "abc123",
+                &PluginScope::User,
+            )
+            .unwrap();
+        integration
+            .add_installed_plugin(
+                "project-plugin",
+                "official",
+                Path::new("/path/to/project-plugin"),
+                "1.0.0",
+                "abc123",
+                &PluginScope::Project(project_dir.clone()),
+            )
+            .unwrap();
+
+        let user = integration.installed_plugins_in_scope(&PluginScope::User).unwrap();
+        assert_eq!(user.len(), 1);
+        assert_eq!(user[0].0, "user-plugin@official");
+
+        let project = integration
+            .installed_plugins_in_scope(&PluginScope::Project(project_dir))
+            .unwrap();
+        assert_eq!(project.len(), 1);
+        assert_eq!(project[0].0, "project-plugin@official");
+    }
+
     #[test]
     fn test_read_settings_empty() {
         let temp_dir = tempfile::tempdir().unwrap();
@@ -671,6 +835,30 @@ mod tests {
         assert_eq!(enabled["new-plugin@official"], json!(true));
     }
 
+    #[test]
+    fn test_disable_plugin_preserves_others() {
+        let temp_dir = tempfile::tempdir().unwrap();
+
+        let existing = r#"{
+            "someOtherSetting": "value",
+            "enabledPlugins": {
+                "existing@marketplace": true,
+                "stale@official": true
+            }
+        }"#;
+        fs::write(temp_dir.path().join("settings.json"), existing).unwrap();
+
+        let integration = ClaudeCodeIntegration::with_claude_dir(temp_dir.path().to_path_buf());
+        integration.disable_plugin("stale", "official").unwrap();
+
+        let settings = integration.read_settings().unwrap();
+        assert_eq!(settings["someOtherSetting"], "value");
+
+        let enabled = settings["enabledPlugins"].as_object().unwrap();
+        assert_eq!(enabled.len(), 1);
+        assert_eq!(enabled["existing@marketplace"], json!(true));
+    }
+
     #[test]
     fn test_chrono_iso8601_now() {
         let timestamp = chrono_iso8601_now();
@@ -869,4 +1057,24 @@ mod tests {
         assert_eq!(my_mkt.source["source"], "directory");
         assert_eq!(my_mkt.source["path"], new_path.to_str().unwrap());
     }
+
+    #[test]
+    fn test_unregister_marketplace_preserves_other_entries() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let integration = ClaudeCodeIntegration::with_claude_dir(temp_dir.path().to_path_buf());
+
+        integration
+            .register_marketplace("keep", Path::new("/path/to/keep"))
+            .unwrap();
+        integration
+            .register_marketplace("stale", Path::new("/path/to/stale"))
+            .unwrap();
+
+        assert!(integration.unregister_marketplace("stale").unwrap());
+        assert!(!integration.unregister_marketplace("stale").unwrap());
+
+        let marketplaces = integration.read_known_marketplaces().unwrap();
+        assert_eq!(marketplaces.len(), 1);
+        assert!(marketplaces.contains_key("keep"));
+    }
 }
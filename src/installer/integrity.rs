@@ -0,0 +1,175 @@
+use sha2::{Digest, Sha256};
+use std::fs::Metadata;
+use std::path::Path;
+
+/// Prefix identifying the algorithm of a content hash.
+const HASH_PREFIX: &str = "sha256-";
+
+/// Mode recorded for symbolic links.
+const SYMLINK_MODE: u32 = 0o120000;
+
+/// Compute a deterministic hash of a directory's contents.
+///
+/// Files are hashed in sorted order of their `/`-separated relative paths,
+/// each contributing its path, mode and contents. Modes are normalized the
+/// way git records them (`644` or `755`), so the hash does not depend on the
+/// umask of the machine that extracted the plugin. Empty directories are ignored.
+pub fn content_hash(dir: &Path) -> std::io::Result<String> {
+    let digest = hash_files(dir, false)?;
+    Ok(format!("{}{}", HASH_PREFIX, digest))
+}
+
+/// Compute a hash of a working directory that stands in for a commit.
+///
+/// Like [`content_hash`], but `.git` directories are skipped and the result
+/// is bare hex, so it can be recorded and abbreviated wherever a commit hash is.
+pub fn source_hash(dir: &Path) -> std::io::Result<String> {
+    hash_files(dir, true)
+}
+
+/// Hash the files below a directory, optionally skipping `.git` directories.
+fn hash_files(dir: &Path, skip_git: bool) -> std::io::Result<String> {
+    let mut files = Vec::new();
+    collect_files(dir, "", skip_git, &mut files)?;
+    files.sort();
+
+    let mut hasher = Sha256::new();
+    for relative in &files {
+        let path = dir.join(relative);
+        let metadata = std::fs::symlink_metadata(&path)?;
+
+        let (mode, contents) = if metadata.file_type().is_symlink() {
+            let target = std::fs::read_link(&path)?;
+            (SYMLINK_MODE, target.to_string_lossy().into_owned().into_bytes())
+        } else {
+            (file_mode(&metadata), std::fs::read(&path)?)
+        };
+
+        hasher.update(relative.as_bytes());
+        hasher.update([0]);
+        hasher.update(format!("{:o}", mode).as_bytes());
+        hasher.update([0]);
+        hasher.update((contents.len() as u64).to_le_bytes());
+        hasher.update(&contents);
+    }
+
+    Ok(format!("{:x}", hasher.finalize()))
+}
+
+/// Collect the relative paths of all files below a directory.
+fn collect_files(dir: &Path, prefix: &str, skip_git: bool, files: &mut Vec<String>) -> std::io::Result<()> {
+    for entry in std::fs::read_dir(dir)? {
+        let entry = entry?;
+        let name = entry.file_name().to_string_lossy().into_owned();
+        if skip_git && name == ".git" {
+            continue;
+        }
+        let relative = if prefix.is_empty() {
+            name
+        } else {
+            format!("{}/{}", prefix, name)
+        };
+
+        if entry.file_type()?.is_dir() {
+            collect_files(&entry.path(), &relative, skip_git, files)?;
+        } else {
+            files.push(relative);
+        }
+    }
+    Ok(())
+}
+
+/// Normalize a file's permissions to git's executable or regular file mode.
+#[cfg(unix)]
+fn file_mode(metadata: &Metadata) -> u32 {
+    use std::os::unix::fs::PermissionsExt;
+
+    if metadata.permissions().mode() & 0o111 != 0 {
+        0o100755
+    } else {
+        0o100644
+    }
+}
+
+#[cfg(not(unix))]
+fn file_mode(_metadata: &Metadata) -> u32 {
+    0o100644
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    fn write_plugin(dir: &Path) {
+        fs::create_dir_all(dir.join(".claude-plugin")).unwrap();
+        fs::create_dir_all(dir.join("skills/review")).unwrap();
+        fs::write(dir.join(".claude-plugin/plugin.json"), r#"{"version": "1.0"}"#).unwrap();
+        fs::write(dir.join("skills/review/SKILL.md"), "# Review").unwrap();
+    }
+
+    #[test]
+    fn test_content_hash_is_deterministic() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let a = temp_dir.path().join("a");
+        let b = temp_dir.path().join("b");
+        write_plugin(&a);
+        write_plugin(&b);
+
+        let hash = content_hash(&a).unwrap();
+        assert!(hash.starts_with("sha256-"));
+        assert_eq!(hash, content_hash(&b).unwrap());
+
+        // Empty directories do not contribute
+        fs::create_dir_all(b.join("empty")).unwrap();
+        assert_eq!(hash, content_hash(&b).unwrap());
+    }
+
+    #[test]
+    fn test_content_hash_detects_changes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        write_plugin(temp_dir.path());
+        let original = content_hash(temp_dir.path()).unwrap();
+
+        fs::write(temp_dir.path().join("skills/review/SKILL.md"), "# Tampered").unwrap();
+        assert_ne!(content_hash(temp_dir.path()).unwrap(), original);
+
+        write_plugin(temp_dir.path());
+        assert_eq!(content_hash(temp_dir.path()).unwrap(), original);
+
+        fs::remove_file(temp_dir.path().join("skills/review/SKILL.md")).unwrap();
+        assert_ne!(content_hash(temp_dir.path()).unwrap(), original);
+    }
+
+    #[test]
+    fn test_source_hash_skips_git() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        write_plugin(temp_dir.path());
+        let original = source_hash(temp_dir.path()).unwrap();
+        assert_eq!(original.len(), 64);
+        assert_eq!(format!("sha256-{}", original), content_hash(temp_dir.path()).unwrap());
+
+        fs::create_dir_all(temp_dir.path().join(".git")).unwrap();
+        fs::write(temp_dir.path().join(".git/HEAD"), "ref: refs/heads/main").unwrap();
+        assert_eq!(source_hash(temp_dir.path()).unwrap(), original);
+        assert_ne!(format!("sha256-{}", original), content_hash(temp_dir.path()).unwrap());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_content_hash_includes_executable_bit() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let temp_dir = tempfile::tempdir().unwrap();
+        write_plugin(temp_dir.path());
+        let original = content_hash(temp_dir.path()).unwrap();
+
+        let script = temp_dir.path().join("skills/review/SKILL.md");
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o755)).unwrap();
+        assert_ne!(content_hash(temp_dir.path()).unwrap(), original);
+
+        // Group/other bits other than execute do not matter
+        fs::set_permissions(&script, fs::Permissions::from_mode(0o600)).unwrap();
+        assert_eq!(content_hash(temp_dir.path()).unwrap(), original);
+    }
+}
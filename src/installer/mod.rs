@@ -1,5 +1,13 @@
+mod atomic;
 mod cache;
 mod claude;
+mod integrity;
+mod transaction;
 
-pub use cache::CacheManager;
-pub use claude::{ClaudeCodeIntegration, InstalledPluginEntry, InstalledPluginsFile, PluginScope};
+pub use cache::{CacheEntry, CacheEntryKind, CacheManager, ExtractedPlugin, GitPluginSource};
+pub use claude::{
+    ClaudeCodeIntegration, InstalledPluginEntry, InstalledPluginsFile, KnownMarketplaceEntry,
+    PluginScope,
+};
+pub use integrity::{content_hash, source_hash};
+pub use transaction::{Recovered, Transaction};
@@ -0,0 +1,369 @@
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::path::{Path, PathBuf};
+
+use tracing::debug;
+
+use super::atomic::{self, FileLock};
+use super::claude::chrono_iso8601_now;
+use crate::{Error, Result};
+
+/// Directory in the cache holding the journal of the running install.
+const TRANSACTION_DIR: &str = "transaction";
+
+/// The journal, written once every snapshot is in place.
+const JOURNAL_FILENAME: &str = "journal.json";
+
+/// Directory next to the journal holding the snapshots.
+const BACKUP_DIR: &str = "backup";
+
+/// Files an install may change, snapshotted so they can be restored together.
+///
+/// [`Transaction::begin`] takes a lock that serializes installs and copies
+/// each file into the cache before anything is changed. [`Transaction::commit`]
+/// drops the snapshots; [`Transaction::rollback`] puts them back. A journal
+/// listing the snapshots stays on disk until either happens, so if the process
+/// is killed in between, the files can be restored by [`Transaction::recover`].
+/// No transaction begins while such a journal is left.
+///
+/// An interrupted transaction is only ever undone, never finished: its
+/// changes are rolled back and the command has to be run again. Since that
+/// may happen long after the process died, the journal records a hash of
+/// each file as the transaction last left it (see [`Transaction::checkpoint`]),
+/// and a file that no longer matches has been edited since, so it is left
+/// alone instead of being reverted.
+pub struct Transaction {
+    dir: PathBuf,
+    journal: Journal,
+    _lock: FileLock,
+}
+
+/// An interrupted transaction rolled back by [`Transaction::recover`].
+#[derive(Debug)]
+pub struct Recovered {
+    /// When the interrupted transaction started.
+    pub started: String,
+    /// Files changed after the interrupted transaction last wrote them,
+    /// which were left as they are.
+    pub skipped: Vec<PathBuf>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct Journal {
+    started: String,
+    files: Vec<JournalEntry>,
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct JournalEntry {
+    /// Absolute path of the file.
+    path: PathBuf,
+    /// Name of the snapshot in the backup directory, or `None` if the file
+    /// did not exist.
+    backup: Option<String>,
+    /// Hash of the file as of the last checkpoint, or `None` if it did not
+    /// exist.
+    written: Option<String>,
+}
+
+impl Transaction {
+    /// Roll back an interrupted transaction found under `cache_dir`, waiting
+    /// for any running one to finish first. Files edited since the interrupted
+    /// transaction last wrote them are skipped.
+    pub fn recover(cache_dir: &Path) -> Result<Option<Recovered>> {
+        let dir = cache_dir.join(TRANSACTION_DIR);
+        let journal_path = dir.join(JOURNAL_FILENAME);
+        let _lock = Self::lock(cache_dir)?;
+
+        let Some(content) = atomic::read(&journal_path)? else {
+            return Ok(None);
+        };
+        let journal = parse_journal(&journal_path, &content)?;
+        debug!(started = %journal.started, "rolling back interrupted transaction");
+        let (_, skipped) = restore(&dir, &journal, true)?;
+        clear(&dir)?;
+        Ok(Some(Recovered {
+            started: journal.started,
+            skipped,
+        }))
+    }
+
+    /// Snapshot `paths` under `cache_dir`, waiting for any other install to
+    /// finish first. Fails with [`Error::InterruptedInstall`] if an interrupted
+    /// transaction has not been recovered.
+    pub fn begin(cache_dir: &Path, paths: &[PathBuf]) -> Result<Self> {
+        let dir = cache_dir.join(TRANSACTION_DIR);
+        let journal_path = dir.join(JOURNAL_FILENAME);
+        let lock = Self::lock(cache_dir)?;
+
+        if let Some(content) = atomic::read(&journal_path)? {
+            let journal = parse_journal(&journal_path, &content)?;
+            return Err(Error::InterruptedInstall(journal.started));
+        }
+        clear(&dir)?;
+
+        let backup_dir = dir.join(BACKUP_DIR);
+        std::fs::create_dir_all(&backup_dir).map_err(|e| Error::FileWrite {
+            path: backup_dir.clone(),
+            source: e,
+        })?;
+
+        let mut files = Vec::with_capacity(paths.len());
+        for (index, path) in paths.iter().enumerate() {
+            let path = std::path::absolute(path).unwrap_or_else(|_| path.clone());
+            let content = atomic::read(&path)?;
+            let backup = match content {
+                Some(ref content) => {
+                    let name = index.to_string();
+                    atomic::write_atomic(&backup_dir.join(&name), content)?;
+                    Some(name)
+                }
+                None => None,
+            };
+            let written = content.as_deref().map(file_hash);
+            files.push(JournalEntry { path, backup, written });
+        }
+
+        let transaction = Self {
+            dir,
+            journal: Journal {
+                started: chrono_iso8601_now(),
+                files,
+            },
+            _lock: lock,
+        };
+        transaction.write_journal()?;
+        Ok(transaction)
+    }
+
+    /// Record the current state of every snapshotted file in the journal.
+    ///
+    /// Called after each step that writes the files: if the process is killed
+    /// later, only files still in this state are rolled back. A file written
+    /// after the last checkpoint counts as edited by someone else and is kept.
+    pub fn checkpoint(&mut self) -> Result<()> {
+        for entry in &mut self.journal.files {
+            entry.written = atomic::read(&entry.path)?.as_deref().map(file_hash);
+        }
+        self.write_journal()
+    }
+
+    fn write_journal(&self) -> Result<()> {
+        let journal_path = self.dir.join(JOURNAL_FILENAME);
+        let content = serde_json::to_vec_pretty(&self.journal).map_err(|e| Error::JsonParse {
+            path: journal_path.clone(),
+            source: e,
+        })?;
+        atomic::write_atomic(&journal_path, &content)
+    }
+
+    /// Wait for any running transaction under `cache_dir` to finish and keep
+    /// others from starting while the returned lock is held.
+    ///
+    /// Unlike [`Self::begin`], this neither snapshots nor recovers anything.
+    pub(crate) fn lock(cache_dir: &Path) -> Result<FileLock> {
+        FileLock::acquire(&cache_dir.join(TRANSACTION_DIR).join(JOURNAL_FILENAME))
+    }
+
+    /// Start time of a transaction whose journal is still in the cache.
+    ///
+    /// The transaction was interrupted, unless another process is running it
+    /// right now.
+    pub fn pending(cache_dir: &Path) -> Result<Option<String>> {
+        let journal_path = cache_dir.join(TRANSACTION_DIR).join(JOURNAL_FILENAME);
+        let Some(content) = atomic::read(&journal_path)? else {
+            return Ok(None);
+        };
+        let journal = parse_journal(&journal_path, &content)?;
+        Ok(Some(journal.started))
+    }
+
+    /// Keep every change and drop the snapshots.
+    pub fn commit(self) -> Result<()> {
+        clear(&self.dir)
+    }
+
+    /// Put every snapshotted file back as it was, removing files that did
+    /// not exist. Returns the number of files that had changed.
+    ///
+    /// Unlike recovery, this happens right after the failure, so files are
+    /// restored even if they were written after the last checkpoint.
+    pub fn rollback(self) -> Result<usize> {
+        let (restored, _) = restore(&self.dir, &self.journal, false)?;
+        clear(&self.dir)?;
+        Ok(restored)
+    }
+}
+
+fn parse_journal(path: &Path, content: &[u8]) -> Result<Journal> {
+    serde_json::from_slice(content).map_err(|e| Error::JsonParse {
+        path: path.to_path_buf(),
+        source: e,
+    })
+}
+
+/// Hash of a file's contents, as recorded in the journal.
+fn file_hash(content: &[u8]) -> String {
+    format!("{:x}", Sha256::digest(content))
+}
+
+/// Restore the files a journal lists from their snapshots. Files that are
+/// already as snapshotted are left alone. With `checked`, so are files that
+/// changed since the last checkpoint. Returns the number of restored files
+/// and the paths of the files skipped because they changed.
+fn restore(dir: &Path, journal: &Journal, checked: bool) -> Result<(usize, Vec<PathBuf>)> {
+    let mut restored = 0;
+    let mut skipped = Vec::new();
+    for entry in &journal.files {
+        let current = atomic::read(&entry.path)?;
+        let backup = match &entry.backup {
+            Some(name) => {
+                let backup_path = dir.join(BACKUP_DIR).join(name);
+                let content = std::fs::read(&backup_path).map_err(|e| Error::FileRead {
+                    path: backup_path,
+                    source: e,
+                })?;
+                Some(content)
+            }
+            None => None,
+        };
+        if current == backup {
+            continue;
+        }
+        if checked && current.as_deref().map(file_hash) != entry.written {
+            debug!(path = %entry.path.display(), "file changed since the last checkpoint, leaving it");
+            skipped.push(entry.path.clone());
+            continue;
+        }
+
+        match backup {
+            Some(content) => {
+                debug!(path = %entry.path.display(), "restoring snapshot");
+                atomic::write_atomic(&entry.path, &content)?;
+            }
+            None => {
+                debug!(path = %entry.path.display(), "removing file created by the transaction");
+                std::fs::remove_file(&entry.path).map_err(|e| Error::FileWrite {
+                    path: entry.path.clone(),
+                    source: e,
+                })?;
+            }
+        }
+        restored += 1;
+    }
+    Ok((restored, skipped))
+}
+
+/// Remove the journal, then the snapshots. The lock file stays, since
+/// another install may be waiting on it.
+fn clear(dir: &Path) -> Result<()> {
+    let journal_path = dir.join(JOURNAL_FILENAME);
+    if journal_path.exists() {
+        std::fs::remove_file(&journal_path).map_err(|e| Error::FileWrite {
+            path: journal_path,
+            source: e,
+        })?;
+    }
+
+    let backup_dir = dir.join(BACKUP_DIR);
+    if backup_dir.exists() {
+        std::fs::remove_dir_all(&backup_dir).map_err(|e| Error::FileWrite {
+            path: backup_dir,
+            source: e,
+        })?;
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::fs;
+
+    #[test]
+    fn test_rollback_restores_files() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache_dir = temp_dir.path().join("cache");
+        let settings = temp_dir.path().join("settings.json");
+        let lock = temp_dir.path().join("plugins.lock");
+        fs::write(&settings, "before").unwrap();
+
+        let transaction = Transaction::begin(&cache_dir, &[settings.clone(), lock.clone()]).unwrap();
+        fs::write(&settings, "after").unwrap();
+        fs::write(&lock, "new lock").unwrap();
+        assert_eq!(transaction.rollback().unwrap(), 2);
+
+        assert_eq!(fs::read_to_string(&settings).unwrap(), "before");
+        assert!(!lock.exists());
+        assert!(!cache_dir.join(TRANSACTION_DIR).join(JOURNAL_FILENAME).exists());
+    }
+
+    #[test]
+    fn test_commit_keeps_changes() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache_dir = temp_dir.path().join("cache");
+        let settings = temp_dir.path().join("settings.json");
+        fs::write(&settings, "before").unwrap();
+
+        let transaction = Transaction::begin(&cache_dir, std::slice::from_ref(&settings)).unwrap();
+        fs::write(&settings, "after").unwrap();
+        transaction.commit().unwrap();
+
+        assert_eq!(fs::read_to_string(&settings).unwrap(), "after");
+        assert!(!cache_dir.join(TRANSACTION_DIR).join(BACKUP_DIR).exists());
+
+        // Nothing is left to recover
+        assert!(Transaction::recover(&cache_dir).unwrap().is_none());
+        assert_eq!(fs::read_to_string(&settings).unwrap(), "after");
+    }
+
+    #[test]
+    fn test_interrupted_transaction_is_rolled_back() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache_dir = temp_dir.path().join("cache");
+        let settings = temp_dir.path().join("settings.json");
+        fs::write(&settings, "before").unwrap();
+
+        // The process dies before committing
+        let mut transaction = Transaction::begin(&cache_dir, std::slice::from_ref(&settings)).unwrap();
+        fs::write(&settings, "half-written").unwrap();
+        transaction.checkpoint().unwrap();
+        drop(transaction);
+
+        // Nothing begins until it is rolled back
+        assert!(Transaction::pending(&cache_dir).unwrap().is_some());
+        let result = Transaction::begin(&cache_dir, std::slice::from_ref(&settings));
+        assert!(matches!(result, Err(Error::InterruptedInstall(_))));
+        assert_eq!(fs::read_to_string(&settings).unwrap(), "half-written");
+
+        assert!(Transaction::recover(&cache_dir).unwrap().is_some());
+        assert_eq!(fs::read_to_string(&settings).unwrap(), "before");
+        assert!(Transaction::pending(&cache_dir).unwrap().is_none());
+        Transaction::begin(&cache_dir, std::slice::from_ref(&settings)).unwrap().commit().unwrap();
+    }
+
+    #[test]
+    fn test_recovery_keeps_files_edited_since() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let cache_dir = temp_dir.path().join("cache");
+        let settings = temp_dir.path().join("settings.json");
+        let installed = temp_dir.path().join("installed_plugins.json");
+        fs::write(&settings, "before").unwrap();
+        fs::write(&installed, "before").unwrap();
+
+        // The process dies after a checkpoint
+        let mut transaction = Transaction::begin(&cache_dir, &[settings.clone(), installed.clone()]).unwrap();
+        fs::write(&settings, "installed").unwrap();
+        fs::write(&installed, "installed").unwrap();
+        transaction.checkpoint().unwrap();
+        drop(transaction);
+
+        // Someone edits one of the files before the next run
+        fs::write(&settings, "edited by hand").unwrap();
+
+        let recovered = Transaction::recover(&cache_dir).unwrap().unwrap();
+        assert_eq!(recovered.skipped, std::slice::from_ref(&settings));
+        assert_eq!(fs::read_to_string(&settings).unwrap(), "edited by hand");
+        assert_eq!(fs::read_to_string(&installed).unwrap(), "before");
+    }
+}
@@ -3,6 +3,8 @@ pub mod config;
 pub mod installer;
 pub mod layout;
 pub mod resolver;
+#[cfg(test)]
+mod test_util;
 
 pub use layout::PluginLayout;
 
@@ -23,6 +25,12 @@ pub enum Error {
     #[error("failed to parse lock file: {0}")]
     LockFileParse(String),
 
+    #[error("lock file not found at {0} (run `skill-manager install` without --locked or --frozen to create it)")]
+    LockNotFound(PathBuf),
+
+    #[error("lock file {path} is out of date with the manifest:\n{changes}\nRun `skill-manager install` without --locked or --frozen to update it")]
+    LockOutdated { path: PathBuf, changes: String },
+
     #[error("marketplace '{0}' not declared in manifest")]
     UndeclaredMarketplace(String),
 
@@ -47,9 +55,21 @@ pub enum Error {
     #[error("tag '{tag}' not found in marketplace '{marketplace}'")]
     TagNotFound { marketplace: String, tag: String },
 
+    #[error("branch '{branch}' not found in '{name}'")]
+    BranchNotFound { name: String, branch: String },
+
+    #[error("no tag in '{name}' matches version requirement '{requirement}'")]
+    NoMatchingVersion { name: String, requirement: String },
+
     #[error("commit '{commit}' not found in marketplace '{marketplace}'")]
     CommitNotFound { marketplace: String, commit: String },
 
+    #[error("{0} is not cached (run without --offline to fetch it)")]
+    NotCachedOffline(String),
+
+    #[error("{0} in the cached clone (run without --offline to fetch it)")]
+    Offline(Box<Error>),
+
     #[error("marketplace.json not found in '{0}'")]
     MarketplaceJsonNotFound(String),
 
@@ -59,6 +79,24 @@ pub enum Error {
     #[error("plugin '{plugin}' not found in marketplace '{marketplace}'")]
     PluginNotFound { plugin: String, marketplace: String },
 
+    #[error("invalid dependency '{dependency}' of plugin '{plugin}': {reason}")]
+    InvalidDependency {
+        plugin: String,
+        dependency: String,
+        reason: String,
+    },
+
+    #[error("plugin '{dependent}' requires '{plugin}' {requirement}, but {resolved} is locked")]
+    DependencyConflict {
+        plugin: String,
+        dependent: String,
+        requirement: String,
+        resolved: String,
+    },
+
+    #[error("dependency cycle: {0}")]
+    DependencyCycle(String),
+
     // Installer errors
     #[error("failed to create cache directory: {0}")]
     CacheCreate(#[source] std::io::Error),
@@ -66,6 +104,16 @@ pub enum Error {
     #[error("failed to extract plugin '{0}': {1}")]
     PluginExtract(String, #[source] std::io::Error),
 
+    #[error("{0} is not in the cache (run `skill-manager install` without --frozen to fetch it)")]
+    NotInCache(String),
+
+    #[error("plugin '{plugin}' does not match plugins.lock: expected {expected}, got {actual}")]
+    ContentHashMismatch {
+        plugin: String,
+        expected: String,
+        actual: String,
+    },
+
     #[error("failed to read {path}: {source}")]
     FileRead {
         path: PathBuf,
@@ -80,6 +128,12 @@ pub enum Error {
         source: std::io::Error,
     },
 
+    #[error("{0} kept changing while it was being updated; try again")]
+    ConcurrentModification(PathBuf),
+
+    #[error("an install started at {0} did not finish (run `skill-manager install` to roll it back)")]
+    InterruptedInstall(String),
+
     #[error("failed to parse JSON in {path}: {source}")]
     JsonParse {
         path: PathBuf,
@@ -94,9 +148,22 @@ pub enum Error {
     #[error("no manifest found (run 'skill-manager init' first)")]
     NoManifest,
 
+    #[error("doctor found {0} error(s)")]
+    DoctorFoundErrors(usize),
+
     #[error("operation aborted by user")]
     Aborted,
 
+    #[error(
+        "plugin '{plugin}' is declared as {global} globally but {project} in the project \
+         (use --prefer-global or --prefer-project)"
+    )]
+    PluginConflict {
+        plugin: String,
+        global: String,
+        project: String,
+    },
+
     // Git errors
     #[error("git error: {0}")]
     Git(#[from] git2::Error),
@@ -104,5 +171,32 @@ pub enum Error {
     // IO errors
     #[error("IO error: {0}")]
     Io(#[from] std::io::Error),
+
+    #[error("{} errors:\n{}", .0.len(), format_errors(.0))]
+    Multiple(Vec<Error>),
+}
+
+impl Error {
+    /// Point out that a commit, tag, branch or version that was not found
+    /// may only be missing from the cache, for errors raised with `--offline`.
+    pub fn offline(self) -> Self {
+        match self {
+            Error::CommitNotFound { .. }
+            | Error::TagNotFound { .. }
+            | Error::BranchNotFound { .. }
+            | Error::NoMatchingVersion { .. } => Error::Offline(Box::new(self)),
+            Error::Multiple(errors) => Error::Multiple(errors.into_iter().map(Error::offline).collect()),
+            other => other,
+        }
+    }
+}
+
+/// List errors one per line for [`Error::Multiple`].
+fn format_errors(errors: &[Error]) -> String {
+    errors
+        .iter()
+        .map(|e| format!("  - {}", e))
+        .collect::<Vec<_>>()
+        .join("\n")
 }
 
@@ -0,0 +1,305 @@
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+use tracing::debug;
+
+use super::marketplace::MarketplaceResolver;
+use crate::{Error, Result};
+
+/// File in a cached clone's `.git` directory recording when it was last
+/// fetched, in seconds since the Unix epoch.
+const LAST_FETCH_FILENAME: &str = "skill-manager-last-fetch";
+
+/// When cached repositories are fetched before use.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum FetchPolicy {
+    /// Fetch every repository once per run.
+    #[default]
+    Always,
+    /// Skip repositories fetched within the given duration.
+    Ttl(Duration),
+    /// Never touch the network; only cached clones are used.
+    Offline,
+}
+
+/// A repository to clone or fetch before resolving or installing.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FetchTarget {
+    /// A marketplace repository.
+    Marketplace { name: String, url: String },
+    /// An external plugin's repository. With a commit, the repository is
+    /// only fetched when that commit is missing.
+    PluginRepo {
+        marketplace: String,
+        plugin: String,
+        url: String,
+        commit: Option<String>,
+    },
+}
+
+impl MarketplaceResolver {
+    /// Clone or fetch repositories in parallel, up to [`Self::jobs`] at a time.
+    ///
+    /// Each distinct repository is fetched once, and directory marketplaces
+    /// not at all. All targets are attempted, and their errors are reported
+    /// together.
+    pub fn prefetch(&self, targets: Vec<FetchTarget>) -> Result<()> {
+        let mut seen = HashSet::new();
+        let targets: Vec<FetchTarget> = targets
+            .into_iter()
+            .filter(|target| !matches!(target, FetchTarget::Marketplace { name, .. } if self.directory(name).is_some()))
+            .filter(|target| seen.insert(self.target_path(target)))
+            .collect();
+
+        let results = map_parallel(&targets, self.jobs(), |target| match target {
+            FetchTarget::Marketplace { name, url } => self.ensure_marketplace(name, url).map(drop),
+            FetchTarget::PluginRepo {
+                marketplace,
+                plugin,
+                url,
+                commit,
+            } => self.fetch_plugin_repo_for(marketplace, plugin, url, commit.as_deref()),
+        });
+
+        collect_results(results).map(drop)
+    }
+
+    /// Whether an existing clone has to be fetched before it is used.
+    ///
+    /// Repositories are fetched at most once per run, never when offline, and
+    /// not again within the fetch TTL.
+    pub(crate) fn needs_fetch(&self, path: &Path) -> bool {
+        if self.is_fetched(path) {
+            return false;
+        }
+        match self.fetch_policy() {
+            FetchPolicy::Always => true,
+            FetchPolicy::Offline => false,
+            FetchPolicy::Ttl(ttl) => match last_fetch_time(path).map(|t| t.elapsed()) {
+                Some(Ok(age)) if age < ttl => {
+                    debug!(path = %path.display(), ?age, "fetched within TTL, skipping fetch");
+                    false
+                }
+                _ => true,
+            },
+        }
+    }
+
+    /// The cache directory a fetch target is cloned into.
+    fn target_path(&self, target: &FetchTarget) -> PathBuf {
+        match target {
+            FetchTarget::Marketplace { name, .. } => self.marketplace_path(name),
+            FetchTarget::PluginRepo { marketplace, plugin, .. } => self.plugin_repo_path(marketplace, plugin),
+        }
+    }
+}
+
+/// When the clone at `path` was last fetched, if recorded.
+pub(crate) fn last_fetch_time(path: &Path) -> Option<SystemTime> {
+    let content = std::fs::read_to_string(path.join(".git").join(LAST_FETCH_FILENAME)).ok()?;
+    let secs = content.trim().parse().ok()?;
+    Some(UNIX_EPOCH + Duration::from_secs(secs))
+}
+
+/// Record that the clone at `path` was fetched just now.
+///
+/// Failures are logged and ignored; the repository is then fetched again
+/// on the next run.
+pub(crate) fn record_fetch_time(path: &Path) {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    let stamp = path.join(".git").join(LAST_FETCH_FILENAME);
+    if let Err(e) = std::fs::write(&stamp, secs.to_string()) {
+        debug!(error = %e, path = %stamp.display(), "failed to record fetch time");
+    }
+}
+
+/// Apply `f` to every item on up to `jobs` threads.
+///
+/// Results are returned in the order of `items`, regardless of the order in
+/// which the work completes.
+pub(crate) fn map_parallel<T, R, F>(items: &[T], jobs: usize, f: F) -> Vec<R>
+where
+    T: Sync,
+    R: Send,
+    F: Fn(&T) -> R + Sync,
+{
+    let threads = jobs.max(1).min(items.len());
+    if threads <= 1 {
+        return items.iter().map(f).collect();
+    }
+
+    let next = AtomicUsize::new(0);
+    let results: Mutex<Vec<Option<R>>> = Mutex::new(items.iter().map(|_| None).collect());
+
+    std::thread::scope(|scope| {
+        for _ in 0..threads {
+            scope.spawn(|| {
+                loop {
+                    let index = next.fetch_add(1, Ordering::Relaxed);
+                    let Some(item) = items.get(index) else {
+                        break;
+                    };
+                    let result = f(item);
+                    results.lock().unwrap_or_else(|e| e.into_inner())[index] = Some(result);
+                }
+            });
+        }
+    });
+
+    results
+        .into_inner()
+        .unwrap_or_else(|e| e.into_inner())
+        .into_iter()
+        .map(|result| result.expect("every item is processed"))
+        .collect()
+}
+
+/// Collect results, reporting every error rather than only the first.
+pub(crate) fn collect_results<R>(results: Vec<Result<R>>) -> Result<Vec<R>> {
+    let mut values = Vec::with_capacity(results.len());
+    let mut errors = Vec::new();
+    for result in results {
+        match result {
+            Ok(value) => values.push(value),
+            Err(e) => errors.push(e),
+        }
+    }
+
+    match errors.len() {
+        0 => Ok(values),
+        1 => Err(errors.remove(0)),
+        _ => Err(Error::Multiple(errors)),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_util::commit_files;
+
+    #[test]
+    fn test_fetch_policy() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let upstream_dir = temp_dir.path().join("upstream");
+        let upstream = git2::Repository::init(&upstream_dir).unwrap();
+        commit_files(&upstream, &[("README.md", "# Test")], "Initial commit");
+        let url = upstream_dir.to_string_lossy().to_string();
+        let cache_dir = temp_dir.path().join("cache");
+
+        // Nothing is cached yet
+        let offline = MarketplaceResolver::new(cache_dir.clone()).with_fetch_policy(FetchPolicy::Offline);
+        let result = offline.ensure_marketplace("test", &url);
+        assert!(matches!(result, Err(Error::NotCachedOffline(_))));
+
+        let resolver = MarketplaceResolver::new(cache_dir.clone());
+        let repo = resolver.ensure_marketplace("test", &url).unwrap();
+        let first = resolver.resolve_head(&repo).unwrap();
+        assert!(last_fetch_time(&resolver.marketplace_path("test")).is_some());
+
+        let latest = commit_files(&upstream, &[("NEW", "NEW")], "NEW");
+
+        // Offline and within the TTL, the cached clone is used as it is
+        for policy in [FetchPolicy::Offline, FetchPolicy::Ttl(Duration::from_secs(3600))] {
+            let resolver = MarketplaceResolver::new(cache_dir.clone()).with_fetch_policy(policy);
+            let repo = resolver.ensure_marketplace("test", &url).unwrap();
+            assert_eq!(resolver.resolve_head(&repo).unwrap(), first);
+        }
+
+        // An expired TTL fetches again
+        let resolver =
+            MarketplaceResolver::new(cache_dir.clone()).with_fetch_policy(FetchPolicy::Ttl(Duration::ZERO));
+        let repo = resolver.ensure_marketplace("test", &url).unwrap();
+        assert_eq!(resolver.resolve_head(&repo).unwrap(), latest);
+    }
+
+    #[test]
+    fn test_offline_errors_mention_the_cache() {
+        let err = Error::Multiple(vec![
+            Error::CommitNotFound {
+                marketplace: "dev".to_string(),
+                commit: "abc1234".to_string(),
+            },
+            Error::NoManifest,
+        ])
+        .offline();
+        assert_eq!(
+            err.to_string(),
+            "2 errors:\n  - commit 'abc1234' not found in marketplace 'dev' in the cached clone \
+             (run without --offline to fetch it)\n  - no manifest found (run 'skill-manager init' first)"
+        );
+    }
+
+    #[test]
+    fn test_map_parallel_keeps_order() {
+        let items: Vec<u64> = (0..50).collect();
+        let results = map_parallel(&items, 8, |n| {
+            // Later items finish first
+            std::thread::sleep(std::time::Duration::from_micros(50 - n));
+            n * 2
+        });
+        assert_eq!(results, items.iter().map(|n| n * 2).collect::<Vec<_>>());
+    }
+
+    #[test]
+    fn test_collect_results_aggregates_errors() {
+        assert_eq!(collect_results(vec![Ok(1), Ok(2)]).unwrap(), vec![1, 2]);
+
+        let err = collect_results::<()>(vec![Err(Error::NoManifest)]).unwrap_err();
+        assert!(matches!(err, Error::NoManifest));
+
+        let err = collect_results(vec![
+            Err(Error::NoManifest),
+            Ok(()),
+            Err(Error::UndeclaredMarketplace("dev".to_string())),
+        ])
+        .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "2 errors:\n  - no manifest found (run 'skill-manager init' first)\n  \
+             - marketplace 'dev' not declared in manifest"
+        );
+    }
+
+    #[test]
+    fn test_prefetch_clones_each_repo_once() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let upstream = temp_dir.path().join("upstream");
+        let repo = git2::Repository::init(&upstream).unwrap();
+        commit_files(&repo, &[("README.md", "# Test")], "Initial commit");
+        let url = upstream.to_string_lossy().to_string();
+
+        let resolver = MarketplaceResolver::new(temp_dir.path().join("cache")).with_jobs(4);
+        let marketplace = FetchTarget::Marketplace {
+            name: "test".to_string(),
+            url: url.clone(),
+        };
+        let plugin = FetchTarget::PluginRepo {
+            marketplace: "test".to_string(),
+            plugin: "plugin".to_string(),
+            url,
+            commit: None,
+        };
+        resolver
+            .prefetch(vec![marketplace.clone(), plugin, marketplace])
+            .unwrap();
+
+        assert!(resolver.is_fetched(&resolver.marketplace_path("test")));
+        assert!(resolver.is_fetched(&resolver.plugin_repo_path("test", "plugin")));
+
+        let missing = FetchTarget::Marketplace {
+            name: "missing".to_string(),
+            url: temp_dir.path().join("missing").to_string_lossy().to_string(),
+        };
+        let other = FetchTarget::Marketplace {
+            name: "other".to_string(),
+            url: temp_dir.path().join("other").to_string_lossy().to_string(),
+        };
+        let err = resolver.prefetch(vec![missing, other]).unwrap_err();
+        assert!(matches!(err, Error::Multiple(ref errors) if errors.len() == 2));
+    }
+}
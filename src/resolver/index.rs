@@ -0,0 +1,218 @@
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+use tracing::debug;
+
+use super::marketplace::{read_marketplace_json_file, MarketplaceResolver};
+use crate::config::MarketplaceEntry;
+use crate::{Error, Result};
+
+/// Search index filename within the cache directory.
+pub const INDEX_FILENAME: &str = "search-index.json";
+
+/// A plugin listed in a marketplace.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedPlugin {
+    pub name: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub description: Option<String>,
+}
+
+/// The plugins listed by a marketplace at a given commit.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IndexedMarketplace {
+    pub url: String,
+    pub commit: String,
+    pub plugins: Vec<IndexedPlugin>,
+}
+
+/// A plugin matching a search query.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct SearchMatch<'a> {
+    pub marketplace: &'a str,
+    pub plugin: &'a IndexedPlugin,
+}
+
+/// Cached listing of every indexed marketplace's plugins.
+///
+/// Stored in the cache directory so searches keep working when a
+/// marketplace cannot be fetched.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SearchIndex {
+    #[serde(default)]
+    pub marketplaces: BTreeMap<String, IndexedMarketplace>,
+}
+
+impl SearchIndex {
+    /// Get the index path for a cache directory.
+    pub fn path(cache_dir: &Path) -> PathBuf {
+        cache_dir.join(INDEX_FILENAME)
+    }
+
+    /// Load the index, or an empty index if it does not exist yet.
+    pub fn load(path: &Path) -> Result<Self> {
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let content = std::fs::read_to_string(path).map_err(|e| Error::FileRead {
+            path: path.to_path_buf(),
+            source: e,
+        })?;
+
+        serde_json::from_str(&content).map_err(|e| Error::JsonParse {
+            path: path.to_path_buf(),
+            source: e,
+        })
+    }
+
+    /// Save the index.
+    pub fn save(&self, path: &Path) -> Result<()> {
+        let content = serde_json::to_string_pretty(self).map_err(|e| Error::JsonParse {
+            path: path.to_path_buf(),
+            source: e,
+        })?;
+
+        std::fs::write(path, content).map_err(|e| Error::FileWrite {
+            path: path.to_path_buf(),
+            source: e,
+        })
+    }
+
+    /// Find plugins whose name or description contains the query (case-insensitive).
+    pub fn search(&self, query: &str) -> Vec<SearchMatch<'_>> {
+        let query = query.to_lowercase();
+
+        self.marketplaces
+            .iter()
+            .flat_map(|(name, marketplace)| {
+                marketplace.plugins.iter().map(move |plugin| SearchMatch {
+                    marketplace: name,
+                    plugin,
+                })
+            })
+            .filter(|m| {
+                m.plugin.name.to_lowercase().contains(&query)
+                    || m
+                        .plugin
+                        .description
+                        .as_ref()
+                        .is_some_and(|d| d.to_lowercase().contains(&query))
+            })
+            .collect()
+    }
+
+    /// List the marketplaces that contain a plugin with exactly this name.
+    pub fn marketplaces_with(&self, plugin: &str) -> Vec<String> {
+        self.marketplaces
+            .iter()
+            .filter(|(_, m)| m.plugins.iter().any(|p| p.name == plugin))
+            .map(|(name, _)| name.clone())
+            .collect()
+    }
+}
+
+impl MarketplaceResolver {
+    /// Fetch a marketplace and list the plugins in its marketplace.json.
+    /// Directory marketplaces are read as they are.
+    pub fn index_marketplace(&self, name: &str, entry: &MarketplaceEntry) -> Result<IndexedMarketplace> {
+        let (commit, json) = match entry.directory() {
+            Some(dir) => (self.resolve_directory(name, dir)?, read_marketplace_json_file(dir, name)?),
+            None => {
+                let (repo, commit) = self.resolve_marketplace(name, entry)?;
+                (commit, self.parse_marketplace_json(&repo, name)?)
+            }
+        };
+        debug!(marketplace = name, plugin_count = json.plugins.len(), "indexed marketplace");
+
+        Ok(IndexedMarketplace {
+            url: entry.url.clone(),
+            commit,
+            plugins: json
+                .plugins
+                .into_iter()
+                .map(|p| IndexedPlugin {
+                    name: p.name,
+                    description: p.description,
+                })
+                .collect(),
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plugin(name: &str, description: Option<&str>) -> IndexedPlugin {
+        IndexedPlugin {
+            name: name.to_string(),
+            description: description.map(str::to_string),
+        }
+    }
+
+    fn test_index() -> SearchIndex {
+        let mut index = SearchIndex::default();
+        index.marketplaces.insert(
+            "official".to_string(),
+            IndexedMarketplace {
+                url: "https://github.com/owner/official.git".to_string(),
+                commit: "abc123".to_string(),
+                plugins: vec![
+                    plugin("typescript-lsp", Some("TypeScript language server")),
+                    plugin("superpowers", None),
+                ],
+            },
+        );
+        index.marketplaces.insert(
+            "community".to_string(),
+            IndexedMarketplace {
+                url: "https://github.com/owner/community.git".to_string(),
+                commit: "def456".to_string(),
+                plugins: vec![plugin("superpowers", Some("Core skills library"))],
+            },
+        );
+        index
+    }
+
+    #[test]
+    fn test_search_matches_name_and_description() {
+        let index = test_index();
+
+        let matches = index.search("LANGUAGE");
+        assert_eq!(matches.len(), 1);
+        assert_eq!(matches[0].marketplace, "official");
+        assert_eq!(matches[0].plugin.name, "typescript-lsp");
+
+        let matches = index.search("super");
+        assert_eq!(matches.len(), 2);
+
+        assert!(index.search("nonexistent").is_empty());
+    }
+
+    #[test]
+    fn test_marketplaces_with() {
+        let index = test_index();
+
+        assert_eq!(
+            index.marketplaces_with("superpowers"),
+            vec!["community".to_string(), "official".to_string()]
+        );
+        assert_eq!(index.marketplaces_with("typescript-lsp"), vec!["official".to_string()]);
+        assert!(index.marketplaces_with("super").is_empty());
+    }
+
+    #[test]
+    fn test_save_and_load() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let path = SearchIndex::path(temp_dir.path());
+
+        assert!(SearchIndex::load(&path).unwrap().marketplaces.is_empty());
+
+        let index = test_index();
+        index.save(&path).unwrap();
+
+        let loaded = SearchIndex::load(&path).unwrap();
+        assert_eq!(loaded.marketplaces, index.marketplaces);
+    }
+}
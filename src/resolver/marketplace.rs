@@ -1,19 +1,170 @@
 use git2::{FetchOptions, RemoteCallbacks, Repository};
+use semver::{Version, VersionReq};
 use serde::Deserialize;
-use std::path::{Path, PathBuf};
+use std::collections::HashMap;
+use std::path::{Component, Path, PathBuf};
+use std::sync::Arc;
 use tracing::{debug, instrument, trace};
 
+use super::fetch::{FetchPolicy, record_fetch_time};
+use super::session::Session;
+use crate::config::{expand_github_shorthand, Manifest, MarketplaceEntry};
+use crate::installer::source_hash;
 use crate::layout::PluginLayout;
 use crate::{Error, Result};
 
-/// Source location for a plugin - either local path or external URL.
-#[derive(Debug, Clone, Deserialize)]
-#[serde(untagged)]
+/// Refspec that updates the remote-tracking branches on fetch.
+const REMOTE_BRANCHES_REFSPEC: &str = "+refs/heads/*:refs/remotes/origin/*";
+
+/// Refspec that updates tags on fetch.
+const TAGS_REFSPEC: &str = "+refs/tags/*:refs/tags/*";
+
+/// Symbolic reference to the remote's default branch.
+const ORIGIN_HEAD: &str = "refs/remotes/origin/HEAD";
+
+/// Number of commits fetched from each branch and tag of a new clone.
+const SHALLOW_DEPTH: i32 = 1;
+
+/// Fetch depth that turns a shallow clone into a complete one.
+const UNSHALLOW_DEPTH: i32 = i32::MAX;
+
+/// Directory inside a marketplace's git directory that plugin directories
+/// are checked out to, see [`MarketplaceResolver::checkout_sparse`].
+const SPARSE_CHECKOUT_DIR: &str = "skill-manager-checkout";
+
+/// Source location for a plugin, as written in marketplace.json.
+///
+/// A bare string is a path inside the marketplace repository. An object is
+/// tagged by its `source` field and points at a separate git repository.
+#[derive(Debug, Clone, PartialEq, Eq, Deserialize)]
+#[serde(from = "RawPluginSource")]
 pub enum PluginSource {
     /// Local path within the marketplace repository.
     Local(String),
-    /// External repository with URL.
-    External { source: String, url: String },
+    /// Separate git repository.
+    External(ExternalSource),
+    /// A source skill-manager cannot install, with the reason. Reported as
+    /// an error only when the plugin is resolved, so the marketplace's other
+    /// plugins stay usable.
+    Unsupported(String),
+}
+
+/// A plugin in its own git repository.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ExternalSource {
+    /// Clone URL of the repository.
+    pub url: String,
+    /// Directory of the plugin inside the repository, if not the root.
+    pub path: Option<String>,
+    /// Tag or branch the marketplace pins the plugin to.
+    pub git_ref: Option<String>,
+    /// Commit the marketplace pins the plugin to.
+    pub sha: Option<String>,
+}
+
+impl ExternalSource {
+    /// A repository with the plugin at its root, following the default branch.
+    pub fn new(url: impl Into<String>) -> Self {
+        Self {
+            url: url.into(),
+            ..Self::default()
+        }
+    }
+
+    /// The plugin's directory inside the repository; empty for the root.
+    pub fn subdir(&self, marketplace: &str, plugin: &str) -> Result<PathBuf> {
+        let Some(ref path) = self.path else {
+            return Ok(PathBuf::new());
+        };
+        sparse_path(path).ok_or_else(|| Error::MarketplaceJsonParse {
+            name: marketplace.to_string(),
+            reason: format!("plugin '{}' has path '{}' outside its repository", plugin, path),
+        })
+    }
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawPluginSource {
+    Path(String),
+    Object(serde_json::Value),
+}
+
+/// The object forms of a plugin source, tagged by their `source` field.
+#[derive(Deserialize)]
+#[serde(tag = "source", rename_all = "kebab-case")]
+enum TaggedPluginSource {
+    /// `{"source": "github", "repo": "owner/repo"}`
+    Github {
+        repo: String,
+        path: Option<String>,
+        #[serde(rename = "ref")]
+        git_ref: Option<String>,
+        sha: Option<String>,
+    },
+    /// `{"source": "url", "url": "https://..."}`
+    #[serde(alias = "git")]
+    Url {
+        url: String,
+        path: Option<String>,
+        #[serde(rename = "ref")]
+        git_ref: Option<String>,
+        sha: Option<String>,
+    },
+    /// `{"source": "git-subdir", "url": "https://...", "path": "plugins/x"}`
+    GitSubdir {
+        url: String,
+        path: String,
+        #[serde(rename = "ref")]
+        git_ref: Option<String>,
+        sha: Option<String>,
+    },
+}
+
+impl From<RawPluginSource> for PluginSource {
+    fn from(raw: RawPluginSource) -> Self {
+        let value = match raw {
+            RawPluginSource::Path(path) => return Self::Local(path),
+            RawPluginSource::Object(value) => value,
+        };
+
+        match serde_json::from_value(value) {
+            Ok(TaggedPluginSource::Github {
+                repo,
+                path,
+                git_ref,
+                sha,
+            }) => Self::External(ExternalSource {
+                url: expand_github_shorthand(&repo),
+                path,
+                git_ref,
+                sha,
+            }),
+            Ok(TaggedPluginSource::Url {
+                url,
+                path,
+                git_ref,
+                sha,
+            }) => Self::External(ExternalSource {
+                url,
+                path,
+                git_ref,
+                sha,
+            }),
+            Ok(TaggedPluginSource::GitSubdir {
+                url,
+                path,
+                git_ref,
+                sha,
+            }) => Self::External(ExternalSource {
+                url,
+                path: Some(path),
+                git_ref,
+                sha,
+            }),
+            Err(e) => Self::Unsupported(e.to_string()),
+        }
+    }
 }
 
 /// Metadata for a plugin entry in marketplace.json.
@@ -34,65 +185,156 @@ pub struct MarketplaceJson {
 }
 
 /// Operations for working with marketplace git repositories.
+///
+/// A resolver fetches each repository and parses each marketplace.json at
+/// most once, so it should live for a single command run.
 pub struct MarketplaceResolver {
     pub(crate) cache_dir: PathBuf,
+    /// Maximum number of repositories fetched in parallel.
+    jobs: usize,
+    /// When cached repositories are fetched.
+    fetch_policy: FetchPolicy,
+    /// Marketplaces read from a local directory instead of a clone.
+    directories: HashMap<String, PathBuf>,
+    /// Work already done during this command run, shared with the
+    /// resolvers made by [`Self::for_manifest`].
+    session: Arc<Session>,
 }
 
 impl MarketplaceResolver {
     /// Create a new resolver with the given cache directory.
+    ///
+    /// Fetches run on as many threads as the machine has cores.
     pub fn new(cache_dir: PathBuf) -> Self {
-        Self { cache_dir }
+        let jobs = std::thread::available_parallelism().map_or(1, |n| n.get());
+        Self {
+            cache_dir,
+            jobs,
+            fetch_policy: FetchPolicy::default(),
+            directories: HashMap::new(),
+            session: Arc::default(),
+        }
     }
 
-    /// Get the local path for a marketplace.
+    /// Limit the number of repositories fetched in parallel.
+    pub fn with_jobs(mut self, jobs: usize) -> Self {
+        self.jobs = jobs.max(1);
+        self
+    }
+
+    /// Maximum number of repositories fetched in parallel.
+    pub fn jobs(&self) -> usize {
+        self.jobs
+    }
+
+    /// Set when cached repositories are fetched.
+    pub fn with_fetch_policy(mut self, policy: FetchPolicy) -> Self {
+        self.fetch_policy = policy;
+        self
+    }
+
+    /// When cached repositories are fetched.
+    pub fn fetch_policy(&self) -> FetchPolicy {
+        self.fetch_policy
+    }
+
+    /// Read the marketplaces declared with a `path` from their directory.
+    ///
+    /// A directory marketplace is never cloned or fetched: its
+    /// marketplace.json and plugins are read from the directory as it is,
+    /// and a hash of its contents (see [`Self::resolve_directory`]) stands in
+    /// for the commit.
+    pub fn with_directories<'a>(
+        mut self,
+        marketplaces: impl IntoIterator<Item = (&'a String, &'a MarketplaceEntry)>,
+    ) -> Self {
+        for (name, entry) in marketplaces {
+            if let Some(dir) = entry.directory() {
+                self.directories.insert(name.clone(), dir.to_path_buf());
+            }
+        }
+        self
+    }
+
+    /// A resolver for one manifest's entries: the same cache, settings and
+    /// work done so far, with only that manifest's `path` marketplaces read
+    /// from their directories.
+    ///
+    /// The global and the project manifest may declare a marketplace of the
+    /// same name differently, so each is resolved with a resolver of its own.
+    pub fn for_manifest(&self, manifest: &Manifest) -> Self {
+        Self {
+            cache_dir: self.cache_dir.clone(),
+            jobs: self.jobs,
+            fetch_policy: self.fetch_policy,
+            directories: HashMap::new(),
+            session: Arc::clone(&self.session),
+        }
+        .with_directories(&manifest.marketplaces)
+    }
+
+    /// The directory of a directory marketplace.
+    pub fn directory(&self, name: &str) -> Option<&Path> {
+        self.directories.get(name).map(PathBuf::as_path)
+    }
+
+    /// Whether a repository was already cloned or fetched by this resolver.
+    pub(crate) fn is_fetched(&self, path: &Path) -> bool {
+        self.session.is_fetched(path)
+    }
+
+    /// Remember that a repository was cloned or fetched.
+    pub(crate) fn mark_fetched(&self, path: &Path) {
+        self.session.mark_fetched(path)
+    }
+
+    /// Get the local path for a marketplace: its clone in the cache, or the
+    /// directory of a directory marketplace.
     pub fn marketplace_path(&self, name: &str) -> PathBuf {
-        self.cache_dir.join("marketplaces").join(name)
+        match self.directory(name) {
+            Some(dir) => dir.to_path_buf(),
+            None => self.cache_dir.join("marketplaces").join(name),
+        }
     }
 
     /// Clone or fetch a marketplace repository.
+    ///
+    /// A marketplace that does not need fetching (see [`Self::needs_fetch`])
+    /// is only opened. Offline, a marketplace that is not cached is an error.
     #[instrument(skip(self), fields(path))]
     pub fn ensure_marketplace(&self, name: &str, url: &str) -> Result<Repository> {
         let path = self.marketplace_path(name);
         tracing::Span::current().record("path", path.display().to_string());
 
-        if path.exists() {
+        let repo = if path.exists() && !self.needs_fetch(&path) {
+            debug!("marketplace does not need fetching, opening");
+            Repository::open(&path).map_err(|e| Error::MarketplaceClone {
+                name: name.to_string(),
+                source: e,
+            })?
+        } else if path.exists() {
             debug!("marketplace exists locally, fetching updates");
-            self.fetch_marketplace(name, &path)
+            let repo = self.fetch_marketplace(name, &path)?;
+            self.mark_fetched(&path);
+            record_fetch_time(&path);
+            repo
+        } else if self.fetch_policy == FetchPolicy::Offline {
+            return Err(Error::NotCachedOffline(format!("marketplace '{}'", name)));
         } else {
             debug!("marketplace not found locally, cloning");
-            self.clone_marketplace(name, url, &path)
-        }
+            let repo = self.clone_marketplace(name, url, &path)?;
+            self.mark_fetched(&path);
+            record_fetch_time(&path);
+            repo
+        };
+
+        Ok(repo)
     }
 
     /// Clone a marketplace to the cache.
     #[instrument(skip(self))]
     fn clone_marketplace(&self, name: &str, url: &str, path: &Path) -> Result<Repository> {
-        debug!(path = %path.display(), "creating cache directory");
-        std::fs::create_dir_all(path.parent().unwrap_or(Path::new(".")))
-            .map_err(Error::CacheCreate)?;
-
-        let mut callbacks = RemoteCallbacks::new();
-        callbacks.credentials(|_url, username_from_url, allowed_types| {
-            // Try SSH agent first for git@ URLs
-            if allowed_types.contains(git2::CredentialType::SSH_KEY) {
-                if let Some(username) = username_from_url {
-                    return git2::Cred::ssh_key_from_agent(username);
-                }
-            }
-            // Fall back to default credentials
-            git2::Cred::default()
-        });
-
-        let mut fo = FetchOptions::new();
-        fo.remote_callbacks(callbacks);
-
-        let mut builder = git2::build::RepoBuilder::new();
-        builder.fetch_options(fo);
-
-        builder.clone(url, path).map_err(|e| Error::MarketplaceClone {
-            name: name.to_string(),
-            source: e,
-        })
+        clone_repo(name, url, path)
     }
 
     /// Fetch updates for an existing marketplace clone.
@@ -104,34 +346,49 @@ impl MarketplaceResolver {
             source: e,
         })?;
 
-        {
-            let mut remote = repo.find_remote("origin").map_err(|e| Error::MarketplaceFetch {
-                name: name.to_string(),
-                source: e,
-            })?;
+        fetch_origin(&repo, name, false)?;
+        Ok(repo)
+    }
 
-            let mut callbacks = RemoteCallbacks::new();
-            callbacks.credentials(|_url, username_from_url, allowed_types| {
-                if allowed_types.contains(git2::CredentialType::SSH_KEY) {
-                    if let Some(username) = username_from_url {
-                        return git2::Cred::ssh_key_from_agent(username);
-                    }
-                }
-                git2::Cred::default()
-            });
+    /// Fetch a marketplace and check out the commit its manifest entry pins.
+    /// Unpinned marketplaces resolve to HEAD. Returns the repository and
+    /// resolved commit.
+    pub fn resolve_marketplace(&self, name: &str, entry: &MarketplaceEntry) -> Result<(Repository, String)> {
+        let repo = self.ensure_marketplace(name, &entry.url)?;
 
-            let mut fo = FetchOptions::new();
-            fo.remote_callbacks(callbacks);
+        let commit = if let Some(ref c) = entry.commit {
+            c.clone()
+        } else if let Some(ref tag) = entry.tag {
+            self.resolve_tag(&repo, name, tag)?
+        } else if let Some(ref branch) = entry.branch {
+            self.resolve_branch(&repo, name, branch)?
+        } else {
+            self.resolve_head(&repo)?
+        };
+
+        // Checkout the resolved commit
+        self.checkout_commit(&repo, name, &commit)?;
+
+        Ok((repo, commit))
+    }
 
-            remote
-                .fetch(&["refs/heads/*:refs/heads/*", "refs/tags/*:refs/tags/*"], Some(&mut fo), None)
-                .map_err(|e| Error::MarketplaceFetch {
-                    name: name.to_string(),
-                    source: e,
-                })?;
+    /// Hash the contents of a directory, ignoring `.git`, once per resolver.
+    ///
+    /// Directory marketplaces lock this hash instead of a commit, and their
+    /// plugins lock the hash of their own directory, so any change to the
+    /// sources is picked up by the next install.
+    pub fn resolve_directory(&self, name: &str, dir: &Path) -> Result<String> {
+        if let Some(hash) = self.session.source_hash(dir) {
+            return Ok(hash);
         }
 
-        Ok(repo)
+        debug!(marketplace = name, dir = %dir.display(), "hashing directory");
+        let hash = source_hash(dir).map_err(|e| Error::FileRead {
+            path: dir.to_path_buf(),
+            source: e,
+        })?;
+        self.session.insert_source_hash(dir, &hash);
+        Ok(hash)
     }
 
     /// Resolve a tag to its commit hash.
@@ -151,39 +408,240 @@ impl MarketplaceResolver {
         Ok(commit.id().to_string())
     }
 
-    /// Resolve HEAD to its commit hash.
+    /// Resolve a branch to the commit at the tip of `origin/<branch>`.
+    pub fn resolve_branch(&self, repo: &Repository, name: &str, branch: &str) -> Result<String> {
+        let refname = format!("refs/remotes/origin/{}", branch);
+        let commit = repo
+            .find_reference(&refname)
+            .and_then(|r| r.peel_to_commit())
+            .map_err(|_| Error::BranchNotFound {
+                name: name.to_string(),
+                branch: branch.to_string(),
+            })?;
+
+        Ok(commit.id().to_string())
+    }
+
+    /// Resolve the highest tag whose version satisfies a semver requirement.
+    ///
+    /// Tags may carry a `v` prefix (`v4.1.1`); tags that do not parse as a
+    /// version are ignored. Prereleases are only considered when allowed.
+    /// Returns the tag name and its commit hash.
+    pub fn resolve_version_req(
+        &self,
+        repo: &Repository,
+        name: &str,
+        req: &VersionReq,
+        allow_prerelease: bool,
+    ) -> Result<(String, String)> {
+        let tags = repo.tag_names(None).map_err(Error::Git)?;
+
+        let best = tags
+            .iter()
+            .flatten()
+            .filter_map(|tag| parse_tag_version(tag).map(|version| (version, tag)))
+            .filter(|(version, _)| version_matches(req, version, allow_prerelease))
+            .max_by(|a, b| a.0.cmp(&b.0));
+
+        let Some((version, tag)) = best else {
+            return Err(Error::NoMatchingVersion {
+                name: name.to_string(),
+                requirement: req.to_string(),
+            });
+        };
+        debug!(%tag, %version, %req, "selected tag for version requirement");
+
+        let commit = self.resolve_tag(repo, name, tag)?;
+        Ok((tag.to_string(), commit))
+    }
+
+    /// Resolve the remote's default branch to its commit hash.
+    ///
+    /// Follows `origin/HEAD` rather than the local HEAD, which is left
+    /// detached at whatever commit was last checked out. Repositories without
+    /// an `origin/HEAD` fall back to the local HEAD.
     pub fn resolve_head(&self, repo: &Repository) -> Result<String> {
-        let head = repo.head().map_err(Error::Git)?;
+        let head = match repo.find_reference(ORIGIN_HEAD) {
+            Ok(reference) => reference,
+            Err(_) => {
+                debug!("no origin/HEAD, falling back to local HEAD");
+                repo.head().map_err(Error::Git)?
+            }
+        };
         let commit = head.peel_to_commit().map_err(Error::Git)?;
         Ok(commit.id().to_string())
     }
 
+    /// Look up a commit, fetching the repository when the commit is missing.
+    ///
+    /// A shallow clone is deepened to its full history, since the commit may
+    /// be older than the history fetched so far. A complete clone is fetched
+    /// only if this resolver has not fetched it yet, and nothing is fetched
+    /// offline.
+    pub(crate) fn find_commit<'r>(
+        &self,
+        repo: &'r Repository,
+        name: &str,
+        commit: &str,
+    ) -> Result<git2::Commit<'r>> {
+        let not_found = || Error::CommitNotFound {
+            marketplace: name.to_string(),
+            commit: commit.to_string(),
+        };
+        let oid = git2::Oid::from_str(commit).map_err(|_| not_found())?;
+        if let Ok(found) = repo.find_commit(oid) {
+            return Ok(found);
+        }
+
+        let Some(workdir) = repo.workdir() else {
+            return Err(not_found());
+        };
+        let shallow = repo.is_shallow();
+        if self.fetch_policy == FetchPolicy::Offline || (!shallow && self.is_fetched(workdir)) {
+            return Err(not_found());
+        }
+
+        debug!(shallow, "commit missing from clone, fetching");
+        fetch_origin(repo, name, shallow)?;
+        self.mark_fetched(workdir);
+        record_fetch_time(workdir);
+
+        repo.find_commit(oid).map_err(|_| not_found())
+    }
+
     /// Checkout a specific commit.
+    ///
+    /// A working tree this resolver already checked out at the commit is left
+    /// as it is.
     #[instrument(skip(self, repo))]
     pub fn checkout_commit(&self, repo: &Repository, marketplace: &str, commit: &str) -> Result<()> {
-        debug!("parsing commit oid");
-        let oid = git2::Oid::from_str(commit).map_err(|_| Error::CommitNotFound {
-            marketplace: marketplace.to_string(),
-            commit: commit.to_string(),
-        })?;
+        let workdir = repo.workdir().map(Path::to_path_buf);
+        if let Some(ref workdir) = workdir
+            && self.session.is_checked_out(workdir, commit)
+        {
+            debug!("commit already checked out");
+            return Ok(());
+        }
 
         debug!("finding commit object");
-        let commit_obj = repo.find_commit(oid).map_err(|_| Error::CommitNotFound {
-            marketplace: marketplace.to_string(),
-            commit: commit.to_string(),
-        })?;
+        let commit_obj = self.find_commit(repo, marketplace, commit)?;
 
         debug!("checking out tree");
         repo.checkout_tree(commit_obj.as_object(), Some(git2::build::CheckoutBuilder::new().force()))
             .map_err(Error::Git)?;
 
         debug!("setting HEAD to detached state");
-        repo.set_head_detached(oid).map_err(Error::Git)?;
+        repo.set_head_detached(commit_obj.id()).map_err(Error::Git)?;
+
+        if let Some(ref workdir) = workdir {
+            self.session.mark_checked_out(workdir, commit);
+        }
 
         debug!("checkout complete");
         Ok(())
     }
 
+    /// Check out the whole tree of a marketplace's clone, the one Claude Code
+    /// reads, at a commit. Directory marketplaces are used as they are.
+    pub fn checkout_marketplace(&self, name: &str, url: &str, commit: &str) -> Result<()> {
+        if self.directory(name).is_some() {
+            return Ok(());
+        }
+
+        let repo = self.ensure_marketplace(name, url)?;
+        self.checkout_commit(&repo, name, commit)
+    }
+
+    /// Check out only some directories of a marketplace at a commit, and
+    /// return the directory they were checked out into.
+    ///
+    /// The directories go to a working tree of their own inside the clone's
+    /// git directory, not to the clone's working tree: Claude Code reads the
+    /// clone as the marketplace's install location, so it stays fully checked
+    /// out at the locked commit. Each directory is replaced as a whole, so no
+    /// file from another commit survives in it. A plugin at the repository
+    /// root checks out the whole tree.
+    #[instrument(skip(self, repo))]
+    pub fn checkout_sparse(
+        &self,
+        repo: &Repository,
+        marketplace: &str,
+        commit: &str,
+        paths: &[&str],
+    ) -> Result<PathBuf> {
+        let dir = repo.path().join(SPARSE_CHECKOUT_DIR);
+
+        let mut wanted = Vec::new();
+        for path in paths {
+            let path = sparse_path(path).ok_or_else(|| Error::MarketplaceJsonParse {
+                name: marketplace.to_string(),
+                reason: format!("plugin path '{}' is outside the repository", path),
+            })?;
+            wanted.push(path);
+        }
+
+        let missing = self.session.missing_paths(&dir, commit, &wanted);
+        if missing.is_empty() {
+            debug!("paths already checked out");
+            return Ok(dir);
+        }
+
+        debug!("finding commit object");
+        let commit_obj = self.find_commit(repo, marketplace, commit)?;
+
+        let mut checkout = git2::build::CheckoutBuilder::new();
+        checkout.force().target_dir(&dir).update_index(false);
+        let whole_tree = missing.iter().any(|p| p.as_os_str().is_empty());
+        for path in &missing {
+            let target = dir.join(path);
+            if target.is_dir() {
+                std::fs::remove_dir_all(&target)?;
+            } else if target.exists() {
+                std::fs::remove_file(&target)?;
+            }
+            if !whole_tree {
+                checkout.path(path);
+            }
+        }
+        std::fs::create_dir_all(&dir)?;
+
+        debug!(paths = ?missing, "checking out paths");
+        repo.checkout_tree(commit_obj.as_object(), Some(&mut checkout))
+            .map_err(Error::Git)?;
+
+        if whole_tree {
+            self.session.mark_checked_out(&dir, commit);
+        } else {
+            self.session.mark_paths_checked_out(&dir, commit, &missing);
+        }
+        Ok(dir)
+    }
+
+    /// The marketplace.json of a marketplace at a commit.
+    ///
+    /// The file is read from the commit itself, so the working tree does not
+    /// need to be checked out. Directory marketplaces are read as they are.
+    /// Each (marketplace, commit) is read and parsed once per resolver; later
+    /// calls share the parsed result.
+    #[instrument(skip(self))]
+    pub fn marketplace_json_at(&self, name: &str, url: &str, commit: &str) -> Result<Arc<MarketplaceJson>> {
+        if let Some(json) = self.session.marketplace_json(name, commit) {
+            debug!("marketplace.json already parsed");
+            return Ok(json);
+        }
+
+        let json = match self.directory(name) {
+            Some(dir) => Arc::new(read_marketplace_json_file(dir, name)?),
+            None => {
+                let repo = self.ensure_marketplace(name, url)?;
+                let tree = self.find_commit(&repo, name, commit)?.tree().map_err(Error::Git)?;
+                Arc::new(read_marketplace_json(&repo, &tree, name)?)
+            }
+        };
+        self.session.insert_marketplace_json(name, commit, Arc::clone(&json));
+        Ok(json)
+    }
+
     /// Parse marketplace.json from a repository.
     #[instrument(skip(self, repo))]
     pub fn parse_marketplace_json(&self, repo: &Repository, marketplace: &str) -> Result<MarketplaceJson> {
@@ -192,35 +650,19 @@ impl MarketplaceResolver {
             Error::MarketplaceJsonNotFound(marketplace.to_string())
         })?;
 
-        let layout = PluginLayout::new(workdir);
-        let json_path = layout.marketplace_json();
-        debug!(path = %json_path.display(), "looking for marketplace.json");
+        read_marketplace_json_file(workdir, marketplace)
+    }
 
-        if !json_path.exists() {
-            debug!(path = %json_path.display(), "marketplace.json not found");
-            return Err(Error::MarketplaceJsonNotFound(marketplace.to_string()));
+    /// Check out a local plugin's directory at a marketplace commit and
+    /// return the directory it lives in. Directory marketplaces are used as
+    /// they are.
+    pub fn local_plugin_root(&self, marketplace: &str, url: &str, commit: &str, path: &str) -> Result<PathBuf> {
+        if let Some(dir) = self.directory(marketplace) {
+            return Ok(dir.to_path_buf());
         }
 
-        debug!("reading marketplace.json");
-        let content = std::fs::read_to_string(json_path).map_err(|e| Error::FileRead {
-            path: json_path.to_path_buf(),
-            source: e,
-        })?;
-        trace!(content_len = content.len(), "marketplace.json content loaded");
-
-        debug!("parsing marketplace.json");
-        let parsed: MarketplaceJson = serde_json::from_str(&content).map_err(|e| {
-            debug!(error = %e, "failed to parse marketplace.json");
-            Error::MarketplaceJsonParse {
-                name: marketplace.to_string(),
-                reason: e.to_string(),
-            }
-        })?;
-
-        debug!(plugin_count = parsed.plugins.len(), "marketplace.json parsed successfully");
-        trace!(plugins = ?parsed.plugins.iter().map(|p| &p.name).collect::<Vec<_>>(), "available plugins");
-
-        Ok(parsed)
+        let repo = self.ensure_marketplace(marketplace, url)?;
+        self.checkout_sparse(&repo, marketplace, commit, &[path])
     }
 
     /// Find a plugin in a marketplace.
@@ -249,18 +691,216 @@ impl MarketplaceResolver {
     }
 }
 
+/// Read marketplace.json from the tree of a commit.
+fn read_marketplace_json(repo: &Repository, tree: &git2::Tree<'_>, marketplace: &str) -> Result<MarketplaceJson> {
+    let layout = PluginLayout::new("");
+    let json_path = layout.marketplace_json();
+    debug!(path = %json_path.display(), "looking for marketplace.json in commit");
+    let blob = tree
+        .get_path(json_path)
+        .and_then(|entry| entry.to_object(repo))
+        .and_then(|object| object.peel_to_blob())
+        .map_err(|_| Error::MarketplaceJsonNotFound(marketplace.to_string()))?;
+
+    let content = std::str::from_utf8(blob.content()).map_err(|e| Error::MarketplaceJsonParse {
+        name: marketplace.to_string(),
+        reason: e.to_string(),
+    })?;
+    trace!(content_len = content.len(), "marketplace.json content loaded");
+
+    parse_marketplace_content(marketplace, content)
+}
+
+/// Read marketplace.json from a working tree or directory.
+pub(super) fn read_marketplace_json_file(dir: &Path, marketplace: &str) -> Result<MarketplaceJson> {
+    let layout = PluginLayout::new(dir);
+    let json_path = layout.marketplace_json();
+    debug!(path = %json_path.display(), "looking for marketplace.json");
+
+    if !json_path.exists() {
+        debug!(path = %json_path.display(), "marketplace.json not found");
+        return Err(Error::MarketplaceJsonNotFound(marketplace.to_string()));
+    }
+
+    debug!("reading marketplace.json");
+    let content = std::fs::read_to_string(json_path).map_err(|e| Error::FileRead {
+        path: json_path.to_path_buf(),
+        source: e,
+    })?;
+    trace!(content_len = content.len(), "marketplace.json content loaded");
+
+    parse_marketplace_content(marketplace, &content)
+}
+
+/// Parse the contents of a marketplace.json file.
+fn parse_marketplace_content(marketplace: &str, content: &str) -> Result<MarketplaceJson> {
+    debug!("parsing marketplace.json");
+    let parsed: MarketplaceJson = serde_json::from_str(content).map_err(|e| {
+        debug!(error = %e, "failed to parse marketplace.json");
+        Error::MarketplaceJsonParse {
+            name: marketplace.to_string(),
+            reason: e.to_string(),
+        }
+    })?;
+
+    debug!(plugin_count = parsed.plugins.len(), "marketplace.json parsed successfully");
+    trace!(plugins = ?parsed.plugins.iter().map(|p| &p.name).collect::<Vec<_>>(), "available plugins");
+
+    Ok(parsed)
+}
+
+/// Whether a remote is reached through libgit2's local transport, which
+/// cannot fetch shallow history.
+fn is_local_remote(url: &str) -> bool {
+    url.starts_with("file://") || Path::new(url).exists()
+}
+
+/// Fetch options that take credentials from the SSH agent.
+///
+/// Remotes that support it only send history `depth` commits deep.
+fn fetch_options<'a>(url: &str, depth: Option<i32>) -> FetchOptions<'a> {
+    let mut callbacks = RemoteCallbacks::new();
+    callbacks.credentials(|_url, username_from_url, allowed_types| {
+        // Try SSH agent first for git@ URLs
+        if allowed_types.contains(git2::CredentialType::SSH_KEY)
+            && let Some(username) = username_from_url
+        {
+            return git2::Cred::ssh_key_from_agent(username);
+        }
+        // Fall back to default credentials
+        git2::Cred::default()
+    });
+
+    let mut fo = FetchOptions::new();
+    fo.remote_callbacks(callbacks);
+    if let Some(depth) = depth
+        && !is_local_remote(url)
+    {
+        fo.depth(depth);
+    }
+    fo
+}
+
+/// Clone a repository into the cache.
+///
+/// Only the tips of branches and tags are fetched, and nothing is checked
+/// out; callers check out the commit and paths they need.
+pub(crate) fn clone_repo(name: &str, url: &str, path: &Path) -> Result<Repository> {
+    debug!(path = %path.display(), "creating cache directory");
+    std::fs::create_dir_all(path.parent().unwrap_or(Path::new(".")))
+        .map_err(Error::CacheCreate)?;
+
+    let mut fo = fetch_options(url, Some(SHALLOW_DEPTH));
+    fo.download_tags(git2::AutotagOption::All);
+
+    let mut checkout = git2::build::CheckoutBuilder::new();
+    checkout.dry_run();
+
+    let mut builder = git2::build::RepoBuilder::new();
+    builder.fetch_options(fo);
+    builder.with_checkout(checkout);
+
+    builder.clone(url, path).map_err(|e| Error::MarketplaceClone {
+        name: name.to_string(),
+        source: e,
+    })
+}
+
+/// Fetch branches and tags from `origin`.
+///
+/// A shallow clone stays shallow, or gets its full history with `deepen`.
+pub(crate) fn fetch_origin(repo: &Repository, name: &str, deepen: bool) -> Result<()> {
+    let fetch_err = |e| Error::MarketplaceFetch {
+        name: name.to_string(),
+        source: e,
+    };
+    let mut remote = repo.find_remote("origin").map_err(fetch_err)?;
+
+    let depth = if deepen {
+        Some(UNSHALLOW_DEPTH)
+    } else {
+        repo.is_shallow().then_some(SHALLOW_DEPTH)
+    };
+    let mut fo = fetch_options(remote.url().unwrap_or_default(), depth);
+
+    remote
+        .fetch(&[REMOTE_BRANCHES_REFSPEC, TAGS_REFSPEC], Some(&mut fo), None)
+        .map_err(fetch_err)?;
+
+    update_remote_head(repo, &remote);
+    Ok(())
+}
+
+/// A plugin directory from marketplace.json as a path inside the repository.
+///
+/// `./` components are dropped. Returns `None` for paths that leave the
+/// repository.
+pub(crate) fn sparse_path(path: &str) -> Option<PathBuf> {
+    Path::new(path)
+        .components()
+        .filter(|c| !matches!(c, Component::CurDir))
+        .map(|c| match c {
+            Component::Normal(part) => Some(part),
+            _ => None,
+        })
+        .collect()
+}
+
+/// Point `origin/HEAD` at the default branch the remote advertised during the
+/// last fetch, so a changed default branch is followed.
+///
+/// Failures are logged and ignored; `origin/HEAD` from the clone is kept.
+fn update_remote_head(repo: &Repository, remote: &git2::Remote<'_>) {
+    let default_branch = match remote.default_branch() {
+        Ok(buf) => buf,
+        Err(e) => {
+            debug!(error = %e, "remote did not advertise a default branch");
+            return;
+        }
+    };
+    let Some(branch) = default_branch
+        .as_str()
+        .and_then(|b| b.strip_prefix("refs/heads/"))
+    else {
+        return;
+    };
+
+    let target = format!("refs/remotes/origin/{}", branch);
+    if let Err(e) = repo.reference_symbolic(ORIGIN_HEAD, &target, true, "update origin/HEAD") {
+        debug!(error = %e, %target, "failed to update origin/HEAD");
+    }
+}
+
+/// Parse a tag name such as `v4.1.1` or `4.1.1` as a semver version.
+pub(crate) fn parse_tag_version(tag: &str) -> Option<Version> {
+    let version = tag.strip_prefix(['v', 'V']).unwrap_or(tag);
+    Version::parse(version).ok()
+}
+
+/// Check a version against a requirement.
+///
+/// semver only matches a prerelease when the requirement names that exact
+/// prerelease. When prereleases are allowed, they also match if their release
+/// version would.
+pub(crate) fn version_matches(req: &VersionReq, version: &Version, allow_prerelease: bool) -> bool {
+    if req.matches(version) {
+        return true;
+    }
+    if !allow_prerelease || version.pre.is_empty() {
+        return false;
+    }
+    req.matches(&Version::new(version.major, version.minor, version.patch))
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::commit_files;
     use std::fs;
 
     fn setup_test_repo(dir: &Path) -> Repository {
         let repo = Repository::init(dir).unwrap();
 
-        // Create .claude-plugin directory and marketplace.json
-        let plugins_dir = dir.join(".claude-plugin");
-        fs::create_dir_all(&plugins_dir).unwrap();
-
         let json_content = r#"{
             "plugins": [
                 {
@@ -275,19 +915,7 @@ mod tests {
                 }
             ]
         }"#;
-        fs::write(plugins_dir.join("marketplace.json"), json_content).unwrap();
-
-        // Commit the file
-        {
-            let mut index = repo.index().unwrap();
-            index.add_path(Path::new(".claude-plugin/marketplace.json")).unwrap();
-            index.write().unwrap();
-            let tree_id = index.write_tree().unwrap();
-            let tree = repo.find_tree(tree_id).unwrap();
-            let sig = git2::Signature::now("Test", "test@test.com").unwrap();
-            repo.commit(Some("HEAD"), &sig, &sig, "Initial commit", &tree, &[])
-                .unwrap();
-        }
+        commit_files(&repo, &[(".claude-plugin/marketplace.json", json_content)], "Initial commit");
 
         repo
     }
@@ -308,7 +936,51 @@ mod tests {
         assert!(matches!(&local.source, PluginSource::Local(path) if path == "./plugins/test-plugin"));
 
         let external = json.plugins.iter().find(|p| p.name == "external-plugin").unwrap();
-        assert!(matches!(&external.source, PluginSource::External { url, .. } if url == "https://github.com/example/external.git"));
+        assert_eq!(
+            external.source,
+            PluginSource::External(ExternalSource::new("https://github.com/example/external.git"))
+        );
+    }
+
+    #[test]
+    fn test_parse_plugin_sources() {
+        let json: MarketplaceJson = serde_json::from_str(
+            r#"{"plugins": [
+                {"name": "local", "source": "./plugins/local"},
+                {"name": "github", "source": {"source": "github", "repo": "owner/repo", "ref": "v1.0"}},
+                {"name": "url", "source": {"source": "url", "url": "https://git.example.com/x.git", "sha": "abc123"}},
+                {"name": "subdir", "source": {"source": "git-subdir", "url": "https://git.example.com/x.git", "path": "plugins/y"}},
+                {"name": "npm", "source": {"source": "npm", "package": "@scope/x"}},
+                {"name": "broken", "source": {"source": "github"}}
+            ]}"#,
+        )
+        .unwrap();
+        let source = |name: &str| json.plugins.iter().find(|p| p.name == name).unwrap().source.clone();
+
+        assert_eq!(source("local"), PluginSource::Local("./plugins/local".to_string()));
+        assert_eq!(
+            source("github"),
+            PluginSource::External(ExternalSource {
+                git_ref: Some("v1.0".to_string()),
+                ..ExternalSource::new("https://github.com/owner/repo.git")
+            })
+        );
+        assert_eq!(
+            source("url"),
+            PluginSource::External(ExternalSource {
+                sha: Some("abc123".to_string()),
+                ..ExternalSource::new("https://git.example.com/x.git")
+            })
+        );
+        assert_eq!(
+            source("subdir"),
+            PluginSource::External(ExternalSource {
+                path: Some("plugins/y".to_string()),
+                ..ExternalSource::new("https://git.example.com/x.git")
+            })
+        );
+        assert!(matches!(source("npm"), PluginSource::Unsupported(reason) if reason.contains("npm")));
+        assert!(matches!(source("broken"), PluginSource::Unsupported(reason) if reason.contains("repo")));
     }
 
     #[test]
@@ -338,4 +1010,314 @@ mod tests {
         let result = resolver.find_plugin(&json, "test", "nonexistent");
         assert!(matches!(result, Err(Error::PluginNotFound { .. })));
     }
+
+    fn commit_and_tag(repo: &Repository, tag: &str) {
+        let commit = commit_files(repo, &[("VERSION", tag)], tag);
+        let object = repo.revparse_single(&commit).unwrap();
+        repo.tag_lightweight(tag, &object, false).unwrap();
+    }
+
+    #[test]
+    fn test_resolve_version_req() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let repo = setup_test_repo(temp_dir.path());
+        for tag in ["v1.0.0", "v1.2.0", "1.3.0-beta.1", "v2.0.0", "latest"] {
+            commit_and_tag(&repo, tag);
+        }
+
+        let resolver = MarketplaceResolver::new(temp_dir.path().to_path_buf());
+
+        let req = VersionReq::parse("^1.0").unwrap();
+        let (tag, commit) = resolver.resolve_version_req(&repo, "test", &req, false).unwrap();
+        assert_eq!(tag, "v1.2.0");
+        assert_eq!(commit, resolver.resolve_tag(&repo, "test", "v1.2.0").unwrap());
+
+        // Prereleases only match when allowed
+        let (tag, _) = resolver.resolve_version_req(&repo, "test", &req, true).unwrap();
+        assert_eq!(tag, "1.3.0-beta.1");
+
+        let req = VersionReq::parse("*").unwrap();
+        let (tag, _) = resolver.resolve_version_req(&repo, "test", &req, false).unwrap();
+        assert_eq!(tag, "v2.0.0");
+
+        let req = VersionReq::parse(">=3").unwrap();
+        let result = resolver.resolve_version_req(&repo, "test", &req, false);
+        assert!(matches!(result, Err(Error::NoMatchingVersion { .. })));
+    }
+
+    #[test]
+    fn test_resolve_branch() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let upstream_dir = temp_dir.path().join("upstream");
+        let upstream = setup_test_repo(&upstream_dir);
+        let first = upstream.head().unwrap().peel_to_commit().unwrap();
+        upstream.branch("develop", &first, false).unwrap();
+        commit_and_tag(&upstream, "v1.0.0");
+        let second = upstream.head().unwrap().peel_to_commit().unwrap();
+
+        let resolver = MarketplaceResolver::new(temp_dir.path().join("cache"));
+        let entry = MarketplaceEntry {
+            url: upstream_dir.to_string_lossy().to_string(),
+            branch: Some("develop".to_string()),
+            ..MarketplaceEntry::default()
+        };
+
+        let (_, commit) = resolver.resolve_marketplace("test", &entry).unwrap();
+        assert_eq!(commit, first.id().to_string());
+
+        // Advancing the upstream branch is picked up on the next run's fetch
+        upstream.branch("develop", &second, true).unwrap();
+        let resolver = MarketplaceResolver::new(temp_dir.path().join("cache"));
+        let (_, commit) = resolver.resolve_marketplace("test", &entry).unwrap();
+        assert_eq!(commit, second.id().to_string());
+
+        let entry = MarketplaceEntry {
+            branch: Some("missing".to_string()),
+            ..entry
+        };
+        let result = resolver.resolve_marketplace("test", &entry);
+        assert!(matches!(result, Err(Error::BranchNotFound { .. })));
+    }
+
+    #[test]
+    fn test_resolve_head_follows_remote_after_fetch() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let upstream_dir = temp_dir.path().join("upstream");
+        let upstream = setup_test_repo(&upstream_dir);
+
+        let resolver = MarketplaceResolver::new(temp_dir.path().join("cache"));
+        let entry = MarketplaceEntry {
+            url: upstream_dir.to_string_lossy().to_string(),
+            ..MarketplaceEntry::default()
+        };
+
+        let (_, first) = resolver.resolve_marketplace("test", &entry).unwrap();
+        assert_eq!(first, upstream.head().unwrap().peel_to_commit().unwrap().id().to_string());
+
+        // The upstream gains a commit; the clone is detached at the first commit
+        commit_and_tag(&upstream, "v1.0.0");
+        let latest = upstream.head().unwrap().peel_to_commit().unwrap().id().to_string();
+
+        let resolver = MarketplaceResolver::new(temp_dir.path().join("cache"));
+        let (_, second) = resolver.resolve_marketplace("test", &entry).unwrap();
+        assert_ne!(second, first);
+        assert_eq!(second, latest);
+
+        // A changed default branch is followed too
+        let first_commit = upstream.find_commit(git2::Oid::from_str(&first).unwrap()).unwrap();
+        upstream.branch("release", &first_commit, false).unwrap();
+        upstream.set_head("refs/heads/release").unwrap();
+        let resolver = MarketplaceResolver::new(temp_dir.path().join("cache"));
+
+        let (_, third) = resolver.resolve_marketplace("test", &entry).unwrap();
+        assert_eq!(third, first);
+    }
+
+    #[test]
+    fn test_marketplace_json_at_is_read_once_per_commit() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let upstream_dir = temp_dir.path().join("upstream");
+        let upstream = setup_test_repo(&upstream_dir);
+        let first = upstream.head().unwrap().peel_to_commit().unwrap().id().to_string();
+
+        // A second commit drops the external plugin
+        let second = commit_files(
+            &upstream,
+            &[(".claude-plugin/marketplace.json", r#"{"plugins": [{"name": "test-plugin", "source": "./plugins/test-plugin"}]}"#)],
+            "Drop external",
+        );
+
+        let resolver = MarketplaceResolver::new(temp_dir.path().join("cache"));
+        let url = upstream_dir.to_string_lossy().to_string();
+        let repo = resolver.ensure_marketplace("test", &url).unwrap();
+        resolver.checkout_commit(&repo, "test", &second).unwrap();
+
+        // Read from the commit, not the checked-out working tree
+        let old = resolver.marketplace_json_at("test", &url, &first).unwrap();
+        assert_eq!(old.plugins.len(), 2);
+        let new = resolver.marketplace_json_at("test", &url, &second).unwrap();
+        assert_eq!(new.plugins.len(), 1);
+        assert_eq!(repo.head().unwrap().target().unwrap().to_string(), second);
+
+        // Later lookups share the parsed file, even if the cache changes underneath
+        fs::remove_dir_all(resolver.marketplace_path("test")).unwrap();
+        let again = resolver.marketplace_json_at("test", &url, &first).unwrap();
+        assert!(Arc::ptr_eq(&old, &again));
+
+        let result = resolver.marketplace_json_at("test", &url, "0123456789abcdef0123456789abcdef01234567");
+        assert!(matches!(result, Err(Error::CommitNotFound { .. })));
+    }
+
+    #[test]
+    fn test_checkout_sparse() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let upstream_dir = temp_dir.path().join("upstream");
+        let upstream = setup_test_repo(&upstream_dir);
+        let first = commit_files(&upstream, &[("plugins/a/old.md", "a"), ("plugins/b/skill.md", "b")], "first");
+        fs::remove_file(upstream_dir.join("plugins/a/old.md")).unwrap();
+        let second = commit_files(&upstream, &[("plugins/a/new.md", "a")], "second");
+        let url = upstream_dir.to_string_lossy().to_string();
+
+        // The registered clone is checked out in full
+        let resolver = MarketplaceResolver::new(temp_dir.path().join("cache"));
+        resolver.checkout_marketplace("test", &url, &second).unwrap();
+        let repo = resolver.ensure_marketplace("test", &url).unwrap();
+        let workdir = resolver.marketplace_path("test");
+        assert!(workdir.join("plugins/a/new.md").exists());
+        assert!(workdir.join("plugins/b/skill.md").exists());
+
+        // Plugin directories are checked out next to it, leaving it alone
+        let dir = resolver.checkout_sparse(&repo, "test", &first, &["./plugins/a"]).unwrap();
+        assert!(dir.join("plugins/a/old.md").exists());
+        assert!(!dir.join("plugins/b").exists());
+        assert!(!workdir.join("plugins/a/old.md").exists());
+        assert!(workdir.join("plugins/b/skill.md").exists());
+        assert_eq!(repo.head().unwrap().target().unwrap().to_string(), second);
+        assert!(repo.statuses(None).unwrap().is_empty());
+
+        // Moving to another commit replaces the directory as a whole
+        let resolver = MarketplaceResolver::new(temp_dir.path().join("cache"));
+        let dir = resolver.checkout_sparse(&repo, "test", &second, &["plugins/a/"]).unwrap();
+        assert!(dir.join("plugins/a/new.md").exists());
+        assert!(!dir.join("plugins/a/old.md").exists());
+        assert!(!dir.join("plugins/b").exists());
+
+        // A plugin at the repository root gets the whole tree
+        resolver.checkout_sparse(&repo, "test", &first, &["./"]).unwrap();
+        assert!(dir.join("plugins/a/old.md").exists());
+        assert!(dir.join("plugins/b/skill.md").exists());
+
+        let result = resolver.checkout_sparse(&repo, "test", &second, &["../outside"]);
+        assert!(matches!(result, Err(Error::MarketplaceJsonParse { .. })));
+    }
+
+    #[test]
+    fn test_find_commit_fetches_missing_commit() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let upstream_dir = temp_dir.path().join("upstream");
+        let upstream = setup_test_repo(&upstream_dir);
+        let url = upstream_dir.to_string_lossy().to_string();
+        let cache_dir = temp_dir.path().join("cache");
+
+        MarketplaceResolver::new(cache_dir.clone())
+            .ensure_marketplace("test", &url)
+            .unwrap();
+        let latest = commit_files(&upstream, &[("plugins/a/skill.md", "a")], "latest");
+
+        // Offline, the commit is reported missing
+        let offline = MarketplaceResolver::new(cache_dir.clone()).with_fetch_policy(FetchPolicy::Offline);
+        let repo = offline.ensure_marketplace("test", &url).unwrap();
+        let result = offline.find_commit(&repo, "test", &latest);
+        assert!(matches!(result, Err(Error::CommitNotFound { .. })));
+
+        // Within the fetch TTL, a locked commit missing from the clone is still fetched
+        let ttl = FetchPolicy::Ttl(std::time::Duration::from_secs(3600));
+        let resolver = MarketplaceResolver::new(cache_dir).with_fetch_policy(ttl);
+        let repo = resolver.ensure_marketplace("test", &url).unwrap();
+        assert_eq!(resolver.find_commit(&repo, "test", &latest).unwrap().id().to_string(), latest);
+    }
+
+    #[test]
+    fn test_sparse_path() {
+        assert_eq!(sparse_path("./plugins/foo/"), Some(PathBuf::from("plugins/foo")));
+        assert_eq!(sparse_path("./"), Some(PathBuf::new()));
+        assert_eq!(sparse_path("plugins/../../etc"), None);
+        assert_eq!(sparse_path("/etc"), None);
+    }
+
+    #[test]
+    fn test_is_local_remote() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        assert!(is_local_remote(&temp_dir.path().to_string_lossy()));
+        assert!(is_local_remote("file:///srv/plugins"));
+        assert!(!is_local_remote("https://github.com/owner/repo.git"));
+        assert!(!is_local_remote("git@github.com:owner/repo.git"));
+    }
+
+    /// A `git daemon` serving a directory over `git://`, which unlike the
+    /// local transport supports shallow fetches. Killed on drop.
+    struct GitDaemon {
+        child: std::process::Child,
+        port: u16,
+    }
+
+    impl GitDaemon {
+        fn serve(root: &Path) -> Self {
+            let port = std::net::TcpListener::bind("127.0.0.1:0")
+                .unwrap()
+                .local_addr()
+                .unwrap()
+                .port();
+            // `git daemon` forks, so run the daemon binary itself to be able to kill it
+            let exec_path = std::process::Command::new("git")
+                .arg("--exec-path")
+                .output()
+                .expect("this test needs git");
+            let exec_path = String::from_utf8(exec_path.stdout).unwrap();
+            let child = std::process::Command::new(Path::new(exec_path.trim()).join("git-daemon"))
+                .arg("--export-all")
+                .arg("--reuseaddr")
+                .arg("--listen=127.0.0.1")
+                .arg(format!("--port={}", port))
+                .arg(format!("--base-path={}", root.display()))
+                .arg(root)
+                .stdout(std::process::Stdio::null())
+                .stderr(std::process::Stdio::null())
+                .spawn()
+                .expect("this test needs git-daemon");
+            let daemon = Self { child, port };
+
+            for _ in 0..100 {
+                if std::net::TcpStream::connect(("127.0.0.1", port)).is_ok() {
+                    return daemon;
+                }
+                std::thread::sleep(std::time::Duration::from_millis(50));
+            }
+            panic!("git daemon did not start");
+        }
+
+        fn url(&self, repo: &str) -> String {
+            format!("git://127.0.0.1:{}/{}", self.port, repo)
+        }
+    }
+
+    impl Drop for GitDaemon {
+        fn drop(&mut self) {
+            let _ = self.child.kill();
+            let _ = self.child.wait();
+        }
+    }
+
+    #[test]
+    fn test_shallow_clone_deepens_for_missing_commit() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let upstream_dir = temp_dir.path().join("upstream");
+        let upstream = setup_test_repo(&upstream_dir);
+        let first = upstream.head().unwrap().peel_to_commit().unwrap().id();
+        commit_and_tag(&upstream, "v1.0.0");
+        commit_and_tag(&upstream, "v2.0.0");
+
+        let daemon = GitDaemon::serve(temp_dir.path());
+        let url = daemon.url("upstream");
+        assert!(!is_local_remote(&url));
+
+        // A new clone only has the tips of branches and tags
+        let resolver = MarketplaceResolver::new(temp_dir.path().join("cache"));
+        let repo = resolver.ensure_marketplace("test", &url).unwrap();
+        assert!(repo.is_shallow());
+        assert!(repo.find_commit(first).is_err());
+
+        // Looking up an older commit fetches the full history
+        let found = resolver.find_commit(&repo, "test", &first.to_string()).unwrap();
+        assert_eq!(found.id(), first);
+        assert!(!repo.is_shallow());
+    }
+
+    #[test]
+    fn test_parse_tag_version() {
+        assert_eq!(parse_tag_version("v4.1.1"), Some(Version::new(4, 1, 1)));
+        assert_eq!(parse_tag_version("4.1.1"), Some(Version::new(4, 1, 1)));
+        assert_eq!(parse_tag_version("release-4"), None);
+        assert_eq!(parse_tag_version("v4.1"), None);
+    }
 }
@@ -1,5 +1,12 @@
+mod fetch;
+mod index;
 mod marketplace;
 mod plugin;
+mod session;
 
-pub use marketplace::{MarketplaceJson, MarketplacePlugin, MarketplaceResolver, PluginSource};
-pub use plugin::{PluginJson, ResolvedPlugin};
+pub use fetch::{FetchPolicy, FetchTarget};
+pub(crate) use fetch::{collect_results, map_parallel};
+pub use index::{IndexedMarketplace, IndexedPlugin, SearchIndex, SearchMatch, INDEX_FILENAME};
+pub use marketplace::{ExternalSource, MarketplaceJson, MarketplacePlugin, MarketplaceResolver, PluginSource};
+pub(crate) use marketplace::sparse_path;
+pub use plugin::{PluginDependency, PluginJson, ResolvedPlugin};
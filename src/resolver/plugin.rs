@@ -1,9 +1,15 @@
 use git2::Repository;
 use serde::Deserialize;
+use std::collections::BTreeMap;
 use std::path::{Path, PathBuf};
 
-use super::marketplace::{MarketplacePlugin, MarketplaceResolver, PluginSource};
-use crate::config::SourceType;
+use semver::Version;
+
+use super::fetch::{record_fetch_time, FetchPolicy};
+use super::marketplace::{
+    clone_repo, fetch_origin, version_matches, ExternalSource, MarketplacePlugin, MarketplaceResolver, PluginSource,
+};
+use crate::config::{PluginEntry, SourceType, GIT_MARKETPLACE};
 use crate::layout::PluginLayout;
 use crate::{Error, Result};
 
@@ -13,6 +19,53 @@ pub struct PluginJson {
     pub name: Option<String>,
     pub version: Option<String>,
     pub description: Option<String>,
+    /// Plugins this plugin needs, keyed by plugin name.
+    #[serde(default)]
+    pub dependencies: BTreeMap<String, PluginDependency>,
+}
+
+/// A dependency declared in plugin.json.
+///
+/// Written either as a version requirement (`"lsp": "^1.0"`) or as a table
+/// with an optional marketplace and a version requirement or tag. Without a
+/// marketplace, the dependency comes from the dependent plugin's marketplace.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(from = "RawDependency")]
+pub struct PluginDependency {
+    pub marketplace: Option<String>,
+    pub version: Option<String>,
+    pub tag: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum RawDependency {
+    Version(String),
+    Detailed {
+        marketplace: Option<String>,
+        version: Option<String>,
+        tag: Option<String>,
+    },
+}
+
+impl From<RawDependency> for PluginDependency {
+    fn from(raw: RawDependency) -> Self {
+        match raw {
+            RawDependency::Version(version) => Self {
+                version: Some(version),
+                ..Self::default()
+            },
+            RawDependency::Detailed {
+                marketplace,
+                version,
+                tag,
+            } => Self {
+                marketplace,
+                version,
+                tag,
+            },
+        }
+    }
 }
 
 /// Resolved plugin information.
@@ -24,6 +77,14 @@ pub struct ResolvedPlugin {
     pub marketplace_commit: String,
     pub plugin_commit: String,
     pub resolved_version: String,
+    /// Semver requirement the plugin was resolved against, if any.
+    pub version_req: Option<String>,
+    /// Tag the plugin commit was resolved from, if any.
+    pub tag: Option<String>,
+    /// Branch the plugin commit was resolved from, if any.
+    pub branch: Option<String>,
+    /// Dependencies declared in plugin.json at the resolved commit.
+    pub dependencies: BTreeMap<String, PluginDependency>,
     /// For local plugins: path within marketplace.
     /// For external plugins: URL of the plugin repo.
     pub source: String,
@@ -53,6 +114,10 @@ impl ResolvedPlugin {
             marketplace_commit: commit.clone(),
             plugin_commit: commit,
             resolved_version,
+            version_req: None,
+            tag: None,
+            branch: None,
+            dependencies: BTreeMap::new(),
             source,
             layout,
         }
@@ -80,6 +145,10 @@ impl ResolvedPlugin {
             marketplace_commit,
             plugin_commit,
             resolved_version,
+            version_req: None,
+            tag: None,
+            branch: None,
+            dependencies: BTreeMap::new(),
             source,
             layout,
         }
@@ -91,44 +160,99 @@ impl ResolvedPlugin {
         let json: PluginJson = serde_json::from_str(&content).ok()?;
         json.version
     }
+
+    /// Read the dependencies declared in plugin.json.
+    ///
+    /// A missing plugin.json declares none, but a malformed one is an error
+    /// so dependencies are never silently dropped.
+    fn read_dependencies(layout: &PluginLayout) -> Result<BTreeMap<String, PluginDependency>> {
+        let path = layout.plugin_json();
+        let content = match std::fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(BTreeMap::new()),
+            Err(e) => {
+                return Err(Error::FileRead {
+                    path: path.to_path_buf(),
+                    source: e,
+                });
+            }
+        };
+
+        #[derive(Deserialize)]
+        struct Dependencies {
+            #[serde(default)]
+            dependencies: BTreeMap<String, PluginDependency>,
+        }
+
+        serde_json::from_str::<Dependencies>(&content)
+            .map(|json| json.dependencies)
+            .map_err(|e| Error::JsonParse {
+                path: path.to_path_buf(),
+                source: e,
+            })
+    }
 }
 
 impl MarketplaceResolver {
     /// Resolve a plugin from a marketplace.
+    ///
+    /// The manifest entry's tag, commit, branch or version requirement selects
+    /// the commit of external plugins, falling back to the commit or ref the
+    /// marketplace pins. Local plugins follow the marketplace commit; a version
+    /// requirement is checked against their plugin.json version.
     pub fn resolve_plugin(
         &self,
         marketplace_name: &str,
         marketplace_commit: &str,
         plugin_name: &str,
         plugin_info: &MarketplacePlugin,
-        requested_tag: Option<&str>,
-        requested_commit: Option<&str>,
+        entry: &PluginEntry,
     ) -> Result<ResolvedPlugin> {
-        match &plugin_info.source {
+        let mut resolved = match &plugin_info.source {
             PluginSource::Local(path) => {
                 // Local plugin - lives within the marketplace repo
-                self.resolve_local_plugin(
+                entry.validate_local(plugin_name)?;
+                let resolved = self.resolve_local_plugin(
                     marketplace_name,
                     marketplace_commit,
                     plugin_name,
                     path,
-                )
+                )?;
+
+                if let Some(ref req) = entry.version {
+                    let satisfied = Version::parse(&resolved.resolved_version)
+                        .is_ok_and(|v| version_matches(req, &v, entry.prerelease));
+                    if !satisfied {
+                        return Err(Error::NoMatchingVersion {
+                            name: plugin_name.to_string(),
+                            requirement: req.to_string(),
+                        });
+                    }
+                }
+
+                resolved
             }
-            PluginSource::External { url, .. } => {
+            PluginSource::External(source) => {
                 // External plugin - separate git repository
-                self.resolve_external_plugin(
-                    marketplace_name,
-                    marketplace_commit,
-                    plugin_name,
-                    url,
-                    requested_tag,
-                    requested_commit,
-                )
+                self.resolve_external_plugin(marketplace_name, marketplace_commit, plugin_name, source, entry)?
             }
-        }
+            PluginSource::Unsupported(reason) => {
+                return Err(Error::MarketplaceJsonParse {
+                    name: marketplace_name.to_string(),
+                    reason: format!("plugin '{}' has an unsupported source: {}", plugin_name, reason),
+                });
+            }
+        };
+
+        resolved.version_req = entry.version.as_ref().map(|v| v.to_string());
+        resolved.dependencies = ResolvedPlugin::read_dependencies(&resolved.layout)?;
+        Ok(resolved)
     }
 
     /// Resolve a local plugin (path within marketplace).
+    ///
+    /// The plugin's directory is checked out at the marketplace commit. In a
+    /// directory marketplace, the plugin's commit is the hash of its directory.
     fn resolve_local_plugin(
         &self,
         marketplace_name: &str,
@@ -136,8 +260,27 @@ impl MarketplaceResolver {
         plugin_name: &str,
         path: &str,
     ) -> Result<ResolvedPlugin> {
-        let marketplace_path = self.marketplace_path(marketplace_name);
-        let plugin_path = marketplace_path.join(path);
+        if let Some(dir) = self.directory(marketplace_name) {
+            let plugin_path = dir.join(path);
+            let plugin_commit = self.resolve_directory(marketplace_name, &plugin_path)?;
+            let mut resolved = ResolvedPlugin::from_local(
+                plugin_name.to_string(),
+                marketplace_name.to_string(),
+                marketplace_commit.to_string(),
+                path.to_string(),
+                PluginLayout::new(&plugin_path),
+            );
+            resolved.plugin_commit = plugin_commit;
+            return Ok(resolved);
+        }
+
+        let repo = Repository::open(self.marketplace_path(marketplace_name)).map_err(|e| Error::MarketplaceClone {
+            name: marketplace_name.to_string(),
+            source: e,
+        })?;
+        let root = self.checkout_sparse(&repo, marketplace_name, marketplace_commit, &[path])?;
+
+        let plugin_path = root.join(path);
         let layout = PluginLayout::new(&plugin_path);
 
         Ok(ResolvedPlugin::from_local(
@@ -149,32 +292,72 @@ impl MarketplaceResolver {
         ))
     }
 
-    /// Resolve an external plugin (separate git repository).
+    /// Resolve a plugin straight from a git repository, without a marketplace.
+    ///
+    /// The repository is resolved and checked out like an external plugin's
+    /// and cached under [`GIT_MARKETPLACE`]. With a `subdir`, the plugin lives
+    /// in that directory of the repository.
+    pub fn resolve_git_plugin(
+        &self,
+        plugin_name: &str,
+        url: &str,
+        subdir: Option<&str>,
+        entry: &PluginEntry,
+    ) -> Result<ResolvedPlugin> {
+        let source = ExternalSource {
+            path: subdir.map(str::to_string),
+            ..ExternalSource::new(url)
+        };
+        let mut resolved = self.resolve_external_plugin(GIT_MARKETPLACE, "", plugin_name, &source, entry)?;
+        resolved.marketplace_commit = resolved.plugin_commit.clone();
+        resolved.dependencies = ResolvedPlugin::read_dependencies(&resolved.layout)?;
+        resolved.version_req = entry.version.as_ref().map(|v| v.to_string());
+        Ok(resolved)
+    }
+
+    /// Resolve an external plugin (separate git repository), optionally
+    /// living in a subdirectory of it.
+    ///
+    /// A pin in the manifest entry wins over the commit or ref the source
+    /// pins. A ref is looked up as a tag first, then as a branch.
     fn resolve_external_plugin(
         &self,
         marketplace_name: &str,
         marketplace_commit: &str,
         plugin_name: &str,
-        url: &str,
-        requested_tag: Option<&str>,
-        requested_commit: Option<&str>,
+        source: &ExternalSource,
+        entry: &PluginEntry,
     ) -> Result<ResolvedPlugin> {
+        let subdir = source.subdir(marketplace_name, plugin_name)?;
+
         // Clone/fetch the external plugin repo
         let plugin_cache_path = self.plugin_repo_path(marketplace_name, plugin_name);
 
-        let repo = if plugin_cache_path.exists() {
-            self.fetch_plugin_repo(plugin_name, &plugin_cache_path)?
-        } else {
-            self.clone_plugin_repo(plugin_name, url, &plugin_cache_path)?
-        };
+        let repo = self.ensure_plugin_repo(plugin_name, &source.url, &plugin_cache_path)?;
 
         // Resolve the version
-        let plugin_commit = if let Some(commit) = requested_commit {
-            commit.to_string()
-        } else if let Some(tag) = requested_tag {
-            self.resolve_tag(&repo, plugin_name, tag)?
+        let mut branch = entry.branch.clone();
+        let (tag, plugin_commit) = if let Some(ref commit) = entry.commit {
+            (None, commit.clone())
+        } else if let Some(ref tag) = entry.tag {
+            (Some(tag.clone()), self.resolve_tag(&repo, plugin_name, tag)?)
+        } else if let Some(ref branch) = entry.branch {
+            (None, self.resolve_branch(&repo, plugin_name, branch)?)
+        } else if let Some(ref req) = entry.version {
+            let (tag, commit) = self.resolve_version_req(&repo, plugin_name, req, entry.prerelease)?;
+            (Some(tag), commit)
+        } else if let Some(ref sha) = source.sha {
+            (None, sha.clone())
+        } else if let Some(ref git_ref) = source.git_ref {
+            match self.resolve_tag(&repo, plugin_name, git_ref) {
+                Ok(commit) => (Some(git_ref.clone()), commit),
+                Err(_) => {
+                    branch = Some(git_ref.clone());
+                    (None, self.resolve_branch(&repo, plugin_name, git_ref)?)
+                }
+            }
         } else {
-            self.resolve_head(&repo)?
+            (None, self.resolve_head(&repo)?)
         };
 
         // Checkout the resolved commit
@@ -182,20 +365,100 @@ impl MarketplaceResolver {
 
         // Read version from plugin.json using PluginLayout
         let workdir = repo.workdir().unwrap_or(&plugin_cache_path);
-        let layout = PluginLayout::new(workdir);
+        let layout = PluginLayout::new(workdir.join(subdir));
 
-        Ok(ResolvedPlugin::from_external(
+        let mut resolved = ResolvedPlugin::from_external(
             plugin_name.to_string(),
             marketplace_name.to_string(),
             marketplace_commit.to_string(),
             plugin_commit,
-            url.to_string(),
+            source.url.clone(),
             layout,
-        ))
+        );
+        resolved.tag = tag;
+        resolved.branch = branch;
+        Ok(resolved)
+    }
+
+    /// Check out an external plugin repository at a locked commit.
+    ///
+    /// The repository is cloned if missing and only fetched when the commit
+    /// is not available locally. Returns the repository path.
+    pub fn checkout_plugin_repo(
+        &self,
+        marketplace_name: &str,
+        plugin_name: &str,
+        url: &str,
+        commit: &str,
+    ) -> Result<PathBuf> {
+        self.fetch_plugin_repo_for(marketplace_name, plugin_name, url, Some(commit))?;
+
+        let path = self.plugin_repo_path(marketplace_name, plugin_name);
+        let repo = Repository::open(&path).map_err(|e| Error::MarketplaceClone {
+            name: plugin_name.to_string(),
+            source: e,
+        })?;
+        self.checkout_commit(&repo, plugin_name, commit)?;
+        Ok(path)
+    }
+
+    /// Make an external plugin repository available in the cache.
+    ///
+    /// With a commit, an existing clone is only fetched when the commit is
+    /// missing; without one, it is fetched once per resolver.
+    pub(crate) fn fetch_plugin_repo_for(
+        &self,
+        marketplace_name: &str,
+        plugin_name: &str,
+        url: &str,
+        commit: Option<&str>,
+    ) -> Result<()> {
+        let path = self.plugin_repo_path(marketplace_name, plugin_name);
+
+        if let Some(commit) = commit
+            && path.exists()
+        {
+            let repo = Repository::open(&path).map_err(|e| Error::MarketplaceClone {
+                name: plugin_name.to_string(),
+                source: e,
+            })?;
+            if git2::Oid::from_str(commit).is_ok_and(|oid| repo.find_commit(oid).is_ok()) {
+                return Ok(());
+            }
+        }
+
+        self.ensure_plugin_repo(plugin_name, url, &path).map(drop)
+    }
+
+    /// Clone or fetch an external plugin repository.
+    ///
+    /// A repository that does not need fetching is only opened. Offline, a
+    /// repository that is not cached is an error.
+    fn ensure_plugin_repo(&self, name: &str, url: &str, path: &Path) -> Result<Repository> {
+        let repo = if path.exists() && !self.needs_fetch(path) {
+            Repository::open(path).map_err(|e| Error::MarketplaceClone {
+                name: name.to_string(),
+                source: e,
+            })?
+        } else if path.exists() {
+            let repo = self.fetch_plugin_repo(name, path)?;
+            self.mark_fetched(path);
+            record_fetch_time(path);
+            repo
+        } else if self.fetch_policy() == FetchPolicy::Offline {
+            return Err(Error::NotCachedOffline(format!("plugin repository '{}'", name)));
+        } else {
+            let repo = self.clone_plugin_repo(name, url, path)?;
+            self.mark_fetched(path);
+            record_fetch_time(path);
+            repo
+        };
+
+        Ok(repo)
     }
 
     /// Get the cache path for an external plugin repo.
-    fn plugin_repo_path(&self, marketplace: &str, plugin: &str) -> PathBuf {
+    pub(crate) fn plugin_repo_path(&self, marketplace: &str, plugin: &str) -> PathBuf {
         self.cache_dir
             .join("plugin-repos")
             .join(marketplace)
@@ -204,29 +467,7 @@ impl MarketplaceResolver {
 
     /// Clone an external plugin repository.
     fn clone_plugin_repo(&self, name: &str, url: &str, path: &Path) -> Result<Repository> {
-        std::fs::create_dir_all(path.parent().unwrap_or(Path::new(".")))
-            .map_err(Error::CacheCreate)?;
-
-        let mut callbacks = git2::RemoteCallbacks::new();
-        callbacks.credentials(|_url, username_from_url, allowed_types| {
-            if allowed_types.contains(git2::CredentialType::SSH_KEY) {
-                if let Some(username) = username_from_url {
-                    return git2::Cred::ssh_key_from_agent(username);
-                }
-            }
-            git2::Cred::default()
-        });
-
-        let mut fo = git2::FetchOptions::new();
-        fo.remote_callbacks(callbacks);
-
-        let mut builder = git2::build::RepoBuilder::new();
-        builder.fetch_options(fo);
-
-        builder.clone(url, path).map_err(|e| Error::MarketplaceClone {
-            name: name.to_string(),
-            source: e,
-        })
+        clone_repo(name, url, path)
     }
 
     /// Fetch updates for an external plugin repository.
@@ -236,37 +477,7 @@ impl MarketplaceResolver {
             source: e,
         })?;
 
-        {
-            let mut remote = repo.find_remote("origin").map_err(|e| Error::MarketplaceFetch {
-                name: name.to_string(),
-                source: e,
-            })?;
-
-            let mut callbacks = git2::RemoteCallbacks::new();
-            callbacks.credentials(|_url, username_from_url, allowed_types| {
-                if allowed_types.contains(git2::CredentialType::SSH_KEY) {
-                    if let Some(username) = username_from_url {
-                        return git2::Cred::ssh_key_from_agent(username);
-                    }
-                }
-                git2::Cred::default()
-            });
-
-            let mut fo = git2::FetchOptions::new();
-            fo.remote_callbacks(callbacks);
-
-            remote
-                .fetch(
-                    &["refs/heads/*:refs/heads/*", "refs/tags/*:refs/tags/*"],
-                    Some(&mut fo),
-                    None,
-                )
-                .map_err(|e| Error::MarketplaceFetch {
-                    name: name.to_string(),
-                    source: e,
-                })?;
-        }
-
+        fetch_origin(&repo, name, false)?;
         Ok(repo)
     }
 }
@@ -274,6 +485,7 @@ impl MarketplaceResolver {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::test_util::commit_files;
     use std::fs;
 
     fn setup_marketplace_with_local_plugin(dir: &Path, include_version: bool) -> Repository {
@@ -288,35 +500,19 @@ mod tests {
                 }
             }
         }"#;
-        fs::write(dir.join("marketplace.json"), marketplace_json).unwrap();
+        let mut files = vec![("marketplace.json", marketplace_json)];
 
         // Create local plugin with plugin.json in .claude-plugin/
-        let plugin_dir = dir.join("plugins/local-plugin");
-        let config_dir = plugin_dir.join(".claude-plugin");
-        fs::create_dir_all(&config_dir).unwrap();
-
+        fs::create_dir_all(dir.join("plugins/local-plugin/.claude-plugin")).unwrap();
+        let plugin_json = r#"{
+            "name": "local-plugin",
+            "version": "1.2.3",
+            "description": "Test local plugin"
+        }"#;
         if include_version {
-            let plugin_json = r#"{
-                "name": "local-plugin",
-                "version": "1.2.3",
-                "description": "Test local plugin"
-            }"#;
-            fs::write(config_dir.join("plugin.json"), plugin_json).unwrap();
-        }
-
-        // Commit everything
-        {
-            let mut index = repo.index().unwrap();
-            index
-                .add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)
-                .unwrap();
-            index.write().unwrap();
-            let tree_id = index.write_tree().unwrap();
-            let tree = repo.find_tree(tree_id).unwrap();
-            let sig = git2::Signature::now("Test", "test@test.com").unwrap();
-            repo.commit(Some("HEAD"), &sig, &sig, "Initial commit", &tree, &[])
-                .unwrap();
+            files.push(("plugins/local-plugin/.claude-plugin/plugin.json", plugin_json));
         }
+        commit_files(&repo, &files, "Initial commit");
 
         repo
     }
@@ -339,7 +535,7 @@ mod tests {
         };
 
         let resolved = resolver
-            .resolve_plugin("test", &commit, "local-plugin", &plugin_info, None, None)
+            .resolve_plugin("test", &commit, "local-plugin", &plugin_info, &PluginEntry::default())
             .unwrap();
 
         assert_eq!(resolved.name, "local-plugin");
@@ -370,7 +566,7 @@ mod tests {
         };
 
         let resolved = resolver
-            .resolve_plugin("test", &commit, "local-plugin", &plugin_info, None, None)
+            .resolve_plugin("test", &commit, "local-plugin", &plugin_info, &PluginEntry::default())
             .unwrap();
 
         // Version should fallback to first 7 chars of commit SHA
@@ -394,29 +590,16 @@ mod tests {
                 }
             }
         }"#;
-        fs::write(marketplace_dir.join("marketplace.json"), marketplace_json).unwrap();
-
-        let plugin_dir = marketplace_dir.join("plugins/local-plugin");
-        let config_dir = plugin_dir.join(".claude-plugin");
-        fs::create_dir_all(&config_dir).unwrap();
-
         // plugin.json exists but has no version field
         let plugin_json = r#"{"name": "local-plugin"}"#;
-        fs::write(config_dir.join("plugin.json"), plugin_json).unwrap();
-
-        // Commit everything
-        {
-            let mut index = repo.index().unwrap();
-            index
-                .add_all(["*"].iter(), git2::IndexAddOption::DEFAULT, None)
-                .unwrap();
-            index.write().unwrap();
-            let tree_id = index.write_tree().unwrap();
-            let tree = repo.find_tree(tree_id).unwrap();
-            let sig = git2::Signature::now("Test", "test@test.com").unwrap();
-            repo.commit(Some("HEAD"), &sig, &sig, "Initial commit", &tree, &[])
-                .unwrap();
-        }
+        commit_files(
+            &repo,
+            &[
+                ("marketplace.json", marketplace_json),
+                ("plugins/local-plugin/.claude-plugin/plugin.json", plugin_json),
+            ],
+            "Initial commit",
+        );
 
         let commit = repo.head().unwrap().peel_to_commit().unwrap().id().to_string();
 
@@ -428,10 +611,213 @@ mod tests {
         };
 
         let resolved = resolver
-            .resolve_plugin("test", &commit, "local-plugin", &plugin_info, None, None)
+            .resolve_plugin("test", &commit, "local-plugin", &plugin_info, &PluginEntry::default())
             .unwrap();
 
         // Version should fallback to first 7 chars of commit SHA
         assert_eq!(resolved.resolved_version, &commit[..7]);
     }
+
+    #[test]
+    fn test_local_plugin_version_requirement() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let marketplace_dir = temp_dir.path().join("marketplaces/test");
+        fs::create_dir_all(&marketplace_dir).unwrap();
+
+        let repo = setup_marketplace_with_local_plugin(&marketplace_dir, true);
+        let commit = repo.head().unwrap().peel_to_commit().unwrap().id().to_string();
+
+        let resolver = MarketplaceResolver::new(temp_dir.path().to_path_buf());
+        let plugin_info = super::super::marketplace::MarketplacePlugin {
+            name: "local-plugin".to_string(),
+            source: PluginSource::Local("plugins/local-plugin".to_string()),
+            description: None,
+        };
+
+        let entry = PluginEntry {
+            version: Some(semver::VersionReq::parse("^1.2").unwrap()),
+            ..PluginEntry::default()
+        };
+        let resolved = resolver
+            .resolve_plugin("test", &commit, "local-plugin", &plugin_info, &entry)
+            .unwrap();
+        assert_eq!(resolved.version_req, Some("^1.2".to_string()));
+
+        let entry = PluginEntry {
+            version: Some(semver::VersionReq::parse("^2").unwrap()),
+            ..PluginEntry::default()
+        };
+        let result = resolver.resolve_plugin("test", &commit, "local-plugin", &plugin_info, &entry);
+        assert!(matches!(result, Err(Error::NoMatchingVersion { .. })));
+
+        // A local plugin follows the marketplace commit, not a branch
+        let entry = PluginEntry {
+            marketplace: "test".to_string(),
+            branch: Some("develop".to_string()),
+            ..PluginEntry::default()
+        };
+        let result = resolver.resolve_plugin("test", &commit, "local-plugin", &plugin_info, &entry);
+        assert!(matches!(result, Err(Error::ManifestParse(ref msg)) if msg.contains("cannot follow a branch")));
+    }
+
+    /// Commit a plugin.json with the given version to an external plugin repo.
+    fn commit_plugin_version(repo: &Repository, version: &str) -> String {
+        let plugin_json = format!(r#"{{"name": "external-plugin", "version": "{}"}}"#, version);
+        commit_files(repo, &[(".claude-plugin/plugin.json", plugin_json)], version)
+    }
+
+    #[test]
+    fn test_external_plugin_follows_remote_after_fetch() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let upstream_dir = temp_dir.path().join("upstream");
+        let upstream = Repository::init(&upstream_dir).unwrap();
+        let first = commit_plugin_version(&upstream, "1.0.0");
+
+        let resolver = MarketplaceResolver::new(temp_dir.path().join("cache"));
+        let plugin_info = super::super::marketplace::MarketplacePlugin {
+            name: "external-plugin".to_string(),
+            source: PluginSource::External(ExternalSource::new(upstream_dir.to_string_lossy())),
+            description: None,
+        };
+
+        let resolved = resolver
+            .resolve_plugin("test", "abc123", "external-plugin", &plugin_info, &PluginEntry::default())
+            .unwrap();
+        assert_eq!(resolved.plugin_commit, first);
+        assert_eq!(resolved.resolved_version, "1.0.0");
+
+        // The upstream gains a commit before the next run
+        let second = commit_plugin_version(&upstream, "1.1.0");
+        let resolver = MarketplaceResolver::new(temp_dir.path().join("cache"));
+
+        let resolved = resolver
+            .resolve_plugin("test", "abc123", "external-plugin", &plugin_info, &PluginEntry::default())
+            .unwrap();
+        assert_eq!(resolved.plugin_commit, second);
+        assert_eq!(resolved.resolved_version, "1.1.0");
+    }
+
+    #[test]
+    fn test_external_plugin_source_pins() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let upstream_dir = temp_dir.path().join("upstream");
+        let upstream = Repository::init(&upstream_dir).unwrap();
+        let first = commit_plugin_version(&upstream, "1.0.0");
+        let first_obj = upstream.revparse_single(&first).unwrap();
+        upstream.tag_lightweight("v1.0.0", &first_obj, false).unwrap();
+        let second = commit_files(
+            &upstream,
+            &[
+                (".claude-plugin/plugin.json", r#"{"name": "external-plugin", "version": "1.1.0"}"#),
+                ("nested/.claude-plugin/plugin.json", r#"{"name": "external-plugin", "version": "3.0.0"}"#),
+            ],
+            "1.1.0",
+        );
+        let default_branch = upstream.head().unwrap().shorthand().unwrap().to_string();
+
+        let resolver = MarketplaceResolver::new(temp_dir.path().join("cache"));
+        let resolve = |source: ExternalSource, entry: &PluginEntry| {
+            let plugin_info = super::super::marketplace::MarketplacePlugin {
+                name: "external-plugin".to_string(),
+                source: PluginSource::External(source),
+                description: None,
+            };
+            resolver.resolve_plugin("test", "abc123", "external-plugin", &plugin_info, entry)
+        };
+        let url = upstream_dir.to_string_lossy();
+
+        // A ref is a tag or a branch
+        let source = ExternalSource {
+            git_ref: Some("v1.0.0".to_string()),
+            ..ExternalSource::new(url.clone())
+        };
+        let resolved = resolve(source, &PluginEntry::default()).unwrap();
+        assert_eq!(resolved.plugin_commit, first);
+        assert_eq!(resolved.tag.as_deref(), Some("v1.0.0"));
+        assert_eq!(resolved.resolved_version, "1.0.0");
+
+        let source = ExternalSource {
+            git_ref: Some(default_branch.clone()),
+            ..ExternalSource::new(url.clone())
+        };
+        let resolved = resolve(source, &PluginEntry::default()).unwrap();
+        assert_eq!(resolved.plugin_commit, second);
+        assert_eq!(resolved.branch, Some(default_branch));
+
+        // A commit pinned by the marketplace, unless the manifest pins another
+        let source = ExternalSource {
+            sha: Some(first.clone()),
+            ..ExternalSource::new(url.clone())
+        };
+        assert_eq!(resolve(source.clone(), &PluginEntry::default()).unwrap().plugin_commit, first);
+        let entry = PluginEntry {
+            commit: Some(second.clone()),
+            ..PluginEntry::default()
+        };
+        assert_eq!(resolve(source, &entry).unwrap().plugin_commit, second);
+
+        // The plugin lives in a subdirectory of the repository
+        let source = ExternalSource {
+            path: Some("./nested".to_string()),
+            ..ExternalSource::new(url.clone())
+        };
+        let resolved = resolve(source, &PluginEntry::default()).unwrap();
+        assert_eq!(resolved.resolved_version, "3.0.0");
+        assert_eq!(resolved.layout.base_path(), resolver.plugin_repo_path("test", "external-plugin").join("nested"));
+
+        let source = ExternalSource {
+            path: Some("../elsewhere".to_string()),
+            ..ExternalSource::new(url.clone())
+        };
+        let result = resolve(source, &PluginEntry::default());
+        assert!(matches!(result, Err(Error::MarketplaceJsonParse { ref reason, .. }) if reason.contains("outside")));
+    }
+
+    #[test]
+    fn test_unsupported_plugin_source() {
+        let temp_dir = tempfile::tempdir().unwrap();
+        let resolver = MarketplaceResolver::new(temp_dir.path().to_path_buf());
+        let plugin_info: super::super::marketplace::MarketplacePlugin =
+            serde_json::from_str(r#"{"name": "npm-plugin", "source": {"source": "npm", "package": "x"}}"#).unwrap();
+
+        let err = resolver
+            .resolve_plugin("test", "abc123", "npm-plugin", &plugin_info, &PluginEntry::default())
+            .unwrap_err();
+        assert!(matches!(err, Error::MarketplaceJsonParse { ref name, .. } if name == "test"));
+        assert!(err.to_string().contains("plugin 'npm-plugin' has an unsupported source"), "{}", err);
+        assert!(err.to_string().contains("npm"), "{}", err);
+    }
+
+    #[test]
+    fn test_parse_plugin_json_dependencies() {
+        let json: PluginJson = serde_json::from_str(
+            r#"{
+                "name": "skills",
+                "dependencies": {
+                    "typescript-lsp": "^1.0",
+                    "helper": { "marketplace": "official", "tag": "v2.0" }
+                }
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            json.dependencies["typescript-lsp"],
+            PluginDependency {
+                version: Some("^1.0".to_string()),
+                ..PluginDependency::default()
+            }
+        );
+        assert_eq!(
+            json.dependencies["helper"],
+            PluginDependency {
+                marketplace: Some("official".to_string()),
+                version: None,
+                tag: Some("v2.0".to_string()),
+            }
+        );
+
+        let json: PluginJson = serde_json::from_str(r#"{"name": "skills"}"#).unwrap();
+        assert!(json.dependencies.is_empty());
+    }
 }
@@ -0,0 +1,115 @@
+use std::collections::{HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use super::marketplace::MarketplaceJson;
+
+/// What a resolver has already done during one command run.
+///
+/// Remembers fetched repositories, the commit each working tree was checked
+/// out at, parsed marketplace.json files and hashed directories, so repeated
+/// lookups for plugins of the same marketplace touch the network and the
+/// disk only once.
+///
+/// Opened `Repository` handles are not kept. A `git2::Repository` is `Send`
+/// but not `Sync`, so sharing one between the threads of
+/// [`map_parallel`](super::fetch::map_parallel) would mean a mutex around
+/// every git operation on it, serializing the work that runs in parallel.
+/// Opening a repository only reads a few files under `.git`, which is cheap
+/// next to the fetches and checkouts this session already saves.
+#[derive(Default)]
+pub(crate) struct Session {
+    /// Repositories cloned or fetched in this session.
+    fetched: Mutex<HashSet<PathBuf>>,
+    /// What each working tree was last checked out at in this session.
+    checked_out: Mutex<HashMap<PathBuf, Checkout>>,
+    /// Parsed marketplace.json files by marketplace name and commit.
+    marketplace_json: Mutex<HashMap<(String, String), Arc<MarketplaceJson>>>,
+    /// Source hashes of directory marketplaces and their plugins.
+    source_hashes: Mutex<HashMap<PathBuf, String>>,
+}
+
+/// The commit a working tree is checked out at.
+struct Checkout {
+    commit: String,
+    /// Paths checked out at the commit, or `None` for the whole tree.
+    paths: Option<HashSet<PathBuf>>,
+}
+
+/// Lock a mutex, ignoring poisoning; the cached data stays valid if a thread panics.
+fn lock<T>(mutex: &Mutex<T>) -> MutexGuard<'_, T> {
+    mutex.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+impl Session {
+    pub(crate) fn is_fetched(&self, path: &Path) -> bool {
+        lock(&self.fetched).contains(path)
+    }
+
+    pub(crate) fn mark_fetched(&self, path: &Path) {
+        lock(&self.fetched).insert(path.to_path_buf());
+    }
+
+    /// Whether the whole working tree is checked out at a commit.
+    pub(crate) fn is_checked_out(&self, workdir: &Path, commit: &str) -> bool {
+        lock(&self.checked_out)
+            .get(workdir)
+            .is_some_and(|c| c.commit == commit && c.paths.is_none())
+    }
+
+    pub(crate) fn mark_checked_out(&self, workdir: &Path, commit: &str) {
+        let checkout = Checkout {
+            commit: commit.to_string(),
+            paths: None,
+        };
+        lock(&self.checked_out).insert(workdir.to_path_buf(), checkout);
+    }
+
+    /// The paths that are not yet checked out at a commit.
+    pub(crate) fn missing_paths(&self, workdir: &Path, commit: &str, paths: &[PathBuf]) -> Vec<PathBuf> {
+        match lock(&self.checked_out).get(workdir) {
+            Some(c) if c.commit == commit => match &c.paths {
+                None => Vec::new(),
+                Some(done) => paths.iter().filter(|p| !done.contains(*p)).cloned().collect(),
+            },
+            _ => paths.to_vec(),
+        }
+    }
+
+    /// Remember that paths were checked out at a commit. Paths checked out
+    /// earlier at a different commit are forgotten.
+    pub(crate) fn mark_paths_checked_out(&self, workdir: &Path, commit: &str, paths: &[PathBuf]) {
+        let mut checked_out = lock(&self.checked_out);
+        let checkout = checked_out.entry(workdir.to_path_buf()).or_insert_with(|| Checkout {
+            commit: commit.to_string(),
+            paths: Some(HashSet::new()),
+        });
+        if checkout.commit != commit {
+            *checkout = Checkout {
+                commit: commit.to_string(),
+                paths: Some(HashSet::new()),
+            };
+        }
+        if let Some(done) = &mut checkout.paths {
+            done.extend(paths.iter().cloned());
+        }
+    }
+
+    pub(crate) fn marketplace_json(&self, marketplace: &str, commit: &str) -> Option<Arc<MarketplaceJson>> {
+        lock(&self.marketplace_json)
+            .get(&(marketplace.to_string(), commit.to_string()))
+            .cloned()
+    }
+
+    pub(crate) fn insert_marketplace_json(&self, marketplace: &str, commit: &str, json: Arc<MarketplaceJson>) {
+        lock(&self.marketplace_json).insert((marketplace.to_string(), commit.to_string()), json);
+    }
+
+    pub(crate) fn source_hash(&self, dir: &Path) -> Option<String> {
+        lock(&self.source_hashes).get(dir).cloned()
+    }
+
+    pub(crate) fn insert_source_hash(&self, dir: &Path, hash: &str) {
+        lock(&self.source_hashes).insert(dir.to_path_buf(), hash.to_string());
+    }
+}
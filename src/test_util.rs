@@ -0,0 +1,61 @@
+//! Git repositories for tests.
+
+use std::fs;
+use std::path::Path;
+
+use git2::{Repository, Signature};
+
+/// Write files below `dir`, creating their directories.
+pub(crate) fn write_files<P: AsRef<Path>, C: AsRef<[u8]>>(dir: &Path, files: &[(P, C)]) {
+    for (path, content) in files {
+        let path = dir.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, content).unwrap();
+    }
+}
+
+/// Write files into a repository's working tree and commit them on top of
+/// `HEAD`, along with any tracked file that was removed. Returns the
+/// commit hash.
+pub(crate) fn commit_files<P: AsRef<Path>, C: AsRef<[u8]>>(
+    repo: &Repository,
+    files: &[(P, C)],
+    message: &str,
+) -> String {
+    write_files(repo.workdir().unwrap(), files);
+
+    let mut index = repo.index().unwrap();
+    for (path, _) in files {
+        index.add_path(path.as_ref()).unwrap();
+    }
+    index.update_all(["*"].iter(), None).unwrap();
+    index.write().unwrap();
+    let tree = repo.find_tree(index.write_tree().unwrap()).unwrap();
+    let sig = Signature::now("Test", "test@test.com").unwrap();
+    let parent = repo.head().ok().map(|h| h.peel_to_commit().unwrap());
+    let parents: Vec<_> = parent.iter().collect();
+    repo.commit(Some("HEAD"), &sig, &sig, message, &tree, &parents)
+        .unwrap()
+        .to_string()
+}
+
+/// The files of a marketplace whose plugins live in it, under
+/// `plugins/<name>`. Each plugin is `(name, version, dependencies)`, the
+/// dependencies being the inside of plugin.json's `dependencies` object.
+pub(crate) fn marketplace_files(plugins: &[(&str, &str, &str)]) -> Vec<(String, String)> {
+    let entries: Vec<String> = plugins
+        .iter()
+        .map(|(name, _, _)| format!(r#"{{"name": "{0}", "source": "./plugins/{0}"}}"#, name))
+        .collect();
+    let mut files = vec![(
+        ".claude-plugin/marketplace.json".to_string(),
+        format!(r#"{{"plugins": [{}]}}"#, entries.join(",")),
+    )];
+    for (name, version, dependencies) in plugins {
+        files.push((
+            format!("plugins/{}/.claude-plugin/plugin.json", name),
+            format!(r#"{{"version": "{}", "dependencies": {{{}}}}}"#, version, dependencies),
+        ));
+    }
+    files
+}